@@ -1,105 +1,84 @@
-use intcode::{run_program, ProgramState};
-use read_input;
-
-#[derive(Copy, Clone, PartialEq)]
-enum TileType {
-    Empty,
-    Wall,
-    Block,
-    HorizontalPaddle,
-    Ball,
-}
+use std::env;
 
-impl TileType {
-    fn from_number(num: usize) -> Self {
-        match num {
-            0 => TileType::Empty,
-            1 => TileType::Wall,
-            2 => TileType::Block,
-            3 => TileType::HorizontalPaddle,
-            4 => TileType::Ball,
-            _ => panic!("invalid number {}", num),
-        }
-    }
-}
+use intcode::peripherals::Canvas;
+use intcode::{
+    get_base_program, run_device, Bus, Family, Peripheral, ProgramState, TranscriptMode,
+};
+
+const BLOCK: i64 = 2;
+const PADDLE: i64 = 3;
+const BALL: i64 = 4;
+
+// the score is drawn just off the screen
+const SCORE: (i64, i64) = (-1, 0);
 
 fn part_one(base_program: &Vec<i64>) {
     let mut state = ProgramState::new(base_program, vec![]);
+    let mut screen = Canvas::points();
+    run_device(&mut state, &mut Bus::new(3).attach(&mut screen));
 
-    let mut output_counter = 0;
+    println!("{}", screen.count(BLOCK));
+}
 
-    let mut tile = (0, 0, TileType::Empty);
-    let mut tiles = Vec::new();
+/// Tilts the joystick towards the ball
+#[derive(Default)]
+struct FollowBall {
+    paddle: i64,
+    ball: i64,
+}
 
-    run_program(&mut state, false, |_, output| {
-        output_counter += 1;
-        if output_counter == 1 {
-            tile.0 = output;
-        } else if output_counter == 2 {
-            tile.1 = output;
+impl Peripheral for FollowBall {
+    fn accepts(&self, frame: &[i64]) -> bool {
+        (frame[0], frame[1]) != SCORE && (frame[2] == PADDLE || frame[2] == BALL)
+    }
+
+    fn receive(&mut self, frame: &[i64]) {
+        if frame[2] == PADDLE {
+            self.paddle = frame[0];
         } else {
-            tile.2 = TileType::from_number(output as usize);
-            output_counter = 0;
-            tiles.push(tile);
+            self.ball = frame[0];
         }
-    });
-
-    println!(
-        "{}",
-        tiles
-            .iter()
-            .filter(|tile| tile.2 == TileType::Block)
-            .count()
-    );
+    }
+
+    fn send(&mut self) -> Option<i64> {
+        Some((self.ball - self.paddle).signum())
+    }
 }
 
-fn part_two(base_program: &Vec<i64>) {
+/// Follows the ball, saving the moves with `record <path>` or playing back saved ones with
+/// `replay <path>`
+fn part_two(base_program: &Vec<i64>, joystick: &TranscriptMode) {
     let mut state = ProgramState::new(base_program, vec![]);
     // play for free
-    state.program[0] = 2;
-
-    let mut coords = (0, 0);
-    let mut output_counter = 0;
-    let mut horizontal_paddle = (0, 0);
-    let mut ball = (0, 0);
-    let mut score = 0;
-    run_program(&mut state, false, |state, output| {
-        output_counter += 1;
-        if output_counter == 1 {
-            coords.0 = output;
-        } else if output_counter == 2 {
-            coords.1 = output;
-        } else {
-            if coords == (-1, 0) {
-                score = output;
-            } else if output == 3 {
-                horizontal_paddle = coords;
-            } else if output == 4 {
-                ball = coords;
-            }
-            output_counter = 0;
-        }
-
-        if horizontal_paddle.0 > ball.0 {
-            state.inputs = vec![-1];
-        } else if horizontal_paddle.0 < ball.0 {
-            state.inputs = vec![1];
-        } else {
-            state.inputs = vec![0];
-        }
+    state.write(0, 2);
+    let replaying = joystick.start(&mut state).unwrap();
+
+    let mut screen = Canvas::points();
+    let mut follow_ball = FollowBall::default();
+    let mut bus = Bus::new(3).attach(&mut screen);
+    // the recorded moves are already queued
+    if replaying.is_none() {
+        bus = bus.attach(&mut follow_ball);
+    }
+    run_device(&mut state, &mut bus);
 
-        false
-    });
+    println!("{}", screen.get(SCORE));
 
-    println!("{}", score);
+    if let Some(input) = joystick.finish(&mut state, replaying.as_ref()).unwrap() {
+        eprintln!("warning: the replay went differently from input {}", input);
+    }
 }
 
 fn main() {
     let text = read_input::read_text("13/input.txt").unwrap();
 
-    let base_program: Vec<i64> = text.split(",").map(|n| n.parse().expect("nope")).collect();
+    let base_program = get_base_program(&text);
+    if let Some(warning) = Family::Arcade.check(&base_program) {
+        eprintln!("warning: {}", warning);
+    }
 
     part_one(&base_program);
 
-    part_two(&base_program);
+    let joystick = TranscriptMode::from_args(env::args().skip(1));
+    part_two(&base_program, &joystick);
 }
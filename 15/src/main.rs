@@ -3,8 +3,8 @@ use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter, Result};
 
 use crate::Direction::Down;
-use intcode::{run_program, ProgramState};
-use read_input;
+use intcode::peripherals::Motor;
+use intcode::{get_base_program, run_device, Bus, ProgramState};
 use std::hash::Hash;
 use std::time::Duration;
 
@@ -52,7 +52,7 @@ struct Work {
     prev_coord: Option<(i64, i64)>,
     coord: (i64, i64),
     state: ProgramState,
-    direction: Direction,
+    motor: Motor,
 }
 
 impl Work {
@@ -60,13 +60,15 @@ impl Work {
         prev_coord: Option<(i64, i64)>,
         coord: (i64, i64),
         state: ProgramState,
+        mut motor: Motor,
         direction: Direction,
     ) -> Self {
+        motor.go(direction as i64);
         Work {
             prev_coord,
             coord,
             state,
-            direction,
+            motor,
         }
     }
 }
@@ -135,15 +137,15 @@ fn queue_new_work(
     coord: &(i64, i64),
     adjacents: &Vec<((i64, i64), Direction)>,
     state: &ProgramState,
+    motor: &Motor,
     work_to_do: &mut Vec<Work>,
 ) {
     for (next_coord, dir) in adjacents {
-        let mut new_state = state.clone();
-        new_state.inputs[0] = *dir as i64;
         work_to_do.push(Work::new(
             Some(coord.clone()),
             next_coord.clone(),
-            new_state,
+            state.clone(),
+            motor.clone(),
             *dir,
         ));
     }
@@ -176,31 +178,35 @@ fn main() {
     let mut map = HashMap::<(i64, i64), TileType>::new();
     let mut paths = HashMap::<(i64, i64), (i64, i64)>::new();
 
-    let base_program: Vec<i64> = text.split(",").map(|n| n.parse().expect("nope")).collect();
+    let base_program = get_base_program(&text);
 
     let mut work_to_do: Vec<Work> = vec![
         Work::new(
             None,
             (0, 0),
-            ProgramState::new(&base_program, vec![Direction::Up as i64]),
+            ProgramState::new(&base_program, vec![]),
+            Motor::new(),
             Direction::Up,
         ),
         Work::new(
             None,
             (0, 0),
-            ProgramState::new(&base_program, vec![Direction::Down as i64]),
+            ProgramState::new(&base_program, vec![]),
+            Motor::new(),
             Direction::Down,
         ),
         Work::new(
             None,
             (0, 0),
-            ProgramState::new(&base_program, vec![Direction::Left as i64]),
+            ProgramState::new(&base_program, vec![]),
+            Motor::new(),
             Direction::Left,
         ),
         Work::new(
             None,
             (0, 0),
-            ProgramState::new(&base_program, vec![Direction::Right as i64]),
+            ProgramState::new(&base_program, vec![]),
+            Motor::new(),
             Direction::Right,
         ),
     ];
@@ -217,33 +223,42 @@ fn main() {
         let coord = work.coord.clone();
         let prev_coord = work.prev_coord.clone();
 
-        run_program(&mut work.state, false, |state, output| {
-            if output == 0 {
+        run_device(&mut work.state, &mut Bus::new(1).attach(&mut work.motor));
+
+        match work.motor.status() {
+            Some(0) => {
                 map.insert(coord, TileType::Wall);
-            } else if output == 1 {
+            }
+            Some(1) => {
                 map.insert(coord, TileType::Empty);
                 let adjacents = get_adjacents(&coord, &map);
                 populate_coords(&adjacents, &mut map);
-                queue_new_work(&coord, &adjacents, &state, &mut work_to_do);
-            } else if output == 2 {
+                queue_new_work(
+                    &coord,
+                    &adjacents,
+                    &work.state,
+                    &work.motor,
+                    &mut work_to_do,
+                );
+            }
+            Some(2) => {
                 map.insert(coord.clone(), TileType::Oxygen);
                 oxygen_coord = Some(coord);
             }
+            status => panic!("unexpected status {:?}", status),
+        }
 
-            if let Some(prev_coord) = prev_coord {
-                if paths.contains_key(&coord) {
-                    panic!("path already contained child {:?}", coord);
-                }
-                paths.insert(coord.clone(), prev_coord);
+        if let Some(prev_coord) = prev_coord {
+            if paths.contains_key(&coord) {
+                panic!("path already contained child {:?}", coord);
             }
+            paths.insert(coord.clone(), prev_coord);
+        }
 
-            min_x = cmp::min(min_x, coord.0);
-            min_y = cmp::min(min_y, coord.1);
-            max_x = cmp::max(max_x, coord.0);
-            max_y = cmp::max(max_y, coord.1);
-
-            true
-        });
+        min_x = cmp::min(min_x, coord.0);
+        min_y = cmp::min(min_y, coord.1);
+        max_x = cmp::max(max_x, coord.0);
+        max_y = cmp::max(max_y, coord.1);
     }
 
     let oxygen_coord = oxygen_coord.unwrap();
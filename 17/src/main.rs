@@ -1,8 +1,8 @@
-use std::cmp;
 use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
 
-use intcode::{run_program, ProgramState};
+use intcode::peripherals::{Canvas, Terminal};
+use intcode::{get_base_program, run_device, Bus, Family, ProgramState};
 use read_input::read_text;
 
 #[derive(PartialEq)]
@@ -86,18 +86,6 @@ type Coord = (i64, i64);
 
 type Tiles = HashMap<Coord, TileType>;
 
-fn print_map(max_x: i64, max_y: i64, tiles: &Tiles) {
-    for y in 0..=max_y {
-        for x in 0..=max_x {
-            let coord = (x, y);
-            if tiles.contains_key(&coord) {
-                print!("{}", tiles.get(&coord).unwrap());
-            }
-        }
-        print!("\n");
-    }
-}
-
 fn move_by_direction(tiles: &Tiles, coord: &mut Coord, direction: &FacingDirection) -> bool {
     match *direction {
         FacingDirection::Down => {
@@ -202,40 +190,32 @@ fn get_next_index(
 
 fn main() {
     let text = read_text("17/input.txt").unwrap();
-    let base_program: Vec<i64> = text.split(",").map(|n| n.parse().expect("nope")).collect();
+    let base_program = get_base_program(&text);
+    if let Some(warning) = Family::Scaffold.check(&base_program) {
+        eprintln!("warning: {}", warning);
+    }
 
     let mut program_state = ProgramState::new(&base_program, Vec::new());
+    let mut camera = Canvas::text();
+    run_device(&mut program_state, &mut Bus::new(1).attach(&mut camera));
+    print!("{}", camera.render(|value| value as u8 as char));
 
     let mut tiles: Tiles = HashMap::new();
     let mut scaffold_spots = HashSet::new();
-
-    let mut coord = (0, 0);
-    let mut robot_coord = (0, 0);
-    let mut max_x = 0;
-    run_program(&mut program_state, false, |_program_state, value| {
-        if value == 35 {
-            tiles.insert(coord.clone(), TileType::Scaffold);
-            scaffold_spots.insert(coord.clone());
-            coord.0 += 1;
-        } else if value == 46 {
-            tiles.insert(coord.clone(), TileType::Empty);
-            coord.0 += 1;
-        } else if value == 10 {
-            coord.1 += 1;
-            coord.0 = 0;
-        } else if value == 94 {
-            tiles.insert(coord.clone(), TileType::Robot);
-            robot_coord.0 = coord.0;
-            robot_coord.1 = coord.1;
-            coord.0 += 1;
+    for (coord, value) in camera.cells() {
+        match value as u8 {
+            b'#' => {
+                tiles.insert(coord, TileType::Scaffold);
+                scaffold_spots.insert(coord);
+            }
+            b'^' => {
+                tiles.insert(coord, TileType::Robot);
+            }
+            _ => {
+                tiles.insert(coord, TileType::Empty);
+            }
         }
-
-        max_x = cmp::max(max_x, coord.0);
-
-        false
-    });
-
-    print_map(max_x, coord.1, &tiles);
+    }
 
     let mut sum = 0;
 
@@ -252,34 +232,25 @@ fn main() {
     println!("p1: {}", sum);
 
     let functions = vec![
-        vec!["L", "10", "R", "8", "R", "8\n"],
-        vec!["L", "10", "L", "12", "R", "8", "R", "10\n"],
-        vec!["R", "10", "L", "12", "R", "10\n"],
+        vec!["L", "10", "R", "8", "R", "8"],
+        vec!["L", "10", "L", "12", "R", "8", "R", "10"],
+        vec!["R", "10", "L", "12", "R", "10"],
     ];
 
-    let mut functions: Vec<i64> = functions
-        .iter()
-        .flat_map(|command: &Vec<&str>| {
-            let vec = command.join(",").as_bytes().to_vec();
-            vec.iter().map(|digit| *digit as i64).collect::<Vec<i64>>()
-        })
-        .collect();
-
-    let mut main_command: Vec<i64> = "A,A,B,C,B,C,B,C,C,A\n"
-        .as_bytes()
-        .iter()
-        .map(|digit| *digit as i64)
-        .collect();
-
-    main_command.append(&mut functions);
-    main_command.append(&mut vec![110, 10]);
-
-    let mut program_state = ProgramState::new(&base_program, main_command);
-    program_state.program[0] = 2;
+    let mut vacuum_robot = Terminal::new();
+    vacuum_robot.send_line("A,A,B,C,B,C,B,C,C,A");
+    for function in &functions {
+        vacuum_robot.send_line(&function.join(","));
+    }
+    // no video feed
+    vacuum_robot.send_line("n");
 
-    run_program(&mut program_state, true, |_program_state, value| {
-        println!("p2 output {}", value);
+    let mut program_state = ProgramState::new(&base_program, Vec::new());
+    program_state.write(0, 2);
+    run_device(
+        &mut program_state,
+        &mut Bus::new(1).attach(&mut vacuum_robot),
+    );
 
-        false
-    });
+    println!("p2: {}", vacuum_robot.values()[0]);
 }
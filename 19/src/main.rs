@@ -1,10 +1,11 @@
 use std::io;
+use std::sync::Arc;
 
-use intcode::{run_program, ProgramState};
+use intcode::{batch, parse_program, run_program, Family, Image, ProgramState};
 use read_input::read_text;
 
-fn check_if_location_in_beam(base_program: &Vec<i64>, x: i64, y: i64) -> bool {
-    let mut program_state = ProgramState::new(base_program, vec![x, y]);
+fn check_if_location_in_beam(base_program: &Arc<Image>, x: i64, y: i64) -> bool {
+    let mut program_state = ProgramState::from_image(base_program, vec![x, y]);
     let mut in_beam = false;
     run_program(&mut program_state, false, |_state, value| {
         if value == 1 {
@@ -19,22 +20,19 @@ fn check_if_location_in_beam(base_program: &Vec<i64>, x: i64, y: i64) -> bool {
 
 fn main() -> io::Result<()> {
     let text = read_text("19/input.txt")?;
-    let base_program: Vec<i64> = text.split(",").map(|n| n.parse().expect("nope")).collect();
-
-    let mut covered_spaces = 0;
-    let mut coordinate_stepper = 0;
-
-    while coordinate_stepper < 2499 {
-        if check_if_location_in_beam(
-            &base_program,
-            coordinate_stepper % 50,
-            coordinate_stepper / 50,
-        ) {
-            covered_spaces += 1;
-        }
-
-        coordinate_stepper += 1;
+    let base_program =
+        parse_program(&text).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+    if let Some(warning) = Family::TractorBeam.check(&base_program) {
+        eprintln!("warning: {}", warning);
     }
+    let base_program = Arc::new(Image::new(base_program));
+
+    // every square of the 50 by 50 grid is an independent run of the drone program
+    let squares = (0..2500).map(|square| vec![square % 50, square / 50]);
+    let covered_spaces = batch::run_all(&base_program, squares, 4)
+        .iter()
+        .filter(|outputs| outputs.contains(&1))
+        .count();
 
     println!("{}", covered_spaces);
 
@@ -0,0 +1,38 @@
+pub fn try_intcode(
+    base_program: &Vec<usize>,
+    working_numbers: &mut Vec<usize>,
+    noun: usize,
+    verb: usize,
+) -> usize {
+    for (idx, num) in base_program.iter().enumerate() {
+        working_numbers[idx] = *num;
+    }
+
+    let mut index = 0;
+
+    working_numbers[1] = noun;
+    working_numbers[2] = verb;
+
+    loop {
+        let op_code = working_numbers[index];
+        match op_code {
+            1 => {
+                let sum = working_numbers[working_numbers[index + 1]]
+                    + working_numbers[working_numbers[index + 2]];
+                let sum_position = working_numbers[index + 3];
+                working_numbers[sum_position] = sum;
+            }
+            2 => {
+                let product = working_numbers[working_numbers[index + 1]]
+                    * working_numbers[working_numbers[index + 2]];
+                let product_position = working_numbers[index + 3];
+                working_numbers[product_position] = product;
+            }
+            99 => break,
+            _ => panic!("Invalid opcode {} at {}", op_code, index),
+        }
+        index += 4;
+    }
+
+    return working_numbers[0];
+}
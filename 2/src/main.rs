@@ -1,43 +1,10 @@
-use read_input::read_text;
-
-fn try_intcode(
-    base_program: &Vec<usize>,
-    working_numbers: &mut Vec<usize>,
-    noun: usize,
-    verb: usize,
-) -> usize {
-    for (idx, num) in base_program.iter().enumerate() {
-        working_numbers[idx] = *num;
-    }
-
-    let mut index = 0;
+use std::sync::Arc;
 
-    working_numbers[1] = noun;
-    working_numbers[2] = verb;
-
-    loop {
-        let op_code = working_numbers[index];
-        match op_code {
-            1 => {
-                let sum = working_numbers[working_numbers[index + 1]]
-                    + working_numbers[working_numbers[index + 2]];
-                let sum_position = working_numbers[index + 3];
-                working_numbers[sum_position] = sum;
-            }
-            2 => {
-                let product = working_numbers[working_numbers[index + 1]]
-                    * working_numbers[working_numbers[index + 2]];
-                let product_position = working_numbers[index + 3];
-                working_numbers[product_position] = product;
-            }
-            99 => break,
-            _ => panic!("Invalid opcode {} at {}", op_code, index),
-        }
-        index += 4;
-    }
+use ::intcode::{Image, Search};
+use read_input::read_text;
 
-    return working_numbers[0];
-}
+mod intcode;
+use intcode::try_intcode;
 
 fn main() {
     let text = read_text("2/input.txt").unwrap();
@@ -49,21 +16,14 @@ fn main() {
     let pos_zero_value = try_intcode(&base_program, &mut numbers, 12, 2);
     println!("{}", pos_zero_value);
 
-    let mut noun = 0;
-    let mut verb = 0;
-    loop {
-        let pos_zero_value = try_intcode(&base_program, &mut numbers, noun, verb);
-        if pos_zero_value == 19690720 {
-            println!("{}", 100 * noun + verb);
-            break;
-        }
-        noun += 1;
-        if noun > 99 {
-            verb += 1;
-            noun = 0;
-            if verb > 99 {
-                panic!("Could not find solution");
-            }
-        }
+    let image = Arc::new(Image::new(base_program.iter().map(|n| *n as i64).collect()));
+    let result = Search::new(&image)
+        .cell(1, 0..=99)
+        .cell(2, 0..=99)
+        .threads(4)
+        .run(|run| run.state.read(0) == 19690720);
+    match result.matches.first() {
+        Some(found) => println!("{}", 100 * found.values[0] + found.values[1]),
+        None => panic!("Could not find solution"),
     }
 }
@@ -1,40 +1,28 @@
 use std::io::Result;
 
-use intcode::{get_base_program, run_program, ProgramState};
+use intcode::peripherals::Terminal;
+use intcode::{get_base_program, run_device, Bus, Family, ProgramState};
 use read_input::read_text;
 
-fn get_instructions_as_input(instructions: &Vec<&str>) -> Vec<i64> {
-    let mut input: Vec<i64> = instructions
-        .join("\n")
-        .as_bytes()
-        .iter()
-        .map(|u| *u as i64)
-        .collect();
-
-    // add a trailing new line
-    input.push(10);
-    input
-}
-
 fn try_program(instructions: &Vec<&str>, base_program: &Vec<i64>) {
-    let input = get_instructions_as_input(instructions);
-
-    let mut program_state = ProgramState::new(base_program, input);
-
-    run_program(&mut program_state, false, |_state, value| {
-        if value > 127 {
-            print!("{}", value);
-        } else {
-            print!("{}", String::from_utf8(vec![value as u8]).unwrap());
-        }
-
-        false
-    });
+    let mut springdroid = Terminal::new().echo();
+    for instruction in instructions {
+        springdroid.send_line(instruction);
+    }
+
+    let mut program_state = ProgramState::new(base_program, vec![]);
+    run_device(
+        &mut program_state,
+        &mut Bus::new(1).attach(&mut springdroid),
+    );
 }
 
 fn main() -> Result<()> {
     let text = read_text("21/input.txt")?;
     let base_program = get_base_program(&text);
+    if let Some(warning) = Family::Springdroid.check(&base_program) {
+        eprintln!("warning: {}", warning);
+    }
 
     // p1
     let mut instructions = Vec::with_capacity(15);
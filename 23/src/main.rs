@@ -1,89 +1,49 @@
-use std::collections::HashMap;
 use std::io::Result;
 
-use intcode;
+use intcode::scheduler::Stop;
 use read_input::read_text;
 
-fn send_packet_to_nic(
-    nics: &mut Vec<intcode::ProgramState>,
-    receiver_addr: usize,
-    outputs: &[i64; 3],
-) {
-    let receiver = nics.get_mut(receiver_addr).unwrap();
-    receiver.inputs.push(outputs[1]);
-    receiver.inputs.push(outputs[2]);
-    receiver.inputs.push(-1);
-}
-
 fn main() -> Result<()> {
     let text = read_text("23/input.txt")?;
 
     let base_program = intcode::get_base_program(&text);
-    let mut nics: Vec<intcode::ProgramState> = (0..50)
-        .map(|n| intcode::ProgramState::new(&base_program, vec![n, -1]))
-        .collect();
-
-    let mut packets: HashMap<i64, (usize, [i64; 3])> = HashMap::new();
+    if let Some(warning) = intcode::Family::Nic.check(&base_program) {
+        eprintln!("warning: {}", warning);
+    }
 
-    let mut logged_first_nat_packet = false;
-    let mut nat_packet = [0i64; 3];
-    let mut sent_to_addr_zero = 0;
+    // each packet is an address, x and y, and a nic with nothing to read gets -1
+    let mut network = intcode::Scheduler::new(1000).frame_len(3).poll_with(-1);
+    for address in 0..50 {
+        network.add(intcode::ProgramState::new(&base_program, vec![address]));
+    }
 
-    'main: loop {
-        let mut sent_packets_this_step = false;
-        for address in 0..nics.len() {
-            let ai64 = address as i64;
-            // put an empty packet in for this address
-            if !packets.contains_key(&ai64) {
-                packets.insert(ai64, (0, [0, 0, 0]));
+    let mut nat_packet: Option<[i64; 2]> = None;
+    let mut sent_to_addr_zero = None;
+
+    loop {
+        let stop = network.run(|network, _, packet| {
+            let receiver_addr = packet[0] as usize;
+            if receiver_addr < network.len() {
+                network.send(receiver_addr, &packet[1..]);
+            } else if receiver_addr == 255 {
+                if nat_packet.is_none() {
+                    println!("p1 {:?}", packet);
+                }
+                nat_packet = Some([packet[1], packet[2]]);
             }
-
-            // get the current index to set output from incode, as well as the array
-            let (output_idx, outputs) = packets.get_mut(&ai64).unwrap();
-            let nic = nics.get_mut(address).unwrap();
-            let result = intcode::run_step(nic, false);
-            if let Some(value) = result.0 {
-                outputs[*output_idx] = value;
-
-                // on last output
-                if *output_idx == 2 {
-                    let receiver_addr = outputs[0] as usize;
-                    // send the packet data
-                    if receiver_addr < 50 {
-                        sent_packets_this_step = true;
-                        send_packet_to_nic(&mut nics, receiver_addr, outputs);
-                    } else if receiver_addr == 255 {
-                        if !logged_first_nat_packet {
-                            logged_first_nat_packet = true;
-                            println!("p1 {:?}", outputs);
-                        }
-                        nat_packet[0] = outputs[0];
-                        nat_packet[1] = outputs[1];
-                        nat_packet[2] = outputs[2];
-                    }
-                    *output_idx = 0;
-                } else {
-                    *output_idx += 1;
+            false
+        });
+
+        match (stop, nat_packet) {
+            (Stop::Idle, Some(packet)) => {
+                if sent_to_addr_zero == Some(packet[1]) {
+                    println!("p2 {}", packet[1]);
+                    break;
                 }
+                sent_to_addr_zero = Some(packet[1]);
+                network.send(0, &packet);
             }
-        }
-
-        let idle_nics = nics
-            .iter()
-            .filter(|nic| {
-                nic.inputs_index >= nic.inputs.len() - 1 && *nic.inputs.last().unwrap() == -1
-            })
-            .count();
-
-        if !sent_packets_this_step && logged_first_nat_packet && idle_nics == nics.len() {
-            println!("sending packet to 0 {:?}", nat_packet);
-            send_packet_to_nic(&mut nics, 0, &nat_packet);
-            // if nat_packet[2] == sent_to_addr_zero {
-            //     println!("p2 {}", sent_to_addr_zero);
-            //     break 'main;
-            // } else {
-            //     sent_to_addr_zero = nat_packet[2];
-            // }
+            (stop, _) => panic!("the network stopped: {}", stop),
         }
     }
 
@@ -1,25 +1,17 @@
-use std::io::Result;
+use std::env;
+use std::io::{self, BufRead, Result, Write};
 
-use intcode;
+use intcode::peripherals::Terminal;
+use intcode::{Bus, TranscriptMode};
 use read_input::read_text;
 
-fn get_instructions_as_input(instructions: &Vec<&str>) -> Vec<i64> {
-    let mut input: Vec<i64> = instructions
-        .join("\n")
-        .as_bytes()
-        .iter()
-        .map(|u| *u as i64)
-        .collect();
-
-    // add a trailing new line
-    input.push(10);
-    input
-}
-
 fn main() -> Result<()> {
     let text = read_text("25/input.txt")?;
 
     let base_program = intcode::get_base_program(&text);
+    if let Some(warning) = intcode::Family::Cryostasis.check(&base_program) {
+        eprintln!("warning: {}", warning);
+    }
 
     // let original_instructions = vec![
     //     "north",
@@ -106,13 +98,34 @@ fn main() -> Result<()> {
         "south",
     ];
 
-    let mut program =
-        intcode::ProgramState::new(&base_program, get_instructions_as_input(&instructions));
+    let mode = TranscriptMode::from_args(env::args().skip(1));
+
+    let mut program = intcode::ProgramState::new(&base_program, vec![]);
+    let mut terminal = Terminal::new().echo();
+    let replaying = mode.start(&mut program)?;
+    if !mode.is_replay() {
+        for instruction in &instructions {
+            terminal.send_line(instruction);
+        }
+    }
+
+    // once the scripted commands run out, carry on with commands typed in
+    let stdin = io::stdin();
+    while !intcode::run_device(&mut program, &mut Bus::new(1).attach(&mut terminal)) {
+        if mode.is_replay() {
+            break;
+        }
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+        terminal.send_line(line.trim_end());
+    }
 
-    intcode::run_program(&mut program, true, |state, value| {
-        print!("{}", String::from_utf8(vec![value as u8]).unwrap());
-        false
-    });
+    if let Some(input) = mode.finish(&mut program, replaying.as_ref())? {
+        eprintln!("warning: the replay went differently from input {}", input);
+    }
 
     Ok(())
 }
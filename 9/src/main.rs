@@ -1,3 +1,4 @@
+use ::intcode::get_base_program;
 use read_input::read_text;
 
 mod intcode;
@@ -6,7 +7,7 @@ use intcode::ProgramState;
 fn main() {
     let text = read_text("9/input.txt").unwrap();
 
-    let base_program: Vec<i64> = text.split(",").map(|n| n.parse().expect("nope")).collect();
+    let base_program = get_base_program(&text);
 
     let mut state = ProgramState::new(&base_program, vec![1]);
     let result = intcode::run_program(&mut state, false);
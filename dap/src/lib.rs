@@ -0,0 +1,6 @@
+//! A Debug Adapter Protocol server for the intcode VM, so editors can debug intcode programs.
+
+pub mod protocol;
+pub mod session;
+
+pub use session::Session;
@@ -0,0 +1,62 @@
+use std::io;
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+
+use dap::protocol::{read_message, write_message};
+use dap::Session;
+
+fn main() -> io::Result<()> {
+    // requests are read on their own thread so a running machine can still be paused
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut reader = stdin.lock();
+        loop {
+            let message = match read_message(&mut reader) {
+                Ok(Some(message)) => message,
+                Ok(None) => break,
+                // the whole of a bad message has been read, so the next one can still be. Any
+                // other error leaves the stream somewhere in the middle of a message.
+                Err(error) if error.kind() == io::ErrorKind::InvalidData => {
+                    eprintln!("skipping malformed message: {}", error);
+                    continue;
+                }
+                Err(error) => {
+                    eprintln!("reading messages: {}", error);
+                    break;
+                }
+            };
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let stdout = io::stdout();
+    let mut writer = stdout.lock();
+    let mut session = Session::new();
+    while !session.is_done() {
+        let message = if session.is_running() {
+            match receiver.try_recv() {
+                Ok(message) => Some(message),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => break,
+            }
+        } else {
+            match receiver.recv() {
+                Ok(message) => Some(message),
+                Err(_) => break,
+            }
+        };
+
+        let replies = match message {
+            Some(message) => session.handle(&message),
+            None => session.run_slice(),
+        };
+        for reply in &replies {
+            write_message(&mut writer, reply)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,133 @@
+//! Message framing: a `Content-Length` header, a blank line, then that many bytes of JSON.
+//!
+//! A message that is framed properly but can't be used, because its body isn't JSON or is longer
+//! than `MAX_LENGTH`, is read to its end and reported as `InvalidData`, so the next one can still
+//! be read. A header without a usable length leaves no way to find where the body ends; that is
+//! reported as any other kind of error, and the stream has to be given up.
+
+use std::io::{self, BufRead, Read, Write};
+
+use serde_json::Value;
+
+/// Longest body that is read into memory
+pub const MAX_LENGTH: usize = 1 << 24;
+
+fn invalid<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Reads the next message, `None` once the stream is closed
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    let mut bad_length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            if length.is_some() || bad_length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            match value.trim().parse::<usize>() {
+                Ok(value) => length = Some(value),
+                Err(_) => bad_length = Some(value.trim().to_string()),
+            }
+        }
+    }
+    // the rest of the header block has been read, but not a body of unknown length
+    if let Some(value) = bad_length {
+        return Err(io::Error::other(format!(
+            "bad Content-Length {:?}, lost track of the messages",
+            value
+        )));
+    }
+
+    let length = length.unwrap();
+    if length > MAX_LENGTH {
+        let skipped = io::copy(&mut reader.by_ref().take(length as u64), &mut io::sink())?;
+        if skipped < length as u64 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        return Err(invalid(format!(
+            "message of {} bytes is longer than {}",
+            length, MAX_LENGTH
+        )));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(invalid)
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_round_trips_messages() {
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, &json!({"seq": 1, "type": "request"})).unwrap();
+        write_message(&mut buffer, &json!({"seq": 2, "command": "threads"})).unwrap();
+        assert!(buffer.starts_with(b"Content-Length: 26\r\n\r\n{"));
+
+        let mut reader = &buffer[..];
+        assert_eq!(
+            read_message(&mut reader).unwrap(),
+            Some(json!({"seq": 1, "type": "request"}))
+        );
+        assert_eq!(
+            read_message(&mut reader).unwrap(),
+            Some(json!({"seq": 2, "command": "threads"}))
+        );
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_reads_on_after_a_malformed_message() {
+        let mut buffer = b"Content-Length: 5\r\n\r\n{seq:".to_vec();
+        write_message(&mut buffer, &json!({"seq": 2})).unwrap();
+
+        let mut reader = &buffer[..];
+        let error = read_message(&mut reader).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(read_message(&mut reader).unwrap(), Some(json!({"seq": 2})));
+    }
+
+    #[test]
+    fn test_skips_messages_over_the_limit() {
+        let mut buffer = format!("Content-Length: {}\r\n\r\n", MAX_LENGTH + 1).into_bytes();
+        buffer.resize(buffer.len() + MAX_LENGTH + 1, b' ');
+        write_message(&mut buffer, &json!({"seq": 2})).unwrap();
+
+        let mut reader = &buffer[..];
+        let error = read_message(&mut reader).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(read_message(&mut reader).unwrap(), Some(json!({"seq": 2})));
+    }
+
+    #[test]
+    fn test_bad_length_loses_the_stream() {
+        let mut buffer = b"Content-Length: many\r\nContent-Type: json\r\n\r\n{}".to_vec();
+        write_message(&mut buffer, &json!({"seq": 2})).unwrap();
+
+        let mut reader = &buffer[..];
+        let error = read_message(&mut reader).unwrap_err();
+        assert_ne!(error.kind(), io::ErrorKind::InvalidData);
+        // the whole header block was read
+        assert!(reader.starts_with(b"{}Content-Length"));
+    }
+}
@@ -0,0 +1,800 @@
+//! Debug Adapter Protocol requests for one intcode machine.
+//!
+//! Programs are launched from a file of comma separated numbers, or from assembler source if the
+//! file ends in `.asm`, in which case its labels can be used wherever an address can. The machine
+//! is the only thread. Breakpoints are function breakpoints, naming a label or an address, or
+//! instruction breakpoints. Memory references are word addresses, and each word reads as 8 little
+//! endian bytes, so offsets into memory have to be whole words.
+//!
+//! A machine waiting for input with none queued stops with the reason `pause`. Evaluating
+//! `input 1, 2` in the console queues more.
+
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::fs;
+
+use serde_json::{json, Value};
+
+use intcode::{asm, parse_program, try_run_step, Frame, ProgramState};
+
+/// Instructions run between checks for new requests while the machine is running
+pub const SLICE: usize = 10_000;
+/// Most bytes returned by one `readMemory`
+pub const MAX_READ: usize = 1 << 16;
+const THREAD: i64 = 1;
+const REGISTERS: i64 = 1;
+/// Variables references from here on are the locals of a stack frame
+const FRAMES: i64 = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Running {
+    Forward,
+    Backward,
+    /// Until the call stack is shallower than this
+    Out(usize),
+    /// Until the call stack is back to this depth, after stepping into a call
+    Over(usize),
+}
+
+pub struct Session {
+    seq: i64,
+    state: Option<ProgramState>,
+    labels: HashMap<String, usize>,
+    function_breakpoints: BTreeSet<usize>,
+    instruction_breakpoints: BTreeSet<usize>,
+    stop_on_entry: bool,
+    running: Option<Running>,
+    done: bool,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Session {
+            seq: 0,
+            state: None,
+            labels: HashMap::new(),
+            function_breakpoints: BTreeSet::new(),
+            instruction_breakpoints: BTreeSet::new(),
+            stop_on_entry: false,
+            running: None,
+            done: false,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// Whether the client has disconnected
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    fn next_seq(&mut self) -> i64 {
+        self.seq += 1;
+        self.seq
+    }
+
+    fn response(&mut self, request: &Value, body: Value) -> Value {
+        json!({
+            "seq": self.next_seq(),
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+            "body": body,
+        })
+    }
+
+    fn error(&mut self, request: &Value, message: &str) -> Value {
+        json!({
+            "seq": self.next_seq(),
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message,
+        })
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> Value {
+        json!({
+            "seq": self.next_seq(),
+            "type": "event",
+            "event": event,
+            "body": body,
+        })
+    }
+
+    fn stopped(&mut self, reason: &str, description: Option<String>) -> Value {
+        self.running = None;
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD,
+            "allThreadsStopped": true,
+        });
+        if let Some(description) = description {
+            body["description"] = json!(description.clone());
+            body["text"] = json!(description);
+        }
+        self.event("stopped", body)
+    }
+
+    /// Handles one request, returning its response and any events it caused
+    pub fn handle(&mut self, request: &Value) -> Vec<Value> {
+        let command = request["command"].as_str().unwrap_or("");
+        let arguments = &request["arguments"];
+        if self.state.is_none() && needs_machine(command) {
+            return vec![self.error(request, "no program has been launched")];
+        }
+
+        match command {
+            "initialize" => {
+                let capabilities = json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsFunctionBreakpoints": true,
+                    "supportsInstructionBreakpoints": true,
+                    "supportsStepBack": true,
+                    "supportsReadMemoryRequest": true,
+                    "supportsEvaluateForHovers": true,
+                });
+                vec![
+                    self.response(request, capabilities),
+                    self.event("initialized", json!({})),
+                ]
+            }
+            "launch" => match self.launch(arguments) {
+                Ok(()) => vec![self.response(request, json!({}))],
+                Err(message) => vec![self.error(request, &message)],
+            },
+            "setFunctionBreakpoints" => {
+                let names: Vec<String> = arguments["breakpoints"]
+                    .as_array()
+                    .map(|breakpoints| {
+                        breakpoints
+                            .iter()
+                            .map(|breakpoint| breakpoint["name"].as_str().unwrap_or("").to_string())
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let resolved: Vec<Option<usize>> =
+                    names.iter().map(|name| self.resolve(name)).collect();
+                self.function_breakpoints = resolved.iter().flatten().cloned().collect();
+                let body = breakpoints_body(&names, &resolved);
+                vec![self.response(request, body)]
+            }
+            "setInstructionBreakpoints" => {
+                let (names, resolved): (Vec<String>, Vec<Option<usize>>) = arguments["breakpoints"]
+                    .as_array()
+                    .map(|breakpoints| {
+                        breakpoints
+                            .iter()
+                            .map(|breakpoint| {
+                                let reference =
+                                    breakpoint["instructionReference"].as_str().unwrap_or("");
+                                let offset = breakpoint["offset"].as_i64().unwrap_or(0);
+                                let address = self
+                                    .resolve(reference)
+                                    .map(|address| address as i64 + offset)
+                                    .filter(|address| *address >= 0)
+                                    .map(|address| address as usize);
+                                (reference.to_string(), address)
+                            })
+                            .unzip()
+                    })
+                    .unwrap_or_default();
+                self.instruction_breakpoints = resolved.iter().flatten().cloned().collect();
+                let body = breakpoints_body(&names, &resolved);
+                vec![self.response(request, body)]
+            }
+            "setBreakpoints" => {
+                let count = arguments["breakpoints"]
+                    .as_array()
+                    .map_or(0, |breakpoints| breakpoints.len());
+                let unverified = json!({
+                    "verified": false,
+                    "message": "use function or instruction breakpoints",
+                });
+                let body = json!({ "breakpoints": vec![unverified; count] });
+                vec![self.response(request, body)]
+            }
+            "setExceptionBreakpoints" => vec![self.response(request, json!({ "breakpoints": [] }))],
+            "configurationDone" => {
+                let response = self.response(request, json!({}));
+                if self.stop_on_entry {
+                    vec![response, self.stopped("entry", None)]
+                } else {
+                    self.running = Some(Running::Forward);
+                    vec![response]
+                }
+            }
+            "threads" => {
+                let body = json!({ "threads": [{ "id": THREAD, "name": "intcode" }] });
+                vec![self.response(request, body)]
+            }
+            "continue" => {
+                self.running = Some(Running::Forward);
+                vec![self.response(request, json!({ "allThreadsContinued": true }))]
+            }
+            "reverseContinue" => {
+                self.running = Some(Running::Backward);
+                vec![self.response(request, json!({}))]
+            }
+            "next" => {
+                let depth = self.machine().backtrace().len();
+                let mut messages = vec![self.response(request, json!({}))];
+                match self.step_forward(&mut messages) {
+                    Some(stop) => messages.push(stop),
+                    // the step made a call, so run until it returns
+                    None if self.machine().backtrace().len() > depth => {
+                        self.running = Some(Running::Over(depth));
+                    }
+                    None => messages.push(self.stopped("step", None)),
+                }
+                messages
+            }
+            "stepIn" => {
+                let mut messages = vec![self.response(request, json!({}))];
+                let stop = self.step_forward(&mut messages);
+                messages.push(match stop {
+                    Some(stop) => stop,
+                    None => self.stopped("step", None),
+                });
+                messages
+            }
+            "stepOut" => {
+                let depth = self.machine().backtrace().len();
+                self.running = Some(Running::Out(depth));
+                vec![self.response(request, json!({}))]
+            }
+            "stepBack" => {
+                let response = self.response(request, json!({}));
+                let description = if self.machine_mut().step_back() {
+                    None
+                } else {
+                    Some("no more history".to_string())
+                };
+                vec![response, self.stopped("step", description)]
+            }
+            "pause" => {
+                let response = self.response(request, json!({}));
+                vec![response, self.stopped("pause", None)]
+            }
+            "stackTrace" => {
+                let body = self.stack_trace();
+                vec![self.response(request, body)]
+            }
+            "scopes" => {
+                let body = self.scopes(arguments["frameId"].as_i64().unwrap_or(0));
+                vec![self.response(request, body)]
+            }
+            "variables" => {
+                let body = self.variables(arguments["variablesReference"].as_i64().unwrap_or(0));
+                vec![self.response(request, body)]
+            }
+            "readMemory" => {
+                let reference = arguments["memoryReference"].as_str().unwrap_or("");
+                let offset = arguments["offset"].as_i64().unwrap_or(0);
+                if offset % 8 != 0 {
+                    let message = format!("offset {} is not a whole number of words", offset);
+                    return vec![self.error(request, &message)];
+                }
+                let start = match self.resolve(reference) {
+                    Some(address) => i64::try_from(address)
+                        .ok()
+                        .and_then(|address| address.checked_add(offset / 8)),
+                    None => {
+                        return vec![
+                            self.error(request, &format!("unknown address {:?}", reference))
+                        ]
+                    }
+                };
+                let start = match start {
+                    Some(start) => start,
+                    None => return vec![self.error(request, "address out of range")],
+                };
+                let count =
+                    (arguments["count"].as_i64().unwrap_or(0).max(0) as usize).min(MAX_READ);
+                // nothing below zero can be read; say how far to skip to get to address 0
+                if start < 0 {
+                    let unreadable = (start.unsigned_abs().saturating_mul(8) as usize).min(count);
+                    let body = json!({
+                        "address": start.to_string(),
+                        "unreadableBytes": unreadable,
+                    });
+                    return vec![self.response(request, body)];
+                }
+                let mut bytes = Vec::with_capacity(count.div_ceil(8) * 8);
+                for word in start as usize..start as usize + count.div_ceil(8) {
+                    bytes.extend_from_slice(&self.machine().read(word).to_le_bytes());
+                }
+                bytes.truncate(count);
+                let body = json!({
+                    "address": start.to_string(),
+                    "data": base64(&bytes),
+                });
+                vec![self.response(request, body)]
+            }
+            "evaluate" => match self.evaluate(arguments["expression"].as_str().unwrap_or("")) {
+                Ok(result) => {
+                    let body = json!({ "result": result, "variablesReference": 0 });
+                    vec![self.response(request, body)]
+                }
+                Err(message) => vec![self.error(request, &message)],
+            },
+            "disconnect" | "terminate" => {
+                self.done = true;
+                self.running = None;
+                vec![self.response(request, json!({}))]
+            }
+            _ => vec![self.error(request, &format!("unsupported request {:?}", command))],
+        }
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<(), String> {
+        let path = arguments["program"]
+            .as_str()
+            .ok_or("launch needs a program")?;
+        let text = fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+        let program = if path.ends_with(".asm") {
+            let items = asm::parse(&text).map_err(|error| format!("{}: {}", path, error))?;
+            self.labels = asm::label_addresses(&items).map_err(|error| error.to_string())?;
+            asm::assemble(&items).map_err(|error| format!("{}: {}", path, error))?
+        } else {
+            self.labels.clear();
+            parse_program(&text).map_err(|error| format!("{}:{}", path, error))?
+        };
+        let inputs = arguments["inputs"]
+            .as_array()
+            .map(|inputs| inputs.iter().filter_map(Value::as_i64).collect())
+            .unwrap_or_default();
+
+        let mut state = ProgramState::new(&program, inputs);
+        state.enable_history(1000, 100);
+        state.track_calls();
+        self.state = Some(state);
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        Ok(())
+    }
+
+    fn machine(&self) -> &ProgramState {
+        self.state.as_ref().unwrap()
+    }
+
+    fn machine_mut(&mut self) -> &mut ProgramState {
+        self.state.as_mut().unwrap()
+    }
+
+    /// A label or an address
+    fn resolve(&self, name: &str) -> Option<usize> {
+        let name = name.trim();
+        match self.labels.get(name) {
+            Some(address) => Some(*address),
+            None => name.parse().ok(),
+        }
+    }
+
+    fn is_breakpoint(&self, address: usize) -> bool {
+        self.function_breakpoints.contains(&address)
+            || self.instruction_breakpoints.contains(&address)
+    }
+
+    /// Runs one instruction, returning the event that ends the run if it can't go on
+    fn step_forward(&mut self, messages: &mut Vec<Value>) -> Option<Value> {
+        let state = self.state.as_mut().unwrap();
+        if state.finished {
+            return Some(self.event("terminated", json!({})));
+        }
+        if state.is_starving() {
+            return Some(self.stopped("pause", Some("waiting for input".to_string())));
+        }
+
+        match try_run_step(state, true) {
+            Err(fault) => {
+                let report = state.fault_report(&fault);
+                Some(self.stopped("exception", Some(report)))
+            }
+            Ok((output, _)) => {
+                let finished = state.finished;
+                if let Some(output) = output {
+                    let body = json!({ "category": "stdout", "output": format!("{}\n", output) });
+                    messages.push(self.event("output", body));
+                }
+                if finished {
+                    self.running = None;
+                    messages.push(self.event("exited", json!({ "exitCode": 0 })));
+                    return Some(self.event("terminated", json!({})));
+                }
+                None
+            }
+        }
+    }
+
+    /// Runs until something stops the machine or `SLICE` instructions have gone by
+    pub fn run_slice(&mut self) -> Vec<Value> {
+        let mut messages = Vec::new();
+        for _ in 0..SLICE {
+            let running = match self.running {
+                Some(running) => running,
+                None => break,
+            };
+            if running == Running::Backward {
+                if !self.machine_mut().step_back() {
+                    let stop = self.stopped("step", Some("no more history".to_string()));
+                    messages.push(stop);
+                } else if self.is_breakpoint(self.machine().index) {
+                    messages.push(self.stopped("breakpoint", None));
+                }
+                continue;
+            }
+
+            if let Some(stop) = self.step_forward(&mut messages) {
+                self.running = None;
+                messages.push(stop);
+            } else if self.is_breakpoint(self.machine().index) {
+                let reason = if self.function_breakpoints.contains(&self.machine().index) {
+                    "function breakpoint"
+                } else {
+                    "instruction breakpoint"
+                };
+                messages.push(self.stopped(reason, None));
+            } else if let Running::Out(depth) = running {
+                if self.machine().backtrace().len() < depth {
+                    messages.push(self.stopped("step", None));
+                }
+            } else if let Running::Over(depth) = running {
+                if self.machine().backtrace().len() <= depth {
+                    messages.push(self.stopped("step", None));
+                }
+            }
+        }
+        messages
+    }
+
+    /// The name of the routine at `address`
+    fn routine_name(&self, address: usize) -> String {
+        let label = self
+            .labels
+            .iter()
+            .filter(|(name, at)| **at == address && !name.starts_with('.'))
+            .map(|(name, _)| name.clone())
+            .min();
+        label.unwrap_or_else(|| format!("routine at {}", address))
+    }
+
+    /// Innermost first: each routine on the stack and the instruction it is at
+    fn frames(&self) -> Vec<(String, usize, Option<Frame>)> {
+        let state = self.machine();
+        let backtrace = state.backtrace();
+        let mut frames = Vec::new();
+        for i in 0..=backtrace.len() {
+            let ip = if i == 0 {
+                state.index
+            } else {
+                backtrace[i - 1].call_site
+            };
+            let (name, frame) = match backtrace.get(i) {
+                Some(frame) => (self.routine_name(frame.entry), Some(*frame)),
+                None => (
+                    self.labels
+                        .get("main")
+                        .map_or("main".to_string(), |main| self.routine_name(*main)),
+                    None,
+                ),
+            };
+            frames.push((name, ip, frame));
+        }
+        frames
+    }
+
+    fn stack_trace(&self) -> Value {
+        let frames: Vec<Value> = self
+            .frames()
+            .into_iter()
+            .enumerate()
+            .map(|(id, (name, ip, _))| {
+                json!({
+                    "id": id,
+                    "name": name,
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": ip.to_string(),
+                })
+            })
+            .collect();
+        json!({ "totalFrames": frames.len(), "stackFrames": frames })
+    }
+
+    fn scopes(&self, frame_id: i64) -> Value {
+        let mut scopes = vec![json!({
+            "name": "Registers",
+            "variablesReference": REGISTERS,
+            "expensive": false,
+        })];
+        let has_locals = self
+            .frames()
+            .get(frame_id.max(0) as usize)
+            .is_some_and(|(_, _, frame)| frame.is_some_and(|frame| frame.frame_size > 0));
+        if has_locals {
+            scopes.push(json!({
+                "name": "Frame",
+                "variablesReference": FRAMES + frame_id,
+                "expensive": false,
+            }));
+        }
+        json!({ "scopes": scopes })
+    }
+
+    fn variables(&self, reference: i64) -> Value {
+        let state = self.machine();
+        let variable = |name: String, value: String| json!({ "name": name, "value": value, "variablesReference": 0 });
+
+        let variables = if reference == REGISTERS {
+            let steps = state
+                .history
+                .as_ref()
+                .map_or(0, |history| history.step_count());
+            vec![
+                variable("ip".to_string(), state.index.to_string()),
+                variable("rb".to_string(), state.relative_base.to_string()),
+                variable(
+                    "pending inputs".to_string(),
+                    state.pending_inputs().to_string(),
+                ),
+                variable("finished".to_string(), state.finished.to_string()),
+                variable("steps".to_string(), steps.to_string()),
+            ]
+        } else {
+            // the cells the routine made room for when it moved the base
+            let frames = self.frames();
+            match frames.get((reference - FRAMES).max(0) as usize) {
+                Some((_, _, Some(frame))) => (0..frame.frame_size)
+                    .map(|offset| {
+                        let address = frame.relative_base + offset;
+                        variable(
+                            format!("[{}]", address),
+                            state.read(address as usize).to_string(),
+                        )
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            }
+        };
+        json!({ "variables": variables })
+    }
+
+    fn evaluate(&mut self, expression: &str) -> Result<String, String> {
+        let expression = expression.trim();
+        if let Some(values) = expression.strip_prefix("input") {
+            let values = parse_program(values).map_err(|error| error.to_string())?;
+            let count = values.len();
+            self.machine_mut().inputs.extend(values);
+            return Ok(format!("queued {} inputs", count));
+        }
+
+        let state = self.machine();
+        match expression {
+            "ip" => Ok(state.index.to_string()),
+            "rb" => Ok(state.relative_base.to_string()),
+            _ => {
+                let inner = expression
+                    .strip_prefix('[')
+                    .and_then(|inner| inner.strip_suffix(']'))
+                    .unwrap_or(expression);
+                match self.resolve(inner) {
+                    Some(address) => Ok(state.read(address).to_string()),
+                    None => Err(format!("can't evaluate {:?}", expression)),
+                }
+            }
+        }
+    }
+}
+
+fn needs_machine(command: &str) -> bool {
+    !matches!(
+        command,
+        "initialize"
+            | "launch"
+            | "setFunctionBreakpoints"
+            | "setInstructionBreakpoints"
+            | "setBreakpoints"
+            | "setExceptionBreakpoints"
+            | "threads"
+            | "disconnect"
+            | "terminate"
+    )
+}
+
+fn breakpoints_body(names: &[String], resolved: &[Option<usize>]) -> Value {
+    let breakpoints: Vec<Value> = names
+        .iter()
+        .zip(resolved)
+        .map(|(name, address)| match address {
+            Some(address) => json!({
+                "verified": true,
+                "instructionReference": address.to_string(),
+            }),
+            None => json!({
+                "verified": false,
+                "message": format!("no label or address {:?}", name),
+            }),
+        })
+        .collect();
+    json!({ "breakpoints": breakpoints })
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let word = chunk.iter().enumerate().fold(0u32, |word, (i, byte)| {
+            word | (*byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(word >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(seq: i64, command: &str, arguments: Value) -> Value {
+        json!({ "seq": seq, "type": "request", "command": command, "arguments": arguments })
+    }
+
+    // each test launches from its own file, since tests run in parallel
+    fn launched(name: &str, source: &str, inputs: Vec<i64>) -> Session {
+        let file = format!("dap-session-{}-{}.asm", std::process::id(), name);
+        let path = std::env::temp_dir().join(file);
+        fs::write(&path, source).unwrap();
+        let mut session = Session::new();
+        let responses = session.handle(&request(
+            1,
+            "launch",
+            json!({ "program": path.to_str().unwrap(), "inputs": inputs }),
+        ));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(responses[0]["success"], true, "{}", responses[0]);
+        session
+    }
+
+    fn run(session: &mut Session) -> Vec<Value> {
+        let mut messages = Vec::new();
+        while session.is_running() {
+            messages.extend(session.run_slice());
+        }
+        messages
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(&7i64.to_le_bytes()), "BwAAAAAAAAA=");
+    }
+
+    #[test]
+    fn test_breakpoints_resolve_labels_and_addresses() {
+        let mut session = launched(
+            "breakpoints",
+            "in [x]\nloop: out [x]\n jz #0, #loop\nx: data 0",
+            vec![5],
+        );
+        let responses = session.handle(&request(
+            2,
+            "setFunctionBreakpoints",
+            json!({ "breakpoints": [{ "name": "loop" }, { "name": "nowhere" }, { "name": "5" }] }),
+        ));
+        let breakpoints = &responses[0]["body"]["breakpoints"];
+        assert_eq!(breakpoints[0]["instructionReference"], "2");
+        assert_eq!(breakpoints[1]["verified"], false);
+        assert_eq!(breakpoints[2]["instructionReference"], "5");
+
+        session.handle(&request(3, "configurationDone", json!({})));
+        let messages = run(&mut session);
+        assert_eq!(
+            messages.last().unwrap()["body"]["reason"],
+            "function breakpoint"
+        );
+        assert_eq!(session.machine().index, 2);
+
+        let result = session.handle(&request(4, "evaluate", json!({ "expression": "[x]" })));
+        assert_eq!(result[0]["body"]["result"], "5");
+    }
+
+    #[test]
+    fn test_next_steps_over_calls() {
+        let source = "arb #100\n add #back, #0, [rb]\n jnz #1, #f\nback: out #3\n hlt\n\
+                      f: arb #2\n out #1\n out #2\n arb #-2\n jnz #1, [rb]";
+        let mut session = launched("next", source, vec![]);
+        for seq in 2..4 {
+            session.handle(&request(seq, "next", json!({})));
+        }
+        assert_eq!(session.machine().index, 6);
+
+        let messages = session.handle(&request(4, "next", json!({})));
+        assert_eq!(messages.len(), 1);
+        assert!(session.is_running());
+        let messages = run(&mut session);
+        let outputs: Vec<&Value> = messages
+            .iter()
+            .filter(|message| message["event"] == "output")
+            .map(|message| &message["body"]["output"])
+            .collect();
+        assert_eq!(outputs, vec!["1\n", "2\n"]);
+        assert_eq!(messages.last().unwrap()["body"]["reason"], "step");
+        assert_eq!(session.machine().index, session.labels["back"]);
+
+        let mut session = launched("step-in", source, vec![]);
+        for seq in 2..5 {
+            session.handle(&request(seq, "stepIn", json!({})));
+        }
+        assert_eq!(session.machine().index, session.labels["f"]);
+    }
+
+    #[test]
+    fn test_read_memory() {
+        let mut session = launched("memory", "hlt\nx: data 7, 8", vec![]);
+        let mut read = |reference: &str, offset: i64, count: i64| {
+            let arguments =
+                json!({ "memoryReference": reference, "offset": offset, "count": count });
+            session
+                .handle(&request(2, "readMemory", arguments))
+                .remove(0)
+        };
+
+        let body = read("x", 8, 3)["body"].clone();
+        assert_eq!(
+            (body["address"].as_str(), body["data"].as_str()),
+            (Some("2"), Some("CAAA"))
+        );
+
+        let body = read("x", -16, 24)["body"].clone();
+        assert_eq!(body["address"], "-1");
+        assert_eq!(body["unreadableBytes"], 8);
+        assert!(body["data"].is_null());
+
+        let body = read("0", 0, i64::MAX)["body"].clone();
+        assert_eq!(body["data"].as_str().unwrap().len(), MAX_READ / 3 * 4 + 4);
+
+        assert_eq!(read("x", 4, 8)["success"], false);
+        assert_eq!(read(&i64::MAX.to_string(), 8, 8)["success"], false);
+        assert_eq!(read(&usize::MAX.to_string(), 0, 8)["success"], false);
+    }
+
+    #[test]
+    fn test_starving_machine_pauses_until_given_input() {
+        let mut session = launched("starving", "in [x]\n out [x]\n hlt\nx: data 0", vec![]);
+        session.handle(&request(2, "configurationDone", json!({})));
+        let messages = run(&mut session);
+        assert_eq!(
+            messages.last().unwrap()["body"]["description"],
+            "waiting for input"
+        );
+
+        session.handle(&request(3, "evaluate", json!({ "expression": "input 42" })));
+        session.handle(&request(4, "continue", json!({})));
+        let messages = run(&mut session);
+        let events: Vec<&str> = messages
+            .iter()
+            .map(|message| message["event"].as_str().unwrap())
+            .collect();
+        assert_eq!(events, vec!["output", "exited", "terminated"]);
+        assert_eq!(messages[0]["body"]["output"], "42\n");
+    }
+}
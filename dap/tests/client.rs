@@ -0,0 +1,153 @@
+//! Drives the server binary over stdio the way an editor would.
+
+use std::fs;
+use std::io::{BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use serde_json::{json, Value};
+
+use dap::protocol::{read_message, write_message};
+
+// reads a number, doubles it in a subroutine and prints it
+const SOURCE: &str = "
+main:
+    arb #100
+    in [x]
+    add #back, #0, [rb]
+    jnz #1, #double
+back:
+    out [x]
+    hlt
+double:
+    arb #2
+    mul [x], #2, [x]
+    arb #-2
+    jnz #1, [rb]
+x:  data 0
+";
+
+struct Client {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    seq: i64,
+}
+
+impl Client {
+    fn start() -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_intcode-dap"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Client {
+            child,
+            stdin,
+            stdout,
+            seq: 0,
+        }
+    }
+
+    fn send(&mut self, command: &str, arguments: Value) -> i64 {
+        self.seq += 1;
+        let request = json!({
+            "seq": self.seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        });
+        write_message(&mut self.stdin, &request).unwrap();
+        self.stdin.flush().unwrap();
+        self.seq
+    }
+
+    fn receive(&mut self) -> Value {
+        read_message(&mut self.stdout)
+            .unwrap()
+            .expect("server hung up")
+    }
+
+    /// Sends a request and returns its response body, skipping any events before it
+    fn request(&mut self, command: &str, arguments: Value) -> Value {
+        let seq = self.send(command, arguments);
+        loop {
+            let message = self.receive();
+            if message["type"] == "response" && message["request_seq"] == seq {
+                assert_eq!(message["success"], true, "{}", message);
+                return message["body"].clone();
+            }
+        }
+    }
+
+    /// Waits for the named event
+    fn event(&mut self, event: &str) -> Value {
+        loop {
+            let message = self.receive();
+            if message["type"] == "event" && message["event"] == event {
+                return message["body"].clone();
+            }
+        }
+    }
+}
+
+#[test]
+fn test_debug_session() {
+    let path = std::env::temp_dir().join(format!("dap-client-{}.asm", std::process::id()));
+    fs::write(&path, SOURCE).unwrap();
+    let mut client = Client::start();
+
+    let capabilities = client.request("initialize", json!({ "adapterID": "intcode" }));
+    assert_eq!(capabilities["supportsStepBack"], true);
+    client.event("initialized");
+    client.request(
+        "launch",
+        json!({ "program": path.to_str().unwrap(), "inputs": [21] }),
+    );
+    let breakpoints = client.request(
+        "setFunctionBreakpoints",
+        json!({ "breakpoints": [{ "name": "double" }] }),
+    );
+    assert_eq!(breakpoints["breakpoints"][0]["verified"], true);
+    client.request("configurationDone", json!({}));
+
+    let stopped = client.event("stopped");
+    assert_eq!(stopped["reason"], "function breakpoint");
+    let trace = client.request("stackTrace", json!({ "threadId": 1 }));
+    let frames: Vec<(&str, &str)> = trace["stackFrames"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|frame| {
+            (
+                frame["name"].as_str().unwrap(),
+                frame["instructionPointerReference"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(frames, vec![("double", "14"), ("main", "8")]);
+
+    // x holds the input, 21
+    let memory = client.request("readMemory", json!({ "memoryReference": "x", "count": 8 }));
+    assert_eq!(memory["address"], "25");
+    assert_eq!(memory["data"], "FQAAAAAAAAA=");
+
+    client.request("next", json!({ "threadId": 1 }));
+    assert_eq!(client.event("stopped")["reason"], "step");
+    client.request("next", json!({ "threadId": 1 }));
+    client.event("stopped");
+    let doubled = client.request("evaluate", json!({ "expression": "[x]" }));
+    assert_eq!(doubled["result"], "42");
+    client.request("stepBack", json!({ "threadId": 1 }));
+    client.event("stopped");
+    let undone = client.request("evaluate", json!({ "expression": "[x]" }));
+    assert_eq!(undone["result"], "21");
+
+    client.request("continue", json!({ "threadId": 1 }));
+    assert_eq!(client.event("output")["output"], "42\n");
+    client.event("terminated");
+    client.request("disconnect", json!({}));
+    assert!(client.child.wait().unwrap().success());
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,73 @@
+//! Times the day 19 part two search, which runs the drone program once for every square it
+//! checks, on the day 11 interpreter and on the intcode crate. The day 11 interpreter formats
+//! every opcode into a string to read its modes, as `run_step` did before instructions were
+//! decoded when the image is loaded.
+//!
+//! Run it with `cargo run --release -p harness --bin bench`.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use harness::engines::DayEleven;
+use harness::Engine;
+use intcode::{parse_program, run_program, Image, ProgramState};
+
+/// Finds the first 100 by 100 square that fits in the beam, the same way day 19 does
+fn search<F: FnMut(i64, i64) -> bool>(mut in_beam: F) -> (i64, usize) {
+    let mut runs = 0;
+    let mut check = |x, y| {
+        runs += 1;
+        in_beam(x, y)
+    };
+    let mut y = 99;
+    loop {
+        let mut x = 0;
+        while !check(x, y) {
+            x += 1;
+        }
+        if check(x + 99, y - 99) {
+            return (x * 10000 + y - 99, runs);
+        }
+        y += 1;
+    }
+}
+
+fn time<F: FnMut(i64, i64) -> bool>(name: &str, in_beam: F) -> (i64, Duration) {
+    let start = Instant::now();
+    let (answer, runs) = search(in_beam);
+    let elapsed = start.elapsed();
+    println!(
+        "{:<14} {:>8.2?}  {} runs, {:.1?} each, answer {}",
+        name,
+        elapsed,
+        runs,
+        elapsed / runs as u32,
+        answer
+    );
+    (answer, elapsed)
+}
+
+fn main() {
+    let program = parse_program(include_str!("../../../19/input.txt")).unwrap();
+
+    let (old, old_time) = time(DayEleven.name(), |x, y| {
+        DayEleven.run(&program, &[x, y]).outputs.contains(&1)
+    });
+
+    let image = Arc::new(Image::new(program.clone()));
+    let (new, new_time) = time("intcode crate", |x, y| {
+        let mut state = ProgramState::from_image(&image, vec![x, y]);
+        let mut in_beam = false;
+        run_program(&mut state, false, |_, output| {
+            in_beam = output == 1;
+            false
+        });
+        in_beam
+    });
+
+    assert_eq!(old, new, "the engines found different squares");
+    println!(
+        "{:.1}x faster",
+        old_time.as_secs_f64() / new_time.as_secs_f64()
+    );
+}
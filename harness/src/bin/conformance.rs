@@ -0,0 +1,18 @@
+use std::panic;
+use std::process;
+
+use harness::{conformance, Engine};
+
+fn main() {
+    // failing engines panic, the report says why
+    panic::set_hook(Box::new(|_| {}));
+
+    let report = conformance::run(&harness::all_engines(), &conformance::cases());
+    print!("{}", report);
+    if report
+        .failures()
+        .any(|failure| failure.engine == harness::engines::Shared.name())
+    {
+        process::exit(1);
+    }
+}
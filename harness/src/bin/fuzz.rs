@@ -0,0 +1,33 @@
+use std::env;
+use std::panic;
+use std::process;
+
+use harness::{fuzz::Fuzzer, Engine};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() > 3 {
+        eprintln!("usage: {} [seed] [programs]", args[0]);
+        process::exit(2);
+    }
+    let number = |i: usize, default: u64| {
+        args.get(i).map_or(default, |arg| {
+            arg.parse().unwrap_or_else(|_| {
+                eprintln!("{:?} is not a number", arg);
+                process::exit(2);
+            })
+        })
+    };
+
+    // diverging engines panic, the report says why
+    panic::set_hook(Box::new(|_| {}));
+
+    let report = Fuzzer::new(number(1, 1))
+        .programs(number(2, 1000) as usize)
+        .run(&harness::all_engines());
+    print!("{}", report);
+    let shared = report.tally(harness::engines::Shared.name()).unwrap();
+    if shared.divergence.is_some() {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,366 @@
+//! Conformance checks built from the puzzles' own self tests and published examples.
+//!
+//! Day 5's TEST program prints a zero for every check that passes and then a diagnostic code, and
+//! day 9's BOOST program in test mode prints the opcodes that misbehaved before its keycode. The
+//! shared crate is the reference for the codes; the other engines have to agree with it.
+
+use std::fmt;
+
+use intcode::testing::{puzzle_examples, Example};
+
+use crate::engines::{run_guarded, Engine, Outputs, Requirements, Run};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Check {
+    Outputs(Vec<i64>),
+    /// Values of memory cells after halting
+    Memory(Vec<(usize, i64)>),
+    /// Day 5's TEST program
+    Diagnostic,
+    /// Day 9's BOOST program in test mode
+    Boost,
+}
+
+#[derive(Clone, Debug)]
+pub struct Case {
+    pub name: String,
+    pub program: Vec<i64>,
+    pub inputs: Vec<i64>,
+    pub check: Check,
+    pub requirements: Requirements,
+}
+
+impl Case {
+    pub fn new(name: &str, program: &str, inputs: Vec<i64>, check: Check) -> Self {
+        let program = intcode::get_base_program(program);
+        let expected = match check {
+            Check::Outputs(ref outputs) => outputs.clone(),
+            Check::Memory(ref cells) => cells.iter().map(|&(_, value)| value).collect(),
+            _ => Vec::new(),
+        };
+        let requirements = Requirements::of(&program, &inputs, &expected);
+
+        Case {
+            name: name.to_string(),
+            program,
+            inputs,
+            check,
+            requirements,
+        }
+    }
+
+    /// A case checking the example's outputs, or its memory if the outputs aren't given
+    pub fn from_example(name: &str, example: &Example) -> Self {
+        let check = match example.outputs {
+            Some(ref outputs) => Check::Outputs(outputs.clone()),
+            None => Check::Memory(example.memory_after.clone()),
+        };
+        Case::new(name, &example.program, example.inputs.clone(), check)
+    }
+}
+
+pub fn cases() -> Vec<Case> {
+    let day_five = include_str!("../../5/input.txt");
+    let day_nine = include_str!("../../9/input.txt");
+
+    let mut cases: Vec<Case> = puzzle_examples()
+        .iter()
+        .map(|(name, example)| Case::from_example(name, example))
+        .collect();
+    cases.push(Case::new(
+        "day 5 TEST, air conditioner",
+        day_five,
+        vec![1],
+        Check::Diagnostic,
+    ));
+    cases.push(Case::new(
+        "day 5 TEST, thermal radiator",
+        day_five,
+        vec![5],
+        Check::Diagnostic,
+    ));
+    cases.push(Case::new("day 9 BOOST", day_nine, vec![1], Check::Boost));
+    cases
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Verdict {
+    Pass(String),
+    Fail(String),
+    Panicked(String),
+    Skipped(String),
+}
+
+impl Verdict {
+    fn label(&self) -> &'static str {
+        match *self {
+            Verdict::Pass(_) => "pass",
+            Verdict::Fail(_) => "FAIL",
+            Verdict::Panicked(_) => "PANIC",
+            Verdict::Skipped(_) => "skip",
+        }
+    }
+
+    fn detail(&self) -> &str {
+        match *self {
+            Verdict::Pass(ref detail)
+            | Verdict::Fail(ref detail)
+            | Verdict::Panicked(ref detail)
+            | Verdict::Skipped(ref detail) => detail,
+        }
+    }
+
+    pub fn is_failure(&self) -> bool {
+        matches!(*self, Verdict::Fail(_) | Verdict::Panicked(_))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CaseResult {
+    pub case: String,
+    pub engine: &'static str,
+    pub verdict: Verdict,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub results: Vec<CaseResult>,
+}
+
+impl Report {
+    pub fn verdict(&self, case: &str, engine: &str) -> Option<&Verdict> {
+        self.results
+            .iter()
+            .find(|result| result.case == case && result.engine == engine)
+            .map(|result| &result.verdict)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &CaseResult> {
+        self.results
+            .iter()
+            .filter(|result| result.verdict.is_failure())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut case = "";
+        for result in &self.results {
+            if result.case != case {
+                case = &result.case;
+                writeln!(f, "{}", case)?;
+            }
+            let line = format!(
+                "    {:<14} {:<5} {}",
+                result.engine,
+                result.verdict.label(),
+                result.verdict.detail()
+            );
+            writeln!(f, "{}", line.trim_end())?;
+        }
+
+        writeln!(f)?;
+        let mut engines: Vec<&str> = Vec::new();
+        for result in &self.results {
+            if !engines.contains(&result.engine) {
+                engines.push(result.engine);
+            }
+        }
+        for engine in engines {
+            let results: Vec<&Verdict> = self
+                .results
+                .iter()
+                .filter(|result| result.engine == engine)
+                .map(|result| &result.verdict)
+                .collect();
+            let count = |label: &str| results.iter().filter(|v| v.label() == label).count();
+            writeln!(
+                f,
+                "{:<14} {} passed, {} failed, {} panicked, {} skipped",
+                engine,
+                count("pass"),
+                count("FAIL"),
+                count("PANIC"),
+                count("skip")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs every case on every engine. The first engine is the reference for diagnostic codes.
+pub fn run(engines: &[Box<dyn Engine>], cases: &[Case]) -> Report {
+    let mut report = Report::default();
+    for case in cases {
+        let mut reference = None;
+        for (i, engine) in engines.iter().enumerate() {
+            let verdict = match case.requirements.unmet(&engine.capabilities()) {
+                Some(reason) => Verdict::Skipped(reason),
+                None => match run_guarded(engine.as_ref(), &case.program, &case.inputs) {
+                    Err(message) => Verdict::Panicked(message),
+                    Ok(run) => {
+                        let outputs = engine.capabilities().outputs;
+                        if i == 0 {
+                            reference = run.outputs.last().cloned();
+                        }
+                        judge(case, &run, outputs, reference)
+                    }
+                },
+            };
+            report.results.push(CaseResult {
+                case: case.name.clone(),
+                engine: engine.name(),
+                verdict,
+            });
+        }
+    }
+    report
+}
+
+fn judge(case: &Case, run: &Run, outputs: Outputs, reference: Option<i64>) -> Verdict {
+    if !run.halted {
+        return Verdict::Fail("did not halt".to_string());
+    }
+
+    match case.check {
+        Check::Memory(ref cells) => {
+            for &(address, value) in cells {
+                match run.memory.get(address) {
+                    Some(found) if *found == value => {}
+                    found => {
+                        return Verdict::Fail(format!(
+                            "cell {} is {:?}, expected {}",
+                            address, found, value
+                        ))
+                    }
+                }
+            }
+            Verdict::Pass(String::new())
+        }
+        Check::Outputs(ref expected) => {
+            let expected = match outputs {
+                Outputs::All => &expected[..],
+                Outputs::Last => &expected[expected.len().saturating_sub(1)..],
+            };
+            if run.outputs == expected {
+                Verdict::Pass(match outputs {
+                    Outputs::All => String::new(),
+                    Outputs::Last => "only the last output is visible".to_string(),
+                })
+            } else {
+                Verdict::Fail(format!("output {:?}, expected {:?}", run.outputs, expected))
+            }
+        }
+        Check::Diagnostic => {
+            let code = match run.outputs.last() {
+                Some(code) => *code,
+                None => return Verdict::Fail("no diagnostic code".to_string()),
+            };
+            let checks = &run.outputs[..run.outputs.len() - 1];
+            if let Some(failed) = checks.iter().position(|value| *value != 0) {
+                return Verdict::Fail(format!(
+                    "check {} of {} was off by {}",
+                    failed + 1,
+                    checks.len(),
+                    checks[failed]
+                ));
+            }
+            agree(code, reference, "diagnostic code", outputs)
+        }
+        Check::Boost => {
+            if run.outputs.len() > 1 {
+                return Verdict::Fail(format!(
+                    "malfunctioning opcodes {:?}",
+                    &run.outputs[..run.outputs.len() - 1]
+                ));
+            }
+            match run.outputs.last() {
+                Some(keycode) => agree(*keycode, reference, "keycode", outputs),
+                None => Verdict::Fail("no keycode".to_string()),
+            }
+        }
+    }
+}
+
+fn agree(code: i64, reference: Option<i64>, what: &str, outputs: Outputs) -> Verdict {
+    if reference.is_some_and(|reference| reference != code) {
+        return Verdict::Fail(format!(
+            "{} {}, the reference gave {}",
+            what,
+            code,
+            reference.unwrap()
+        ));
+    }
+    let mut detail = format!("{} {}", what, code);
+    if outputs == Outputs::Last {
+        detail.push_str(", earlier outputs not visible");
+    }
+    Verdict::Pass(detail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engines::{all_engines, InstructionSet, Shared};
+
+    #[test]
+    fn test_requirements_are_inferred() {
+        let cases = cases();
+        let find = |name: &str| cases.iter().find(|case| case.name == name).unwrap();
+        assert_eq!(
+            find("day 2 example").requirements.instructions,
+            InstructionSet::Basic
+        );
+        assert_eq!(
+            find("day 5 TEST, air conditioner")
+                .requirements
+                .instructions,
+            InstructionSet::Diagnostic
+        );
+        assert_eq!(
+            find("day 9 quine").requirements.instructions,
+            InstructionSet::Complete
+        );
+        assert!(find("day 9 big literal").requirements.wide);
+        assert!(!find("day 5 echo").requirements.wide);
+    }
+
+    #[test]
+    fn test_shared_crate_passes_everything() {
+        let engines: Vec<Box<dyn Engine>> = vec![Box::new(Shared)];
+        let report = run(&engines, &cases());
+        assert_eq!(report.failures().count(), 0, "{}", report);
+    }
+
+    #[test]
+    fn test_known_defects_in_the_day_copies() {
+        let report = run(&all_engines(), &cases());
+
+        // day 5 only looks at the last digit of the opcode, so 99 reads as 9
+        assert_eq!(
+            report.verdict("day 5 echo", "day 5"),
+            Some(&Verdict::Panicked("Invalid opcode 9 at 4".to_string()))
+        );
+        // day 7 always reads outputs in position mode
+        assert!(report
+            .verdict("day 5 compare, below", "day 7")
+            .unwrap()
+            .is_failure());
+        assert_eq!(
+            report.verdict("day 9 quine", "day 7"),
+            Some(&Verdict::Skipped(
+                "needs the Complete instruction set".to_string()
+            ))
+        );
+        for result in &report.results {
+            if result.engine == "day 11" {
+                assert!(
+                    !result.verdict.is_failure(),
+                    "{}: {:?}",
+                    result.case,
+                    result.verdict
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,343 @@
+//! Every intcode interpreter in the workspace behind one trait.
+//!
+//! The copies days 2, 5 and 9 still run are included straight from their crates, so the harness
+//! always runs the code the puzzles ran. They are linted and warned about where they live. Days 7
+//! and 11 run on the shared crate now, and their old interpreters are kept in this crate.
+
+use std::panic::{self, AssertUnwindSafe};
+
+use intcode::decode::{self, Mode, Opcode};
+
+#[allow(dead_code, clippy::all)]
+mod day_eleven;
+#[allow(dead_code, clippy::all)]
+#[path = "../../5/src/intcode.rs"]
+mod day_five;
+#[allow(dead_code, clippy::all)]
+#[path = "../../9/src/intcode.rs"]
+mod day_nine;
+#[allow(dead_code, clippy::all)]
+mod day_seven;
+#[allow(dead_code, clippy::all)]
+#[path = "../../2/src/intcode.rs"]
+mod day_two;
+
+/// Instructions an interpreter knows, each level including the ones before it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum InstructionSet {
+    /// Add, multiply and halt in position mode, from day 2
+    Basic,
+    /// Parameter modes, input, output, jumps and comparisons, from day 5
+    Diagnostic,
+    /// The relative base and memory past the end of the program, from day 9
+    Complete,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outputs {
+    All,
+    /// Only the last output is returned
+    Last,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Capabilities {
+    pub instructions: InstructionSet,
+    /// Values are 64 bit rather than 32
+    pub wide: bool,
+    pub outputs: Outputs,
+    /// Every read gets the same input value
+    pub single_input: bool,
+    /// Values can be negative
+    pub negative: bool,
+}
+
+/// What a program needs from an engine to run it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Requirements {
+    pub instructions: InstructionSet,
+    /// Needs values that don't fit in 32 bits
+    pub wide: bool,
+    /// Needs negative values
+    pub negative: bool,
+    pub inputs: usize,
+}
+
+impl Requirements {
+    /// Infers the requirements of a program from its reachable instructions and every value it
+    /// starts with, is given or is expected to produce
+    pub fn of(program: &[i64], inputs: &[i64], results: &[i64]) -> Self {
+        let instructions = decode::reachable(program)
+            .values()
+            .map(|instruction| {
+                let relative = instruction.opcode == Opcode::AdjustBase
+                    || instruction
+                        .params
+                        .iter()
+                        .any(|param| param.mode == Mode::Relative);
+                let basic = [Opcode::Add, Opcode::Multiply, Opcode::Halt]
+                    .contains(&instruction.opcode)
+                    && instruction
+                        .params
+                        .iter()
+                        .all(|param| param.mode == Mode::Position);
+                if relative {
+                    InstructionSet::Complete
+                } else if basic {
+                    InstructionSet::Basic
+                } else {
+                    InstructionSet::Diagnostic
+                }
+            })
+            .max()
+            .unwrap_or(InstructionSet::Basic);
+        let values = || program.iter().chain(inputs.iter()).chain(results.iter());
+
+        Requirements {
+            instructions,
+            wide: values().any(|value| *value != *value as i32 as i64),
+            negative: values().any(|value| *value < 0),
+            inputs: inputs.len(),
+        }
+    }
+
+    /// Why an engine with these capabilities can't run the program, if it can't
+    pub fn unmet(&self, capabilities: &Capabilities) -> Option<String> {
+        if capabilities.instructions < self.instructions {
+            Some(format!("needs the {:?} instruction set", self.instructions))
+        } else if self.wide && !capabilities.wide {
+            Some("needs 64 bit values".to_string())
+        } else if self.negative && !capabilities.negative {
+            Some("needs negative values".to_string())
+        } else if capabilities.single_input && self.inputs > 1 {
+            Some("needs more than one input".to_string())
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Run {
+    pub outputs: Vec<i64>,
+    pub memory: Vec<i64>,
+    pub halted: bool,
+}
+
+pub trait Engine: Sync {
+    fn name(&self) -> &'static str;
+    fn capabilities(&self) -> Capabilities;
+    /// Runs to completion, reusing the last input once they run out. May panic.
+    fn run(&self, program: &[i64], inputs: &[i64]) -> Run;
+}
+
+/// Runs an engine, turning a panic into its message
+pub fn run_guarded(engine: &dyn Engine, program: &[i64], inputs: &[i64]) -> Result<Run, String> {
+    panic::catch_unwind(AssertUnwindSafe(|| engine.run(program, inputs))).map_err(|payload| {
+        if let Some(message) = payload.downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = payload.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "panicked".to_string()
+        }
+    })
+}
+
+fn narrow(values: &[i64]) -> Vec<i32> {
+    values.iter().map(|value| *value as i32).collect()
+}
+
+fn widen(values: &[i32]) -> Vec<i64> {
+    values.iter().map(|value| *value as i64).collect()
+}
+
+pub struct Shared;
+
+impl Engine for Shared {
+    fn name(&self) -> &'static str {
+        "intcode crate"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            instructions: InstructionSet::Complete,
+            wide: true,
+            outputs: Outputs::All,
+            single_input: false,
+            negative: true,
+        }
+    }
+
+    fn run(&self, program: &[i64], inputs: &[i64]) -> Run {
+        let mut state = intcode::ProgramState::new(program, inputs.to_vec());
+        let mut outputs = Vec::new();
+        intcode::run_program(&mut state, false, |_, output| {
+            outputs.push(output);
+            false
+        });
+        Run {
+            outputs,
+            memory: state.program.to_vec(),
+            halted: state.finished,
+        }
+    }
+}
+
+pub struct DayTwo;
+
+impl Engine for DayTwo {
+    fn name(&self) -> &'static str {
+        "day 2"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            instructions: InstructionSet::Basic,
+            wide: true,
+            outputs: Outputs::All,
+            single_input: false,
+            negative: false,
+        }
+    }
+
+    fn run(&self, program: &[i64], _inputs: &[i64]) -> Run {
+        let base: Vec<usize> = program.iter().map(|value| *value as usize).collect();
+        let mut memory = base.clone();
+        // the noun and verb are patched in, so patch in what is already there
+        day_two::try_intcode(&base, &mut memory, base[1], base[2]);
+        Run {
+            outputs: Vec::new(),
+            memory: memory.iter().map(|value| *value as i64).collect(),
+            halted: true,
+        }
+    }
+}
+
+pub struct DayFive;
+
+impl Engine for DayFive {
+    fn name(&self) -> &'static str {
+        "day 5"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            instructions: InstructionSet::Diagnostic,
+            wide: false,
+            outputs: Outputs::All,
+            single_input: true,
+            negative: true,
+        }
+    }
+
+    fn run(&self, program: &[i64], inputs: &[i64]) -> Run {
+        let mut memory = narrow(program);
+        let mut outputs = Vec::new();
+        let input = inputs.first().cloned().unwrap_or(0) as i32;
+        day_five::run_program(&mut memory, input, |output| outputs.push(output as i64));
+        Run {
+            outputs,
+            memory: widen(&memory),
+            halted: true,
+        }
+    }
+}
+
+pub struct DaySeven;
+
+impl Engine for DaySeven {
+    fn name(&self) -> &'static str {
+        "day 7"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            instructions: InstructionSet::Diagnostic,
+            wide: false,
+            outputs: Outputs::Last,
+            single_input: false,
+            negative: true,
+        }
+    }
+
+    fn run(&self, program: &[i64], inputs: &[i64]) -> Run {
+        let mut state = day_seven::ProgramState::new(&narrow(program), 0);
+        state.inputs = narrow(inputs);
+        let output = day_seven::run_program(&mut state, false);
+        Run {
+            outputs: output.into_iter().map(|value| value as i64).collect(),
+            memory: widen(&state.program),
+            halted: state.finished,
+        }
+    }
+}
+
+pub struct DayNine;
+
+impl Engine for DayNine {
+    fn name(&self) -> &'static str {
+        "day 9"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            instructions: InstructionSet::Complete,
+            wide: true,
+            outputs: Outputs::Last,
+            single_input: false,
+            negative: true,
+        }
+    }
+
+    fn run(&self, program: &[i64], inputs: &[i64]) -> Run {
+        let mut state = day_nine::ProgramState::new(&program.to_vec(), inputs.to_vec());
+        let output = day_nine::run_program(&mut state, false);
+        Run {
+            outputs: output.into_iter().collect(),
+            memory: state.program,
+            halted: state.finished,
+        }
+    }
+}
+
+pub struct DayEleven;
+
+impl Engine for DayEleven {
+    fn name(&self) -> &'static str {
+        "day 11"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            instructions: InstructionSet::Complete,
+            wide: true,
+            outputs: Outputs::All,
+            single_input: false,
+            negative: true,
+        }
+    }
+
+    fn run(&self, program: &[i64], inputs: &[i64]) -> Run {
+        let mut state = day_eleven::ProgramState::new(&program.to_vec(), inputs.to_vec());
+        let mut outputs = Vec::new();
+        day_eleven::run_program(&mut state, false, |_, output| outputs.push(output));
+        Run {
+            outputs,
+            memory: state.program,
+            halted: state.finished,
+        }
+    }
+}
+
+/// Every engine, the shared crate first
+pub fn all_engines() -> Vec<Box<dyn Engine>> {
+    vec![
+        Box::new(Shared),
+        Box::new(DayTwo),
+        Box::new(DayFive),
+        Box::new(DaySeven),
+        Box::new(DayNine),
+        Box::new(DayEleven),
+    ]
+}
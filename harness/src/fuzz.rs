@@ -0,0 +1,441 @@
+//! Differential fuzzing of the engines on random programs.
+//!
+//! Programs only ever jump forwards and only write past their code, so every one of them halts. The
+//! shared crate runs each program first with checked arithmetic under a step budget; programs that
+//! overflow or run out of steps are thrown away. Every engine that can run what is left has to agree
+//! with that run on the outputs it reports, the memory it halts with and whether it halts at all.
+
+use std::fmt;
+
+use intcode::asm::{assemble, Item, Operand, Value};
+use intcode::decode::Opcode;
+use intcode::{try_run_step, ArithmeticMode, ProgramState};
+
+use crate::engines::{run_guarded, Engine, InstructionSet, Outputs, Requirements, Run};
+
+/// Xorshift generator, so a seed gives the same programs everywhere
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng((seed ^ 0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// A number in `0..n`
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// A number in `low..=high`
+    pub fn between(&mut self, low: i64, high: i64) -> i64 {
+        low + self.below((high - low + 1) as usize) as i64
+    }
+}
+
+/// Largest constant or data value in a generated program
+const VALUE_RANGE: i64 = 20;
+/// Largest relative offset, and how far one `arb` moves the base
+const RELATIVE_RANGE: i64 = 3;
+
+struct Layout {
+    /// Address of each instruction, then of the final halt
+    starts: Vec<usize>,
+    data_start: usize,
+    len: usize,
+}
+
+impl Layout {
+    fn read(&self, rng: &mut Rng, set: InstructionSet) -> Operand {
+        let choices = match set {
+            InstructionSet::Basic => 1,
+            InstructionSet::Diagnostic => 2,
+            InstructionSet::Complete => 3,
+        };
+        match rng.below(choices) {
+            0 => Operand::position(rng.below(self.len) as i64),
+            1 => Operand::immediate(rng.between(-VALUE_RANGE, VALUE_RANGE)),
+            _ => Operand::relative(rng.between(0, RELATIVE_RANGE)),
+        }
+    }
+
+    /// Writes never land in the code, the base never drops below the data
+    fn write(&self, rng: &mut Rng, set: InstructionSet) -> Operand {
+        if set == InstructionSet::Complete && rng.below(2) == 0 {
+            Operand::relative(rng.between(0, RELATIVE_RANGE))
+        } else {
+            Operand::position(rng.between(self.data_start as i64, self.len as i64 - 1))
+        }
+    }
+
+    /// Some instruction after `index`, or the halt
+    fn target(&self, rng: &mut Rng, index: usize) -> Operand {
+        let later = &self.starts[index + 1..];
+        Operand::immediate(later[rng.below(later.len())] as i64)
+    }
+}
+
+/// A random program using at most `set`, and its inputs
+pub fn generate(rng: &mut Rng, set: InstructionSet) -> (Vec<i64>, Vec<i64>) {
+    let opcodes: &[Opcode] = match set {
+        InstructionSet::Basic => &[Opcode::Add, Opcode::Add, Opcode::Multiply],
+        InstructionSet::Diagnostic => &[
+            Opcode::Add,
+            Opcode::Multiply,
+            Opcode::Input,
+            Opcode::Output,
+            Opcode::JumpIfTrue,
+            Opcode::JumpIfFalse,
+            Opcode::LessThan,
+            Opcode::Equals,
+        ],
+        InstructionSet::Complete => &[
+            Opcode::Add,
+            Opcode::Multiply,
+            Opcode::Input,
+            Opcode::Output,
+            Opcode::JumpIfTrue,
+            Opcode::JumpIfFalse,
+            Opcode::LessThan,
+            Opcode::Equals,
+            Opcode::AdjustBase,
+        ],
+    };
+    let count = 2 + rng.below(14);
+    let mut chosen: Vec<Opcode> = (0..count)
+        .map(|_| opcodes[rng.below(opcodes.len())])
+        .collect();
+    let moves = chosen
+        .iter()
+        .filter(|opcode| **opcode == Opcode::AdjustBase)
+        .count() as i64;
+    if set == InstructionSet::Complete {
+        chosen.insert(0, Opcode::AdjustBase);
+    }
+    chosen.push(Opcode::Halt);
+
+    let mut starts = Vec::new();
+    let mut address = 0;
+    for opcode in &chosen {
+        starts.push(address);
+        address += opcode.arity() + 1;
+    }
+    let data = 4 + rng.below(8);
+    let layout = Layout {
+        starts,
+        data_start: address,
+        len: address + data,
+    };
+
+    let low = if set == InstructionSet::Basic {
+        0
+    } else {
+        -VALUE_RANGE
+    };
+    let mut items = Vec::new();
+    for (index, opcode) in chosen.iter().enumerate() {
+        let operands = match *opcode {
+            Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals => vec![
+                layout.read(rng, set),
+                layout.read(rng, set),
+                layout.write(rng, set),
+            ],
+            Opcode::Input => vec![layout.write(rng, set)],
+            Opcode::Output => vec![layout.read(rng, set)],
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                vec![layout.read(rng, set), layout.target(rng, index)]
+            }
+            // the first one puts the base far enough into the data that the rest can't leave it
+            Opcode::AdjustBase if index == 0 => vec![Operand::immediate(
+                layout.data_start as i64 + moves * RELATIVE_RANGE,
+            )],
+            Opcode::AdjustBase => vec![Operand::immediate(
+                rng.between(-RELATIVE_RANGE, RELATIVE_RANGE),
+            )],
+            Opcode::Halt => vec![],
+        };
+        items.push(Item::Instruction(*opcode, operands));
+    }
+    items.push(Item::Data(
+        (0..data)
+            .map(|_| Value::Number(rng.between(low, VALUE_RANGE)))
+            .collect(),
+    ));
+
+    let inputs = if set == InstructionSet::Basic {
+        Vec::new()
+    } else {
+        (0..1 + rng.below(3))
+            .map(|_| rng.between(-VALUE_RANGE, VALUE_RANGE))
+            .collect()
+    };
+    (assemble(&items).expect("generated bad assembly"), inputs)
+}
+
+/// Runs the shared crate with checked arithmetic, `None` if it faults or runs out of steps
+pub fn reference(program: &[i64], inputs: &[i64], step_budget: u64) -> Option<Run> {
+    let mut state = ProgramState::new(program, inputs.to_vec());
+    state.arithmetic = ArithmeticMode::Checked;
+    let mut outputs = Vec::new();
+    for _ in 0..step_budget {
+        if state.finished {
+            break;
+        }
+        if let (Some(output), _) = try_run_step(&mut state, false).ok()? {
+            outputs.push(output);
+        }
+    }
+    if !state.finished {
+        return None;
+    }
+
+    Some(Run {
+        outputs,
+        memory: state.program.to_vec(),
+        halted: true,
+    })
+}
+
+/// How `run` differs from the reference, if it does
+pub fn compare(reference: &Run, run: &Run, outputs: Outputs) -> Option<String> {
+    if run.halted != reference.halted {
+        return Some(format!(
+            "halted is {}, the reference gave {}",
+            run.halted, reference.halted
+        ));
+    }
+
+    let expected = match outputs {
+        Outputs::All => &reference.outputs[..],
+        Outputs::Last => &reference.outputs[reference.outputs.len().saturating_sub(1)..],
+    };
+    if run.outputs != expected {
+        return Some(format!(
+            "output {:?}, the reference gave {:?}",
+            run.outputs, expected
+        ));
+    }
+
+    // memory that was never touched reads as zero, however much of it an engine allocated
+    let len = run.memory.len().max(reference.memory.len());
+    let cell = |memory: &[i64], address: usize| memory.get(address).cloned().unwrap_or(0);
+    (0..len)
+        .find(|address| cell(&run.memory, *address) != cell(&reference.memory, *address))
+        .map(|address| {
+            format!(
+                "cell {} is {}, the reference gave {}",
+                address,
+                cell(&run.memory, address),
+                cell(&reference.memory, address)
+            )
+        })
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    pub program: Vec<i64>,
+    pub inputs: Vec<i64>,
+    pub detail: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Tally {
+    pub engine: &'static str,
+    /// Programs the engine ran and agreed on
+    pub agreed: usize,
+    /// Programs the engine can't run
+    pub skipped: usize,
+    /// The first program it disagreed on; it isn't run after that
+    pub divergence: Option<Divergence>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub programs: usize,
+    /// Programs that overflowed or ran out of steps in the reference
+    pub discarded: usize,
+    pub tallies: Vec<Tally>,
+}
+
+impl Report {
+    pub fn tally(&self, engine: &str) -> Option<&Tally> {
+        self.tallies.iter().find(|tally| tally.engine == engine)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} programs, {} discarded",
+            self.programs, self.discarded
+        )?;
+        for tally in &self.tallies {
+            writeln!(
+                f,
+                "{:<14} agreed on {}, skipped {}",
+                tally.engine, tally.agreed, tally.skipped
+            )?;
+            if let Some(ref divergence) = tally.divergence {
+                let words: Vec<String> = divergence.program.iter().map(i64::to_string).collect();
+                writeln!(f, "    diverged: {}", divergence.detail)?;
+                writeln!(f, "    program:  {}", words.join(","))?;
+                writeln!(f, "    inputs:   {:?}", divergence.inputs)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Fuzzer {
+    seed: u64,
+    programs: usize,
+    step_budget: u64,
+}
+
+impl Fuzzer {
+    pub fn new(seed: u64) -> Self {
+        Fuzzer {
+            seed,
+            programs: 1000,
+            step_budget: 10_000,
+        }
+    }
+
+    pub fn programs(mut self, programs: usize) -> Self {
+        self.programs = programs;
+        self
+    }
+
+    pub fn step_budget(mut self, step_budget: u64) -> Self {
+        self.step_budget = step_budget;
+        self
+    }
+
+    /// Generates programs for each instruction set in turn and runs them on every engine
+    pub fn run(&self, engines: &[Box<dyn Engine>]) -> Report {
+        let sets = [
+            InstructionSet::Basic,
+            InstructionSet::Diagnostic,
+            InstructionSet::Complete,
+        ];
+        let mut rng = Rng::new(self.seed);
+        let mut report = Report {
+            programs: self.programs,
+            discarded: 0,
+            tallies: engines
+                .iter()
+                .map(|engine| Tally {
+                    engine: engine.name(),
+                    agreed: 0,
+                    skipped: 0,
+                    divergence: None,
+                })
+                .collect(),
+        };
+
+        for i in 0..self.programs {
+            let (program, inputs) = generate(&mut rng, sets[i % sets.len()]);
+            let expected = match reference(&program, &inputs, self.step_budget) {
+                Some(expected) => expected,
+                None => {
+                    report.discarded += 1;
+                    continue;
+                }
+            };
+            let results: Vec<i64> = expected
+                .memory
+                .iter()
+                .chain(expected.outputs.iter())
+                .cloned()
+                .collect();
+            let requirements = Requirements::of(&program, &inputs, &results);
+
+            for (engine, tally) in engines.iter().zip(report.tallies.iter_mut()) {
+                if tally.divergence.is_some() {
+                    continue;
+                }
+                let capabilities = engine.capabilities();
+                if requirements.unmet(&capabilities).is_some() {
+                    tally.skipped += 1;
+                    continue;
+                }
+                let detail = match run_guarded(engine.as_ref(), &program, &inputs) {
+                    Err(message) => Some(format!("panicked: {}", message)),
+                    Ok(run) => compare(&expected, &run, capabilities.outputs),
+                };
+                match detail {
+                    Some(detail) => {
+                        tally.divergence = Some(Divergence {
+                            program: program.clone(),
+                            inputs: inputs.clone(),
+                            detail,
+                        })
+                    }
+                    None => tally.agreed += 1,
+                }
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engines::all_engines;
+
+    #[test]
+    fn test_generated_programs_halt() {
+        let mut rng = Rng::new(7);
+        for set in [
+            InstructionSet::Basic,
+            InstructionSet::Diagnostic,
+            InstructionSet::Complete,
+        ] {
+            for _ in 0..200 {
+                let (program, inputs) = generate(&mut rng, set);
+                let mut state = ProgramState::new(&program, inputs.clone());
+                for _ in 0..program.len() {
+                    if state.finished {
+                        break;
+                    }
+                    try_run_step(&mut state, false).unwrap();
+                }
+                assert!(state.finished, "{:?} {:?}", program, inputs);
+                assert!(Requirements::of(&program, &inputs, &[]).instructions <= set);
+            }
+        }
+
+        let first = generate(&mut Rng::new(3), InstructionSet::Complete);
+        assert_eq!(generate(&mut Rng::new(3), InstructionSet::Complete), first);
+    }
+
+    #[test]
+    fn test_finds_the_known_defects() {
+        let report = Fuzzer::new(1).programs(600).run(&all_engines());
+
+        for engine in &["intcode crate", "day 2", "day 9", "day 11"] {
+            let tally = report.tally(engine).unwrap();
+            assert!(tally.divergence.is_none(), "{}", report);
+            assert!(tally.agreed > 100, "{}", report);
+        }
+        // day 5 reads 99 as 9, day 7 reads every output in position mode
+        let day_five = report.tally("day 5").unwrap();
+        assert!(day_five
+            .divergence
+            .as_ref()
+            .unwrap()
+            .detail
+            .starts_with("panicked: Invalid opcode 9 at"));
+        assert!(report.tally("day 7").unwrap().divergence.is_some());
+    }
+}
@@ -0,0 +1,7 @@
+//! Runs the intcode interpreters in the workspace against each other.
+
+pub mod conformance;
+pub mod engines;
+pub mod fuzz;
+
+pub use engines::{all_engines, Capabilities, Engine, InstructionSet, Outputs, Requirements, Run};
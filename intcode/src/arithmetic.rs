@@ -0,0 +1,65 @@
+use crate::fault::Fault;
+
+/// How opcodes 1 and 2, relative addresses and opcode 9 treat results that do not fit in an i64.
+///
+/// Memory cells are i64, so a wider result has nowhere to go here. With the `bigint` feature,
+/// `big::BigState` runs programs on arbitrary-precision cells instead.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ArithmeticMode {
+    /// Two's complement wrap around, the same in debug and release builds.
+    #[default]
+    Wrapping,
+    /// Stop the program with a `Fault::Overflow`.
+    Checked,
+}
+
+impl ArithmeticMode {
+    pub fn add(self, ip: usize, lhs: i64, rhs: i64) -> Result<i64, Fault> {
+        match self {
+            ArithmeticMode::Wrapping => Ok(lhs.wrapping_add(rhs)),
+            ArithmeticMode::Checked => lhs.checked_add(rhs).ok_or(Fault::Overflow {
+                ip,
+                opcode: 1,
+                lhs,
+                rhs,
+            }),
+        }
+    }
+
+    pub fn mul(self, ip: usize, lhs: i64, rhs: i64) -> Result<i64, Fault> {
+        match self {
+            ArithmeticMode::Wrapping => Ok(lhs.wrapping_mul(rhs)),
+            ArithmeticMode::Checked => lhs.checked_mul(rhs).ok_or(Fault::Overflow {
+                ip,
+                opcode: 2,
+                lhs,
+                rhs,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrapping_matches_release_behaviour() {
+        assert_eq!(ArithmeticMode::Wrapping.add(0, i64::MAX, 1), Ok(i64::MIN));
+        assert_eq!(ArithmeticMode::Wrapping.mul(0, i64::MAX, 2), Ok(-2));
+    }
+
+    #[test]
+    fn test_checked_reports_operands() {
+        assert_eq!(
+            ArithmeticMode::Checked.mul(12, i64::MAX, 2),
+            Err(Fault::Overflow {
+                ip: 12,
+                opcode: 2,
+                lhs: i64::MAX,
+                rhs: 2,
+            })
+        );
+        assert_eq!(ArithmeticMode::Checked.add(0, 2, 3), Ok(5));
+    }
+}
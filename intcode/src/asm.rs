@@ -0,0 +1,419 @@
+//! Assembler with labels.
+//!
+//! The text syntax is the one the disassembler prints, `add #11, [x], [rb+2]`, plus `name:` labels,
+//! `data` directives and `;` comments. Labels can be used anywhere a number can, so `#loop` is the
+//! address of `loop` and `[x]` is the cell there.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::decode::{Mode, Opcode};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Value {
+    Number(i64),
+    /// Address of a label plus an offset
+    Label(String, i64),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Number(number) => write!(f, "{}", number),
+            Value::Label(ref name, 0) => write!(f, "{}", name),
+            Value::Label(ref name, offset) => write!(f, "{}{:+}", name, offset),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Operand {
+    pub mode: Mode,
+    pub value: Value,
+}
+
+impl Operand {
+    pub fn immediate(value: i64) -> Self {
+        Operand {
+            mode: Mode::Immediate,
+            value: Value::Number(value),
+        }
+    }
+
+    pub fn position(address: i64) -> Self {
+        Operand {
+            mode: Mode::Position,
+            value: Value::Number(address),
+        }
+    }
+
+    pub fn relative(offset: i64) -> Self {
+        Operand {
+            mode: Mode::Relative,
+            value: Value::Number(offset),
+        }
+    }
+
+    /// The address of a label, for jump targets
+    pub fn label(name: &str) -> Self {
+        Operand {
+            mode: Mode::Immediate,
+            value: Value::Label(name.to_string(), 0),
+        }
+    }
+
+    /// The cell at a label
+    pub fn at(name: &str) -> Self {
+        Operand {
+            mode: Mode::Position,
+            value: Value::Label(name.to_string(), 0),
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.mode, &self.value) {
+            (Mode::Immediate, value) => write!(f, "#{}", value),
+            (Mode::Position, value) => write!(f, "[{}]", value),
+            (Mode::Relative, &Value::Number(offset)) => write!(f, "[rb{:+}]", offset),
+            (Mode::Relative, value) => write!(f, "[rb+{}]", value),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Item {
+    Label(String),
+    Instruction(Opcode, Vec<Operand>),
+    Data(Vec<Value>),
+}
+
+impl Item {
+    /// Number of words this item assembles to
+    pub fn len(&self) -> usize {
+        match *self {
+            Item::Label(_) => 0,
+            Item::Instruction(_, ref operands) => operands.len() + 1,
+            Item::Data(ref values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Item::Label(ref name) => write!(f, "{}:", name),
+            Item::Instruction(opcode, ref operands) => {
+                write!(f, "    {}", opcode.mnemonic())?;
+                for (i, operand) in operands.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { " " } else { ", " }, operand)?;
+                }
+                Ok(())
+            }
+            Item::Data(ref values) => {
+                write!(f, "    data")?;
+                for (i, value) in values.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { " " } else { ", " }, value)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AsmError {
+    Syntax {
+        line: usize,
+        message: String,
+    },
+    DuplicateLabel(String),
+    UndefinedLabel(String),
+    OperandCount {
+        opcode: Opcode,
+        expected: usize,
+        found: usize,
+    },
+    ImmediateWrite(Opcode),
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AsmError::Syntax { line, ref message } => write!(f, "line {}: {}", line, message),
+            AsmError::DuplicateLabel(ref name) => write!(f, "label {} is defined twice", name),
+            AsmError::UndefinedLabel(ref name) => write!(f, "label {} is not defined", name),
+            AsmError::OperandCount {
+                opcode,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} takes {} operands, {} given",
+                opcode.mnemonic(),
+                expected,
+                found
+            ),
+            AsmError::ImmediateWrite(opcode) => {
+                write!(
+                    f,
+                    "{} can't write to an immediate operand",
+                    opcode.mnemonic()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// Address of every label in `items`
+pub fn label_addresses(items: &[Item]) -> Result<HashMap<String, usize>, AsmError> {
+    let mut labels = HashMap::new();
+    let mut address = 0;
+    for item in items {
+        if let Item::Label(ref name) = *item {
+            if labels.insert(name.clone(), address).is_some() {
+                return Err(AsmError::DuplicateLabel(name.clone()));
+            }
+        }
+        address += item.len();
+    }
+    Ok(labels)
+}
+
+pub fn assemble(items: &[Item]) -> Result<Vec<i64>, AsmError> {
+    let labels = label_addresses(items)?;
+    let resolve = |value: &Value| match *value {
+        Value::Number(number) => Ok(number),
+        Value::Label(ref name, offset) => labels
+            .get(name)
+            .map(|address| *address as i64 + offset)
+            .ok_or_else(|| AsmError::UndefinedLabel(name.clone())),
+    };
+
+    let mut words = Vec::new();
+    for item in items {
+        match *item {
+            Item::Label(_) => {}
+            Item::Instruction(opcode, ref operands) => {
+                if operands.len() != opcode.arity() {
+                    return Err(AsmError::OperandCount {
+                        opcode,
+                        expected: opcode.arity(),
+                        found: operands.len(),
+                    });
+                }
+                if let Some(write) = opcode.write_param() {
+                    if operands[write].mode == Mode::Immediate {
+                        return Err(AsmError::ImmediateWrite(opcode));
+                    }
+                }
+                let modes = operands
+                    .iter()
+                    .rev()
+                    .fold(0, |modes, operand| modes * 10 + operand.mode.digit());
+                words.push(modes * 100 + opcode.number());
+                for operand in operands {
+                    words.push(resolve(&operand.value)?);
+                }
+            }
+            Item::Data(ref values) => {
+                for value in values {
+                    words.push(resolve(value)?);
+                }
+            }
+        }
+    }
+
+    Ok(words)
+}
+
+/// Parses assembly text into items
+pub fn parse(text: &str) -> Result<Vec<Item>, AsmError> {
+    let mut items = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let error = |message: String| AsmError::Syntax {
+            line: number + 1,
+            message,
+        };
+        let mut line = line.split(';').next().unwrap().trim();
+
+        if let Some(colon) = line.find(':') {
+            let name = line[..colon].trim();
+            if !is_identifier(name) {
+                return Err(error(format!("bad label {:?}", name)));
+            }
+            items.push(Item::Label(name.to_string()));
+            line = line[colon + 1..].trim();
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, rest) = match line.find(char::is_whitespace) {
+            Some(space) => (&line[..space], line[space..].trim()),
+            None => (line, ""),
+        };
+        let fields: Vec<&str> = if rest.is_empty() {
+            Vec::new()
+        } else {
+            rest.split(',').map(str::trim).collect()
+        };
+
+        if mnemonic == "data" {
+            let values = fields
+                .iter()
+                .map(|field| {
+                    parse_value(field).ok_or_else(|| error(format!("bad value {:?}", field)))
+                })
+                .collect::<Result<_, _>>()?;
+            items.push(Item::Data(values));
+            continue;
+        }
+
+        let opcode = Opcode::from_mnemonic(mnemonic)
+            .ok_or_else(|| error(format!("unknown instruction {:?}", mnemonic)))?;
+        let operands = fields
+            .iter()
+            .map(|field| {
+                parse_operand(field).ok_or_else(|| error(format!("bad operand {:?}", field)))
+            })
+            .collect::<Result<_, _>>()?;
+        items.push(Item::Instruction(opcode, operands));
+    }
+
+    Ok(items)
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' || first == '.' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn parse_value(text: &str) -> Option<Value> {
+    if let Ok(number) = text.parse() {
+        return Some(Value::Number(number));
+    }
+    let split = text.rfind(['+', '-']).filter(|split| *split > 0);
+    let (name, offset) = match split {
+        Some(split) => (
+            text[..split].trim(),
+            text[split..].replace(' ', "").parse().ok()?,
+        ),
+        None => (text, 0),
+    };
+    if is_identifier(name) {
+        Some(Value::Label(name.to_string(), offset))
+    } else {
+        None
+    }
+}
+
+fn parse_operand(text: &str) -> Option<Operand> {
+    if let Some(value) = text.strip_prefix('#') {
+        return Some(Operand {
+            mode: Mode::Immediate,
+            value: parse_value(value.trim())?,
+        });
+    }
+    let inner = text.strip_prefix('[')?.strip_suffix(']')?.trim();
+    if inner == "rb" {
+        return Some(Operand::relative(0));
+    }
+    if let Some(offset) = inner.strip_prefix("rb") {
+        let offset = offset.trim();
+        let value = match offset.strip_prefix('+') {
+            Some(positive) => parse_value(positive.trim())?,
+            None => Value::Number(offset.replace(' ', "").parse().ok()?),
+        };
+        return Some(Operand {
+            mode: Mode::Relative,
+            value,
+        });
+    }
+    Some(Operand {
+        mode: Mode::Position,
+        value: parse_value(inner)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run_program, ProgramState};
+
+    #[test]
+    fn test_assembles_labels_and_modes() {
+        let items = parse(
+            "
+            ; count down from the input, printing each number
+                in [n]
+            loop:
+                out [n]
+                add [n], #-1, [n]
+                jnz [n], #loop
+                hlt
+            n:  data 0
+            ",
+        )
+        .unwrap();
+        let program = assemble(&items).unwrap();
+        assert_eq!(
+            program,
+            vec![3, 12, 4, 12, 1001, 12, -1, 12, 1005, 12, 2, 99, 0]
+        );
+
+        let mut state = ProgramState::new(&program, vec![3]);
+        let mut outputs = Vec::new();
+        run_program(&mut state, false, |_, output| {
+            outputs.push(output);
+            false
+        });
+        assert_eq!(outputs, vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn test_round_trips_through_display() {
+        let text = "start:\n    add #start+4, [rb-2], [rb+3]\n    jz [x], #start\n    hlt\nx:\n    data 7, start";
+        let items = parse(text).unwrap();
+        let printed: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+        assert_eq!(printed.join("\n"), text);
+    }
+
+    #[test]
+    fn test_reports_errors() {
+        assert_eq!(
+            parse("  add #1, #2\n  jmp #0"),
+            Err(AsmError::Syntax {
+                line: 2,
+                message: "unknown instruction \"jmp\"".to_string()
+            })
+        );
+        assert_eq!(
+            assemble(&parse("add #1, #2").unwrap()),
+            Err(AsmError::OperandCount {
+                opcode: Opcode::Add,
+                expected: 3,
+                found: 2
+            })
+        );
+        assert_eq!(
+            assemble(&parse("in #4").unwrap()),
+            Err(AsmError::ImmediateWrite(Opcode::Input))
+        );
+        assert_eq!(
+            assemble(&parse("jz #0, #nowhere").unwrap()),
+            Err(AsmError::UndefinedLabel("nowhere".to_string()))
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Running one program many times over on different inputs.
+//!
+//! `run_all` starts a fresh run of the same image for each list of inputs and collects what each
+//! run printed, spreading the runs over a few threads. Each thread keeps one machine and resets
+//! it between runs, so the memory a run writes is only allocated once per thread rather than once
+//! per run. A run ends when the program halts or wants more input than it was given.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use crate::{try_run_step, Fault, Image, ProgramState};
+
+/// Runs `image` once for each list of inputs and returns the outputs of each run, in the order
+/// the inputs came in. Panics if any run faults.
+pub fn run_all<I>(image: &Arc<Image>, inputs: I, threads: usize) -> Vec<Vec<i64>>
+where
+    I: IntoIterator<Item = Vec<i64>>,
+{
+    try_run_all(image, inputs, threads)
+        .into_iter()
+        .enumerate()
+        .map(|(run, result)| result.unwrap_or_else(|fault| panic!("run {}: {}", run, fault)))
+        .collect()
+}
+
+pub fn try_run_all<I>(image: &Arc<Image>, inputs: I, threads: usize) -> Vec<Result<Vec<i64>, Fault>>
+where
+    I: IntoIterator<Item = Vec<i64>>,
+{
+    let inputs: Vec<Vec<i64>> = inputs.into_iter().collect();
+    let threads = threads.max(1).min(inputs.len().max(1));
+    let next = AtomicUsize::new(0);
+
+    // each thread takes the next run nobody has started, so slow runs don't hold up the rest
+    let work = || {
+        let mut scratch = ProgramState::from_image(image, Vec::new());
+        let mut results = Vec::new();
+        loop {
+            let run = next.fetch_add(1, Ordering::Relaxed);
+            let inputs = match inputs.get(run) {
+                Some(inputs) => inputs,
+                None => return results,
+            };
+            results.push((run, run_once(&mut scratch, inputs)));
+        }
+    };
+
+    let parts = if threads == 1 {
+        vec![work()]
+    } else {
+        thread::scope(|scope| {
+            let handles: Vec<_> = (0..threads).map(|_| scope.spawn(work)).collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect()
+        })
+    };
+
+    let mut results: Vec<(usize, Result<Vec<i64>, Fault>)> = parts.into_iter().flatten().collect();
+    results.sort_by_key(|(run, _)| *run);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+// puts the machine back to the start of the image with new inputs, keeping its allocations
+fn restart(state: &mut ProgramState, inputs: &[i64]) {
+    state.program.reset();
+    state.index = 0;
+    state.relative_base = 0;
+    state.finished = false;
+    state.inputs.clear();
+    for input in inputs {
+        state.inputs.push(*input);
+    }
+}
+
+fn run_once(state: &mut ProgramState, inputs: &[i64]) -> Result<Vec<i64>, Fault> {
+    restart(state, inputs);
+    let mut outputs = Vec::new();
+    loop {
+        match try_run_step(state, true)? {
+            (Some(output), _) => outputs.push(output),
+            (None, true) => return Ok(outputs),
+            (None, false) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outputs_come_back_in_input_order() {
+        // prints its input times 3
+        let image = Arc::new(Image::new(vec![3, 9, 1002, 9, 3, 9, 4, 9, 99, 0]));
+        let inputs: Vec<Vec<i64>> = (0..200).map(|n| vec![n]).collect();
+
+        let expected: Vec<Vec<i64>> = (0..200).map(|n| vec![n * 3]).collect();
+        assert_eq!(run_all(&image, inputs.clone(), 1), expected);
+        assert_eq!(run_all(&image, inputs, 4), expected);
+        assert!(run_all(&image, Vec::new(), 4).is_empty());
+    }
+
+    #[test]
+    fn test_runs_start_from_a_clean_machine() {
+        // adds its input to [9], prints it, and halts; [9] starts at 10
+        let image = Arc::new(Image::new(vec![3, 10, 1, 9, 10, 9, 4, 9, 99, 10, 0]));
+        assert_eq!(
+            run_all(&image, vec![vec![1], vec![2], vec![], vec![3]], 1),
+            vec![vec![11], vec![12], vec![], vec![13]]
+        );
+    }
+
+    #[test]
+    fn test_faults_are_kept_with_their_run() {
+        // runs whatever it reads as its next instruction
+        let image = Arc::new(Image::new(vec![3, 2, 0]));
+        let results = try_run_all(&image, vec![vec![99], vec![77], vec![99]], 2);
+        assert_eq!(
+            results,
+            vec![
+                Ok(vec![]),
+                Err(Fault::InvalidOpcode { ip: 2, opcode: 77 }),
+                Ok(vec![])
+            ]
+        );
+    }
+}
@@ -0,0 +1,502 @@
+//! Running programs with arbitrary-precision cells, behind the `bigint` feature.
+//!
+//! `ProgramState` keeps its memory in i64 cells, so its `ArithmeticMode` can only choose between
+//! wrapping and stopping when a result doesn't fit. `BigState` is a separate, plain machine whose
+//! cells are `BigInt`s: opcodes 1 and 2 never overflow, and every value the program stores, reads
+//! back or outputs keeps its full width. Addresses still have to be between 0 and `MAX_ADDRESS`;
+//! writing or jumping anywhere else stops the machine with `Fault::InvalidAddress`. It has none of
+//! the debugging features of `ProgramState`.
+
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::{Add, Mul, Neg};
+use std::str::FromStr;
+
+use crate::decode::{self, Mode, Opcode};
+use crate::{Fault, MAX_ADDRESS};
+
+/// A signed integer of any size
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    negative: bool,
+    // least significant first, with no high zero limbs, so zero is empty and never negative
+    limbs: Vec<u32>,
+}
+
+impl BigInt {
+    pub fn zero() -> Self {
+        BigInt::default()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// The value if it fits in an i64
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.limbs.len() > 2 {
+            return None;
+        }
+        let magnitude = self
+            .limbs
+            .iter()
+            .rev()
+            .fold(0u64, |value, limb| value << 32 | *limb as u64);
+        if self.negative {
+            0i64.checked_sub_unsigned(magnitude)
+        } else {
+            Some(magnitude as i64).filter(|value| *value >= 0)
+        }
+    }
+
+    /// The closest i64, for reporting a value that may not fit
+    fn saturating_i64(&self) -> i64 {
+        self.to_i64()
+            .unwrap_or(if self.negative { i64::MIN } else { i64::MAX })
+    }
+
+    fn from_limbs(negative: bool, mut limbs: Vec<u32>) -> Self {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        BigInt {
+            negative: negative && !limbs.is_empty(),
+            limbs,
+        }
+    }
+
+    /// Divides the magnitude in place, returning the remainder
+    fn divide_magnitude(&mut self, divisor: u32) -> u32 {
+        let mut remainder = 0u64;
+        for limb in self.limbs.iter_mut().rev() {
+            let value = remainder << 32 | *limb as u64;
+            *limb = (value / divisor as u64) as u32;
+            remainder = value % divisor as u64;
+        }
+        *self = BigInt::from_limbs(self.negative, std::mem::take(&mut self.limbs));
+        remainder as u32
+    }
+}
+
+fn compare_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut sum = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0u64;
+    for i in 0..a.len().max(b.len()) {
+        let value = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        sum.push(value as u32);
+        carry = value >> 32;
+    }
+    sum.push(carry as u32);
+    sum
+}
+
+// `a` must be at least as large as `b`
+fn subtract_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut difference = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, limb) in a.iter().enumerate() {
+        let mut value = *limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if value < 0 {
+            value += 1 << 32;
+            borrow = 1;
+        }
+        difference.push(value as u32);
+    }
+    difference
+}
+
+impl From<i64> for BigInt {
+    fn from(value: i64) -> Self {
+        let magnitude = value.unsigned_abs();
+        BigInt::from_limbs(value < 0, vec![magnitude as u32, (magnitude >> 32) as u32])
+    }
+}
+
+impl Neg for BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::from_limbs(!self.negative, self.limbs)
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::from_limbs(self.negative, add_magnitudes(&self.limbs, &other.limbs));
+        }
+        match compare_magnitudes(&self.limbs, &other.limbs) {
+            Ordering::Less => BigInt::from_limbs(
+                other.negative,
+                subtract_magnitudes(&other.limbs, &self.limbs),
+            ),
+            _ => BigInt::from_limbs(
+                self.negative,
+                subtract_magnitudes(&self.limbs, &other.limbs),
+            ),
+        }
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        let mut product = vec![0u32; self.limbs.len() + other.limbs.len()];
+        for (i, a) in self.limbs.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, b) in other.limbs.iter().enumerate() {
+                let value = product[i + j] as u64 + *a as u64 * *b as u64 + carry;
+                product[i + j] = value as u32;
+                carry = value >> 32;
+            }
+            product[i + other.limbs.len()] = carry as u32;
+        }
+        BigInt::from_limbs(self.negative != other.negative, product)
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare_magnitudes(&self.limbs, &other.limbs),
+            (true, true) => compare_magnitudes(&other.limbs, &self.limbs),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const CHUNK: u32 = 1_000_000_000;
+        let mut rest = self.clone();
+        let mut chunks = Vec::new();
+        loop {
+            chunks.push(rest.divide_magnitude(CHUNK));
+            if rest.is_zero() {
+                break;
+            }
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", chunks.pop().unwrap())?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseBigIntError;
+
+impl fmt::Display for ParseBigIntError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "not a decimal integer")
+    }
+}
+
+impl FromStr for BigInt {
+    type Err = ParseBigIntError;
+
+    fn from_str(text: &str) -> Result<Self, ParseBigIntError> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text),
+        };
+        if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(ParseBigIntError);
+        }
+        let ten = BigInt::from(10);
+        let value = digits.bytes().fold(BigInt::zero(), |value, digit| {
+            &(&value * &ten) + &BigInt::from((digit - b'0') as i64)
+        });
+        Ok(if negative { -value } else { value })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BigState {
+    pub program: Vec<BigInt>,
+    pub index: usize,
+    pub inputs: VecDeque<BigInt>,
+    /// Handed out again once `inputs` runs dry, unless input use is limited
+    pub last_input: Option<BigInt>,
+    pub finished: bool,
+    pub relative_base: BigInt,
+}
+
+impl BigState {
+    pub fn new(base_program: &[i64], inputs: Vec<BigInt>) -> Self {
+        BigState {
+            program: base_program
+                .iter()
+                .map(|word| BigInt::from(*word))
+                .collect(),
+            index: 0,
+            inputs: inputs.into(),
+            last_input: None,
+            finished: false,
+            relative_base: BigInt::zero(),
+        }
+    }
+
+    /// The value at `address`, zero anywhere never written
+    pub fn read(&self, address: usize) -> BigInt {
+        self.program.get(address).cloned().unwrap_or_default()
+    }
+
+    pub fn write(&mut self, address: usize, value: BigInt) {
+        if address >= self.program.len() {
+            self.program.resize(address + 1, BigInt::zero());
+        }
+        self.program[address] = value;
+    }
+
+    fn checked_address(&self, address: &BigInt) -> Result<usize, Fault> {
+        match address.to_i64() {
+            Some(address) if address >= 0 && address as u64 <= MAX_ADDRESS as u64 => {
+                Ok(address as usize)
+            }
+            _ => Err(Fault::InvalidAddress {
+                ip: self.index,
+                address: address.saturating_i64(),
+            }),
+        }
+    }
+}
+
+pub fn try_run_step(
+    state: &mut BigState,
+    limit_input_use: bool,
+) -> Result<(Option<BigInt>, bool), Fault> {
+    let ip = state.index;
+    let word = state.read(ip);
+    // only the opcode word has to fit; the operands are read below at their full width
+    let instruction = word
+        .to_i64()
+        .and_then(|word| decode::decode_with(|a| Some(if a == ip { word } else { 0 }), ip))
+        .ok_or(Fault::InvalidOpcode {
+            ip,
+            opcode: word.saturating_i64(),
+        })?;
+    let params = &instruction.params;
+    // the decoded operands are placeholders, so the real ones are read from memory
+    let address = |state: &BigState, i: usize| {
+        let operand = state.read(ip + 1 + i);
+        match params[i].mode {
+            Mode::Relative => &state.relative_base + &operand,
+            _ => operand,
+        }
+    };
+    let value = |state: &BigState, i: usize| match params[i].mode {
+        Mode::Immediate => state.read(ip + 1 + i),
+        // like `ProgramState`, reading anywhere outside memory gives zero
+        _ => match state.checked_address(&address(state, i)) {
+            Ok(address) => state.read(address),
+            Err(_) => BigInt::zero(),
+        },
+    };
+
+    let result = match instruction.opcode {
+        Opcode::Add => Some(&value(state, 0) + &value(state, 1)),
+        Opcode::Multiply => Some(&value(state, 0) * &value(state, 1)),
+        Opcode::LessThan => Some(BigInt::from((value(state, 0) < value(state, 1)) as i64)),
+        Opcode::Equals => Some(BigInt::from((value(state, 0) == value(state, 1)) as i64)),
+        Opcode::Input => {
+            // check the address before the input is taken, so a fault doesn't lose it
+            state.checked_address(&address(state, 0))?;
+            match state.inputs.pop_front() {
+                Some(input) => {
+                    state.last_input = Some(input.clone());
+                    Some(input)
+                }
+                None if !limit_input_use && state.last_input.is_some() => state.last_input.clone(),
+                None => return Ok((None, true)),
+            }
+        }
+        Opcode::Output => {
+            let output = value(state, 0);
+            state.index = instruction.next();
+            return Ok((Some(output), false));
+        }
+        Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+            let jump_when = instruction.opcode == Opcode::JumpIfTrue;
+            if value(state, 0).is_zero() != jump_when {
+                state.index = state.checked_address(&value(state, 1))?;
+                return Ok((None, false));
+            }
+            None
+        }
+        Opcode::AdjustBase => {
+            state.relative_base = &state.relative_base + &value(state, 0);
+            None
+        }
+        Opcode::Halt => {
+            state.finished = true;
+            return Ok((None, true));
+        }
+    };
+    if let Some(result) = result {
+        let target = address(state, instruction.opcode.write_param().unwrap());
+        let target = state.checked_address(&target)?;
+        state.write(target, result);
+    }
+    state.index = instruction.next();
+    Ok((None, false))
+}
+
+pub fn run_program<F>(state: &mut BigState, limit_input_use: bool, handle_output: F)
+where
+    F: FnMut(&mut BigState, BigInt) -> bool,
+{
+    if let Err(fault) = try_run_program(state, limit_input_use, handle_output) {
+        panic!("{}", fault);
+    }
+}
+
+pub fn try_run_program<F>(
+    state: &mut BigState,
+    limit_input_use: bool,
+    mut handle_output: F,
+) -> Result<(), Fault>
+where
+    F: FnMut(&mut BigState, BigInt) -> bool,
+{
+    loop {
+        let result = try_run_step(state, limit_input_use)?;
+        if let Some(output) = result.0 {
+            if handle_output(state, output) {
+                break;
+            }
+        }
+        if result.1 {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(text: &str) -> BigInt {
+        text.parse().unwrap()
+    }
+
+    fn outputs_of(program: &[i64], inputs: Vec<BigInt>) -> Result<Vec<BigInt>, Fault> {
+        let mut state = BigState::new(program, inputs);
+        let mut outputs = Vec::new();
+        try_run_program(&mut state, true, |_, output| {
+            outputs.push(output);
+            false
+        })?;
+        Ok(outputs)
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let max = BigInt::from(i64::MAX);
+        let square = &max * &max;
+        assert_eq!(square.to_string(), "85070591730234615847396907784232501249");
+        assert_eq!(square.to_i64(), None);
+        assert_eq!(big("85070591730234615847396907784232501249"), square);
+
+        let min = BigInt::from(i64::MIN);
+        assert_eq!(min.to_i64(), Some(i64::MIN));
+        assert_eq!((&min + &BigInt::from(-1)).to_i64(), None);
+        assert_eq!((&BigInt::from(3) + &BigInt::from(-5)).to_string(), "-2");
+        assert_eq!((&BigInt::from(-3) + &BigInt::from(5)).to_string(), "2");
+        assert!((&BigInt::from(-7) + &BigInt::from(7)).is_zero());
+        assert_eq!((&BigInt::from(-4) * &BigInt::from(0)).to_string(), "0");
+        assert_eq!(big("-1000000000000").to_i64(), Some(-1_000_000_000_000));
+        assert!(big("-10") < big("-9") && big("-9") < big("0") && big("0") < max);
+        assert_eq!("1-".parse::<BigInt>(), Err(ParseBigIntError));
+    }
+
+    #[test]
+    fn test_products_keep_their_full_width() {
+        // squares its input twice, writing each square back over the input
+        let program = vec![3, 13, 2, 13, 13, 13, 2, 13, 13, 13, 4, 13, 99, 0];
+        assert_eq!(
+            outputs_of(&program, vec![BigInt::from(i64::MAX)]),
+            Ok(vec![big(
+                "7237005577332262210834635695349653859421902880380109739573089701262786560001"
+            )])
+        );
+    }
+
+    #[test]
+    fn test_relative_base_and_comparisons_past_i64() {
+        let huge = &BigInt::from(i64::MAX) * &BigInt::from(4);
+
+        // lt #huge, #huge + 1, [7]; out [7]
+        let mut state = BigState::new(&[1107, 0, 0, 7, 4, 7, 99, 0], vec![]);
+        state.write(1, huge.clone());
+        state.write(2, &huge + &BigInt::from(1));
+        assert_eq!(state.read(2).to_i64(), None);
+        let mut outputs = Vec::new();
+        run_program(&mut state, true, |_, output| {
+            outputs.push(output);
+            false
+        });
+        assert_eq!(outputs, vec![BigInt::from(1)]);
+
+        // arb #huge; add #0, [rb - huge + 6], [rb - huge]: the offsets only cancel out at full
+        // width, copying the 99 at 6 to 0
+        let mut state = BigState::new(&[109, 0, 22101, 0, 0, 0, 99], vec![]);
+        state.write(1, huge.clone());
+        state.write(4, &(-huge.clone()) + &BigInt::from(6));
+        state.write(5, -huge);
+        assert_eq!(try_run_program(&mut state, true, |_, _| false), Ok(()));
+        assert_eq!(state.read(0), BigInt::from(99));
+        assert!(state.finished);
+    }
+
+    #[test]
+    fn test_invalid_addresses_fault() {
+        // jumps past MAX_ADDRESS
+        let program = vec![1105, 1, 1 << 40];
+        assert_eq!(
+            outputs_of(&program, vec![]),
+            Err(Fault::InvalidAddress {
+                ip: 0,
+                address: 1 << 40,
+            })
+        );
+
+        // writes the input to [rb - 1]
+        assert_eq!(
+            outputs_of(&[203, -1, 99], vec![BigInt::from(7)]),
+            Err(Fault::InvalidAddress { ip: 0, address: -1 })
+        );
+        assert_eq!(
+            outputs_of(&[1, 0, 0, 0, -3], vec![]),
+            Err(Fault::InvalidOpcode { ip: 4, opcode: -3 })
+        );
+    }
+}
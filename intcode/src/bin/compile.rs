@@ -0,0 +1,40 @@
+extern crate intcode;
+
+use std::env;
+use std::fs;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let flags = &args[2.min(args.len())..];
+    let listing = flags.iter().any(|flag| flag == "--asm");
+    let optimize = flags.iter().any(|flag| flag == "-O");
+    if args.len() < 2 || flags.iter().any(|flag| flag != "--asm" && flag != "-O") {
+        eprintln!("usage: compile <source> [--asm] [-O]");
+        process::exit(2);
+    }
+
+    let source = fs::read_to_string(&args[1]).expect("Could not read source");
+    let mut items = match intcode::compiler::compile(&source) {
+        Ok(items) => items,
+        Err(error) => {
+            eprintln!("{}:{}", args[1], error);
+            process::exit(1);
+        }
+    };
+    if optimize {
+        let (optimized, report) = intcode::optimize::optimize(&items);
+        eprintln!("{}", report);
+        items = optimized;
+    }
+
+    if listing {
+        for item in &items {
+            println!("{}", item);
+        }
+    } else {
+        let program = intcode::asm::assemble(&items).expect("generated assembly is valid");
+        let words: Vec<String> = program.iter().map(|word| word.to_string()).collect();
+        println!("{}", words.join(","));
+    }
+}
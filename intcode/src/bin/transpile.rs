@@ -0,0 +1,23 @@
+extern crate intcode;
+
+use std::env;
+use std::fs;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("usage: transpile <program.txt> <function name>");
+        process::exit(2);
+    }
+
+    let text = fs::read_to_string(&args[1]).expect("Could not read program");
+    let program = intcode::get_base_program(&text);
+    match intcode::transpile::transpile(&program, &args[2]) {
+        Ok(source) => print!("{}", source),
+        Err(error) => {
+            eprintln!("refusing to transpile {}: {}", args[1], error);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Peripherals wired to a machine's input and output.
+//!
+//! A `Bus` sits on the other end of a machine's input and output instructions. Outputs are
+//! gathered into frames of however many values the program sends as one message, and every frame
+//! is offered to each attached peripheral, which takes the ones its protocol understands. Inputs
+//! come from the first peripheral, in the order they were attached, that has something to send.
+//!
+//! The peripherals the puzzles need are in `peripherals`.
+
+use crate::Device;
+
+/// A virtual device a program talks to through a `Bus`
+pub trait Peripheral {
+    /// Whether the frame is meant for this peripheral
+    fn accepts(&self, _frame: &[i64]) -> bool {
+        true
+    }
+
+    fn receive(&mut self, frame: &[i64]);
+
+    /// Next value for an input instruction, or `None` if this peripheral has nothing to send
+    fn send(&mut self) -> Option<i64> {
+        None
+    }
+}
+
+pub struct Bus<'a> {
+    frame_len: usize,
+    frame: Vec<i64>,
+    peripherals: Vec<&'a mut dyn Peripheral>,
+}
+
+impl<'a> Bus<'a> {
+    /// A bus for a program that sends messages of `frame_len` values
+    pub fn new(frame_len: usize) -> Self {
+        Bus {
+            frame_len: frame_len.max(1),
+            frame: Vec::new(),
+            peripherals: Vec::new(),
+        }
+    }
+
+    pub fn attach(mut self, peripheral: &'a mut dyn Peripheral) -> Self {
+        self.peripherals.push(peripheral);
+        self
+    }
+
+    /// Values of a frame the program hasn't finished sending
+    pub fn partial_frame(&self) -> &[i64] {
+        &self.frame
+    }
+}
+
+impl<'a> Device for Bus<'a> {
+    fn input(&mut self) -> Option<i64> {
+        self.peripherals
+            .iter_mut()
+            .filter_map(|peripheral| peripheral.send())
+            .next()
+    }
+
+    fn output(&mut self, value: i64) {
+        self.frame.push(value);
+        if self.frame.len() < self.frame_len {
+            return;
+        }
+        let frame = std::mem::take(&mut self.frame);
+        for peripheral in self.peripherals.iter_mut() {
+            if peripheral.accepts(&frame) {
+                peripheral.receive(&frame);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run_device, ProgramState};
+
+    #[derive(Default)]
+    struct Recorder {
+        wanted: i64,
+        frames: Vec<Vec<i64>>,
+        inputs: Vec<i64>,
+    }
+
+    impl Peripheral for Recorder {
+        fn accepts(&self, frame: &[i64]) -> bool {
+            frame[0] == self.wanted
+        }
+
+        fn receive(&mut self, frame: &[i64]) {
+            self.frames.push(frame.to_vec());
+        }
+
+        fn send(&mut self) -> Option<i64> {
+            self.inputs.pop()
+        }
+    }
+
+    #[test]
+    fn test_routes_frames_and_inputs() {
+        // prints (1, 10), (2, 20) and (1, the number it reads), then halts
+        let program = vec![
+            104, 1, 104, 10, 104, 2, 104, 20, 3, 15, 104, 1, 4, 15, 99, 0,
+        ];
+        let mut state = ProgramState::new(&program, vec![]);
+        let mut ones = Recorder {
+            wanted: 1,
+            ..Recorder::default()
+        };
+        let mut twos = Recorder {
+            wanted: 2,
+            inputs: vec![7],
+            ..Recorder::default()
+        };
+        let mut bus = Bus::new(2).attach(&mut ones).attach(&mut twos);
+        assert!(run_device(&mut state, &mut bus));
+        assert!(bus.partial_frame().is_empty());
+
+        assert_eq!(ones.frames, vec![vec![1, 10], vec![1, 7]]);
+        assert_eq!(twos.frames, vec![vec![2, 20]]);
+        assert!(twos.inputs.is_empty());
+    }
+}
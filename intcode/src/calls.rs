@@ -0,0 +1,250 @@
+//! Heuristic call stack tracking.
+//!
+//! The puzzle programs call subroutines by storing a return address relative to the base, then
+//! jumping to the routine, which moves the base with opcode 9 to make room for its locals. They
+//! return by moving the base back and jumping through the stored address. Watching for that
+//! pattern is enough to rebuild the call stack without any debug information.
+
+use std::fmt;
+
+use crate::decode::{Mode, Opcode};
+use crate::{Fault, Op, ProgramState};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frame {
+    /// Address of the jump that made the call
+    pub call_site: usize,
+    /// Where the called routine starts
+    pub entry: usize,
+    pub return_address: usize,
+    /// Relative base when the call was made
+    pub relative_base: i64,
+    /// How far the routine has moved the relative base since it was entered
+    pub frame_size: i64,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "routine at {}, called from {}, returns to {}",
+            self.entry, self.call_site, self.return_address
+        )
+    }
+}
+
+/// How one instruction changed the tracker, kept in the undo history
+#[derive(Clone, Debug)]
+pub(crate) enum CallUndo {
+    Stored,
+    Jump {
+        popped: Vec<Frame>,
+        pushed: bool,
+        stored: Vec<i64>,
+    },
+    Resize(i64),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CallTracker {
+    frames: Vec<Frame>,
+    // values stored relative to the base since the last jump, possible return addresses
+    stored: Vec<i64>,
+}
+
+impl CallTracker {
+    pub fn new() -> Self {
+        CallTracker::default()
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    fn observe(
+        &mut self,
+        state: &ProgramState,
+        op: &Op,
+        ip: usize,
+        relative_base: i64,
+    ) -> Option<CallUndo> {
+        match op.opcode {
+            Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals | Opcode::Input => {
+                let param = op.params[op.opcode.write_param().unwrap()];
+                if param.mode != Mode::Relative {
+                    return None;
+                }
+                let address = param.value.wrapping_add(relative_base) as usize;
+                self.stored.push(state.program.get(address));
+                Some(CallUndo::Stored)
+            }
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                let next = ip + op.len;
+                if state.index == next {
+                    return None;
+                }
+                let target = state.index;
+                let mut popped = Vec::new();
+                let mut pushed = false;
+                if let Some(depth) = self
+                    .frames
+                    .iter()
+                    .rposition(|frame| frame.return_address == target)
+                {
+                    popped = self.frames.split_off(depth);
+                } else if self.stored.contains(&(next as i64)) {
+                    self.frames.push(Frame {
+                        call_site: ip,
+                        entry: target,
+                        return_address: next,
+                        relative_base,
+                        frame_size: 0,
+                    });
+                    pushed = true;
+                }
+                Some(CallUndo::Jump {
+                    popped,
+                    pushed,
+                    stored: std::mem::take(&mut self.stored),
+                })
+            }
+            Opcode::AdjustBase => {
+                let frame = self.frames.last_mut()?;
+                let change = state.relative_base - relative_base;
+                frame.frame_size += change;
+                Some(CallUndo::Resize(change))
+            }
+            Opcode::Output | Opcode::Halt => None,
+        }
+    }
+
+    pub(crate) fn undo(&mut self, undo: CallUndo) {
+        match undo {
+            CallUndo::Stored => {
+                self.stored.pop();
+            }
+            CallUndo::Jump {
+                popped,
+                pushed,
+                stored,
+            } => {
+                if pushed {
+                    self.frames.pop();
+                }
+                self.frames.extend(popped);
+                self.stored = stored;
+            }
+            CallUndo::Resize(change) => {
+                if let Some(frame) = self.frames.last_mut() {
+                    frame.frame_size -= change;
+                }
+            }
+        }
+    }
+}
+
+impl ProgramState {
+    /// Starts rebuilding the call stack as the program runs
+    pub fn track_calls(&mut self) {
+        self.calls = Some(CallTracker::new());
+    }
+
+    /// Active calls, innermost first. Empty unless `track_calls` was called.
+    pub fn backtrace(&self) -> Vec<Frame> {
+        match self.calls {
+            Some(ref calls) => calls.frames.iter().rev().cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// The fault's message followed by the backtrace at the point it was raised
+    pub fn fault_report(&self, fault: &Fault) -> String {
+        let mut report = fault.to_string();
+        for frame in self.backtrace() {
+            report.push_str(&format!("\n    in {}", frame));
+        }
+        report
+    }
+}
+
+pub(crate) fn observe(state: &mut ProgramState, op: &Op, ip: usize, relative_base: i64) {
+    if let Some(mut calls) = state.calls.take() {
+        let undo = calls.observe(state, op, ip, relative_base);
+        if let (Some(undo), Some(history)) = (undo, state.history.as_mut()) {
+            history.record_calls(undo);
+        }
+        state.calls = Some(calls);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{run_step, try_run_step, Fault, ProgramState};
+
+    // main calls f at 11, which calls g at 25
+    fn program(g: &[i64]) -> Vec<i64> {
+        let mut program = vec![
+            109, 100, 21101, 9, 0, 0, 1105, 1, 11, 99, 0, 109, 2, 21101, 20, 0, 0, 1105, 1, 25,
+            109, -2, 2105, 1, 0,
+        ];
+        program.extend_from_slice(g);
+        program
+    }
+
+    #[test]
+    fn test_backtrace_on_invalid_opcode() {
+        let mut state = ProgramState::new(&program(&[0]), vec![]);
+        state.track_calls();
+        let fault = loop {
+            if let Err(fault) = try_run_step(&mut state, false) {
+                break fault;
+            }
+        };
+
+        assert_eq!(fault, Fault::InvalidOpcode { ip: 25, opcode: 0 });
+        let frames = state.backtrace();
+        assert_eq!(frames.len(), 2);
+        assert_eq!((frames[0].entry, frames[0].call_site), (25, 17));
+        assert_eq!((frames[1].entry, frames[1].return_address), (11, 9));
+        assert_eq!(frames[1].frame_size, 2);
+        assert_eq!(
+            state.fault_report(&fault),
+            "Invalid opcode 0 at 25\n    in routine at 25, called from 17, returns to 20\n    in routine at 11, called from 6, returns to 9"
+        );
+    }
+
+    #[test]
+    fn test_returns_pop_frames() {
+        // g returns straight away
+        let mut state = ProgramState::new(&program(&[2105, 1, 0]), vec![]);
+        state.track_calls();
+        let mut deepest = 0;
+        while !run_step(&mut state, false).1 {
+            deepest = deepest.max(state.backtrace().len());
+        }
+
+        assert_eq!(deepest, 2);
+        assert!(state.backtrace().is_empty());
+        assert!(state.finished);
+    }
+
+    #[test]
+    fn test_stepping_back_restores_the_call_stack() {
+        let mut state = ProgramState::new(&program(&[2105, 1, 0]), vec![]);
+        state.track_calls();
+        state.enable_history(100, 4);
+        let mut backtraces = vec![state.backtrace()];
+        while !run_step(&mut state, false).1 {
+            backtraces.push(state.backtrace());
+        }
+        assert!(backtraces.iter().any(|frames| frames.len() == 2));
+
+        // the halt isn't in the list, so step back over it first
+        assert!(state.step_back());
+        while let Some(expected) = backtraces.pop() {
+            assert_eq!(state.backtrace(), expected, "at {}", state.index);
+            state.step_back();
+        }
+        assert_eq!(state.index, 0);
+    }
+}
@@ -0,0 +1,852 @@
+//! Compiler for a tiny language that targets intcode through the assembler.
+//!
+//! ```text
+//! fn fib(n) {
+//!     if n < 2 { return n; }
+//!     return fib(n - 1) + fib(n - 2);
+//! }
+//!
+//! fn main() {
+//!     var n = read();
+//!     while n > 0 {
+//!         write(fib(n));
+//!         n = n - 1;
+//!     }
+//! }
+//! ```
+//!
+//! Everything is an integer. Calls follow the same convention as the puzzle programs: the caller
+//! stores the return address at the base of the new frame, moves the relative base up to it and
+//! jumps. Arguments sit right after the return address, then locals, then temporaries, all at
+//! offsets known when compiling. The return value is left in the first argument slot.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::asm::{self, Item, Operand};
+use crate::decode::Opcode;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompileError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+fn error<T>(line: usize, message: String) -> Result<T, CompileError> {
+    Err(CompileError { line, message })
+}
+
+/// Compiles source code to assembler items
+pub fn compile(source: &str) -> Result<Vec<Item>, CompileError> {
+    let tokens = tokenize(source)?;
+    let functions = Parser { tokens, next: 0 }.program()?;
+    generate(&functions)
+}
+
+/// Compiles source code all the way to an intcode program
+pub fn compile_program(source: &str) -> Result<Vec<i64>, CompileError> {
+    let items = compile(source)?;
+    Ok(asm::assemble(&items).expect("generated assembly is valid"))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Symbol(&'static str),
+    End,
+}
+
+const SYMBOLS: [&str; 17] = [
+    "<=", ">=", "==", "!=", "(", ")", "{", "}", ",", ";", "=", "+", "-", "*", "<", ">", "!",
+];
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, CompileError> {
+    let mut tokens = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        let line_number = number + 1;
+        let line = line.split("//").next().unwrap();
+        let mut rest = line.trim_start();
+        while !rest.is_empty() {
+            let first = rest.chars().next().unwrap();
+            let length = if first.is_ascii_digit() {
+                let length = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                match rest[..length].parse() {
+                    Ok(value) => tokens.push((Token::Number(value), line_number)),
+                    Err(_) => return error(line_number, format!("{} is too big", &rest[..length])),
+                }
+                length
+            } else if first.is_ascii_alphabetic() || first == '_' {
+                let length = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                tokens.push((Token::Name(rest[..length].to_string()), line_number));
+                length
+            } else {
+                match SYMBOLS.iter().find(|symbol| rest.starts_with(*symbol)) {
+                    Some(symbol) => {
+                        tokens.push((Token::Symbol(symbol), line_number));
+                        symbol.len()
+                    }
+                    None => return error(line_number, format!("unexpected {:?}", first)),
+                }
+            };
+            rest = rest[length..].trim_start();
+        }
+    }
+    let last_line = source.lines().count().max(1);
+    tokens.push((Token::End, last_line));
+    Ok(tokens)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Less,
+    Greater,
+    LessOrEqual,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(i64),
+    Var(String, usize),
+    Read,
+    Call(String, Vec<Expr>, usize),
+    Negate(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Debug)]
+enum Stmt {
+    Var(String, Expr, usize),
+    Assign(String, Expr, usize),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    Return(Expr),
+    Write(Expr),
+    Expr(Expr),
+}
+
+#[derive(Clone, Debug)]
+struct Function {
+    name: String,
+    params: Vec<String>,
+    body: Vec<Stmt>,
+    line: usize,
+}
+
+const KEYWORDS: [&str; 8] = [
+    "fn", "var", "if", "else", "while", "return", "read", "write",
+];
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.next].0
+    }
+
+    fn line(&self) -> usize {
+        self.tokens[self.next].1
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.next].0.clone();
+        if token != Token::End {
+            self.next += 1;
+        }
+        token
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        matches!(*self.peek(), Token::Symbol(found) if found == symbol)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(*self.peek(), Token::Name(ref name) if name == keyword)
+    }
+
+    fn unexpected<T>(&self, wanted: &str) -> Result<T, CompileError> {
+        let found = match *self.peek() {
+            Token::Number(value) => value.to_string(),
+            Token::Name(ref name) => name.clone(),
+            Token::Symbol(symbol) => symbol.to_string(),
+            Token::End => "end of input".to_string(),
+        };
+        error(self.line(), format!("expected {}, found {}", wanted, found))
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), CompileError> {
+        if self.is_symbol(symbol) {
+            self.advance();
+            Ok(())
+        } else {
+            self.unexpected(&format!("'{}'", symbol))
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), CompileError> {
+        if self.is_keyword(keyword) {
+            self.advance();
+            Ok(())
+        } else {
+            self.unexpected(keyword)
+        }
+    }
+
+    fn name(&mut self) -> Result<String, CompileError> {
+        match *self.peek() {
+            Token::Name(ref name) if !KEYWORDS.contains(&name.as_str()) => {
+                let name = name.clone();
+                self.advance();
+                Ok(name)
+            }
+            _ => self.unexpected("a name"),
+        }
+    }
+
+    fn program(&mut self) -> Result<Vec<Function>, CompileError> {
+        let mut functions = Vec::new();
+        while *self.peek() != Token::End {
+            functions.push(self.function()?);
+        }
+        Ok(functions)
+    }
+
+    fn function(&mut self) -> Result<Function, CompileError> {
+        let line = self.line();
+        self.expect_keyword("fn")?;
+        let name = self.name()?;
+        self.expect_symbol("(")?;
+        let mut params = Vec::new();
+        while !self.is_symbol(")") {
+            if !params.is_empty() {
+                self.expect_symbol(",")?;
+            }
+            params.push(self.name()?);
+        }
+        self.advance();
+        let body = self.block()?;
+
+        Ok(Function {
+            name,
+            params,
+            body,
+            line,
+        })
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, CompileError> {
+        self.expect_symbol("{")?;
+        let mut body = Vec::new();
+        while !self.is_symbol("}") {
+            body.push(self.statement()?);
+        }
+        self.advance();
+        Ok(body)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, CompileError> {
+        let line = self.line();
+        if self.is_keyword("var") {
+            self.advance();
+            let name = self.name()?;
+            self.expect_symbol("=")?;
+            let value = self.expression()?;
+            self.expect_symbol(";")?;
+            return Ok(Stmt::Var(name, value, line));
+        }
+        if self.is_keyword("if") {
+            self.advance();
+            let condition = self.expression()?;
+            let then = self.block()?;
+            let otherwise = if self.is_keyword("else") {
+                self.advance();
+                if self.is_keyword("if") {
+                    vec![self.statement()?]
+                } else {
+                    self.block()?
+                }
+            } else {
+                Vec::new()
+            };
+            return Ok(Stmt::If(condition, then, otherwise));
+        }
+        if self.is_keyword("while") {
+            self.advance();
+            let condition = self.expression()?;
+            return Ok(Stmt::While(condition, self.block()?));
+        }
+        if self.is_keyword("return") {
+            self.advance();
+            let value = if self.is_symbol(";") {
+                Expr::Number(0)
+            } else {
+                self.expression()?
+            };
+            self.expect_symbol(";")?;
+            return Ok(Stmt::Return(value));
+        }
+        if self.is_keyword("write") {
+            self.advance();
+            self.expect_symbol("(")?;
+            let value = self.expression()?;
+            self.expect_symbol(")")?;
+            self.expect_symbol(";")?;
+            return Ok(Stmt::Write(value));
+        }
+
+        let assigns = matches!(*self.peek(), Token::Name(_))
+            && self.tokens[self.next + 1].0 == Token::Symbol("=");
+        if assigns {
+            let name = self.name()?;
+            self.advance();
+            let value = self.expression()?;
+            self.expect_symbol(";")?;
+            return Ok(Stmt::Assign(name, value, line));
+        }
+
+        let value = self.expression()?;
+        self.expect_symbol(";")?;
+        Ok(Stmt::Expr(value))
+    }
+
+    fn expression(&mut self) -> Result<Expr, CompileError> {
+        let lhs = self.additive()?;
+        let op = match *self.peek() {
+            Token::Symbol("<") => BinaryOp::Less,
+            Token::Symbol(">") => BinaryOp::Greater,
+            Token::Symbol("<=") => BinaryOp::LessOrEqual,
+            Token::Symbol(">=") => BinaryOp::GreaterOrEqual,
+            Token::Symbol("==") => BinaryOp::Equal,
+            Token::Symbol("!=") => BinaryOp::NotEqual,
+            _ => return Ok(lhs),
+        };
+        self.advance();
+        let rhs = self.additive()?;
+        Ok(Expr::Binary(op, Box::new(lhs), Box::new(rhs)))
+    }
+
+    fn additive(&mut self) -> Result<Expr, CompileError> {
+        let mut lhs = self.term()?;
+        loop {
+            let op = match *self.peek() {
+                Token::Symbol("+") => BinaryOp::Add,
+                Token::Symbol("-") => BinaryOp::Subtract,
+                _ => return Ok(lhs),
+            };
+            self.advance();
+            let rhs = self.term()?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, CompileError> {
+        let mut lhs = self.unary()?;
+        while self.is_symbol("*") {
+            self.advance();
+            let rhs = self.unary()?;
+            lhs = Expr::Binary(BinaryOp::Multiply, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, CompileError> {
+        if self.is_symbol("-") {
+            self.advance();
+            return Ok(match self.unary()? {
+                Expr::Number(value) => Expr::Number(-value),
+                value => Expr::Negate(Box::new(value)),
+            });
+        }
+        if self.is_symbol("!") {
+            self.advance();
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, CompileError> {
+        let line = self.line();
+        match *self.peek() {
+            Token::Number(value) => {
+                self.advance();
+                Ok(Expr::Number(value))
+            }
+            Token::Symbol("(") => {
+                self.advance();
+                let value = self.expression()?;
+                self.expect_symbol(")")?;
+                Ok(value)
+            }
+            Token::Name(ref name) if name == "read" => {
+                self.advance();
+                self.expect_symbol("(")?;
+                self.expect_symbol(")")?;
+                Ok(Expr::Read)
+            }
+            Token::Name(_) => {
+                let name = self.name()?;
+                if !self.is_symbol("(") {
+                    return Ok(Expr::Var(name, line));
+                }
+                self.advance();
+                let mut args = Vec::new();
+                while !self.is_symbol(")") {
+                    if !args.is_empty() {
+                        self.expect_symbol(",")?;
+                    }
+                    args.push(self.expression()?);
+                }
+                self.advance();
+                Ok(Expr::Call(name, args, line))
+            }
+            _ => self.unexpected("an expression"),
+        }
+    }
+}
+
+struct Generator<'a> {
+    items: Vec<Item>,
+    arities: &'a HashMap<String, usize>,
+    labels: usize,
+    // relative base offsets of the current function's arguments and locals
+    slots: HashMap<String, i64>,
+}
+
+fn generate(functions: &[Function]) -> Result<Vec<Item>, CompileError> {
+    let mut arities = HashMap::new();
+    for function in functions {
+        if arities
+            .insert(function.name.clone(), function.params.len())
+            .is_some()
+        {
+            return error(function.line, format!("{} is defined twice", function.name));
+        }
+    }
+    match arities.get("main") {
+        Some(0) => {}
+        Some(_) => {
+            let line = functions.iter().find(|f| f.name == "main").unwrap().line;
+            return error(line, "main can't take arguments".to_string());
+        }
+        None => return error(1, "there is no main function".to_string()),
+    }
+
+    let mut generator = Generator {
+        items: Vec::new(),
+        arities: &arities,
+        labels: 0,
+        slots: HashMap::new(),
+    };
+    generator.emit(Opcode::AdjustBase, vec![Operand::label(".stack")]);
+    generator.emit(
+        Opcode::Add,
+        vec![
+            Operand::label(".exit"),
+            Operand::immediate(0),
+            Operand::relative(0),
+        ],
+    );
+    generator.jump("main");
+    generator.items.push(Item::Label(".exit".to_string()));
+    generator.emit(Opcode::Halt, vec![]);
+
+    for function in functions {
+        generator.function(function)?;
+    }
+    generator.items.push(Item::Label(".stack".to_string()));
+
+    Ok(generator.items)
+}
+
+impl<'a> Generator<'a> {
+    fn emit(&mut self, opcode: Opcode, operands: Vec<Operand>) {
+        self.items.push(Item::Instruction(opcode, operands));
+    }
+
+    fn jump(&mut self, label: &str) {
+        self.emit(
+            Opcode::JumpIfFalse,
+            vec![Operand::immediate(0), Operand::label(label)],
+        );
+    }
+
+    fn new_label(&mut self) -> String {
+        self.labels += 1;
+        format!(".L{}", self.labels)
+    }
+
+    fn function(&mut self, function: &Function) -> Result<(), CompileError> {
+        self.slots.clear();
+        for param in &function.params {
+            let slot = self.slots.len() as i64 + 1;
+            if self.slots.insert(param.clone(), slot).is_some() {
+                return error(function.line, format!("{} is declared twice", param));
+            }
+        }
+        self.declare(&function.body)?;
+        let top = self.slots.len() as i64 + 1;
+
+        self.items.push(Item::Label(function.name.clone()));
+        for statement in &function.body {
+            self.statement(statement, top)?;
+        }
+        self.statement(&Stmt::Return(Expr::Number(0)), top)
+    }
+
+    // gives every local its own slot for the whole function
+    fn declare(&mut self, body: &[Stmt]) -> Result<(), CompileError> {
+        for statement in body {
+            match *statement {
+                Stmt::Var(ref name, _, line) => {
+                    let slot = self.slots.len() as i64 + 1;
+                    if self.slots.insert(name.clone(), slot).is_some() {
+                        return error(line, format!("{} is declared twice", name));
+                    }
+                }
+                Stmt::If(_, ref then, ref otherwise) => {
+                    self.declare(then)?;
+                    self.declare(otherwise)?;
+                }
+                Stmt::While(_, ref body) => self.declare(body)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn slot(&self, name: &str, line: usize) -> Result<i64, CompileError> {
+        match self.slots.get(name) {
+            Some(slot) => Ok(*slot),
+            None => error(line, format!("{} is not declared", name)),
+        }
+    }
+
+    // `top` is the first slot free for temporaries
+    fn statement(&mut self, statement: &Stmt, top: i64) -> Result<(), CompileError> {
+        match *statement {
+            Stmt::Var(ref name, ref value, line) | Stmt::Assign(ref name, ref value, line) => {
+                let slot = self.slot(name, line)?;
+                self.expression(value, slot, top)
+            }
+            Stmt::If(ref condition, ref then, ref otherwise) => {
+                let condition = self.operand(condition, top)?;
+                let end = self.new_label();
+                if otherwise.is_empty() {
+                    self.emit(Opcode::JumpIfFalse, vec![condition, Operand::label(&end)]);
+                    for statement in then {
+                        self.statement(statement, top)?;
+                    }
+                } else {
+                    let other = self.new_label();
+                    self.emit(Opcode::JumpIfFalse, vec![condition, Operand::label(&other)]);
+                    for statement in then {
+                        self.statement(statement, top)?;
+                    }
+                    self.jump(&end);
+                    self.items.push(Item::Label(other));
+                    for statement in otherwise {
+                        self.statement(statement, top)?;
+                    }
+                }
+                self.items.push(Item::Label(end));
+                Ok(())
+            }
+            Stmt::While(ref condition, ref body) => {
+                let start = self.new_label();
+                let end = self.new_label();
+                self.items.push(Item::Label(start.clone()));
+                let condition = self.operand(condition, top)?;
+                self.emit(Opcode::JumpIfFalse, vec![condition, Operand::label(&end)]);
+                for statement in body {
+                    self.statement(statement, top)?;
+                }
+                self.jump(&start);
+                self.items.push(Item::Label(end));
+                Ok(())
+            }
+            Stmt::Return(ref value) => {
+                self.expression(value, 1, top)?;
+                self.emit(
+                    Opcode::JumpIfFalse,
+                    vec![Operand::immediate(0), Operand::relative(0)],
+                );
+                Ok(())
+            }
+            Stmt::Write(ref value) => {
+                let value = self.operand(value, top)?;
+                self.emit(Opcode::Output, vec![value]);
+                Ok(())
+            }
+            Stmt::Expr(ref value) => self.expression(value, top, top + 1),
+        }
+    }
+
+    // an operand for the value, computing it into `top` when it isn't a constant or variable
+    fn operand(&mut self, value: &Expr, top: i64) -> Result<Operand, CompileError> {
+        match *value {
+            Expr::Number(number) => Ok(Operand::immediate(number)),
+            Expr::Var(ref name, line) => Ok(Operand::relative(self.slot(name, line)?)),
+            _ => {
+                self.expression(value, top, top + 1)?;
+                Ok(Operand::relative(top))
+            }
+        }
+    }
+
+    // computes the value into `destination`, using slots from `top` up as scratch
+    fn expression(&mut self, value: &Expr, destination: i64, top: i64) -> Result<(), CompileError> {
+        let destination_operand = Operand::relative(destination);
+        match *value {
+            Expr::Number(_) | Expr::Var(..) => {
+                let value = self.operand(value, top)?;
+                self.emit(
+                    Opcode::Add,
+                    vec![value, Operand::immediate(0), destination_operand],
+                );
+            }
+            Expr::Read => self.emit(Opcode::Input, vec![destination_operand]),
+            Expr::Negate(ref inner) => {
+                let inner = self.operand(inner, top)?;
+                self.emit(
+                    Opcode::Multiply,
+                    vec![inner, Operand::immediate(-1), destination_operand],
+                );
+            }
+            Expr::Not(ref inner) => {
+                let inner = self.operand(inner, top)?;
+                self.emit(
+                    Opcode::Equals,
+                    vec![inner, Operand::immediate(0), destination_operand],
+                );
+            }
+            Expr::Binary(op, ref lhs, ref rhs) => {
+                let lhs = self.operand(lhs, top)?;
+                let rhs = self.operand(rhs, top + 1)?;
+                let scratch = Operand::relative(top + 2);
+                match op {
+                    BinaryOp::Add => self.emit(Opcode::Add, vec![lhs, rhs, destination_operand]),
+                    BinaryOp::Multiply => {
+                        self.emit(Opcode::Multiply, vec![lhs, rhs, destination_operand])
+                    }
+                    BinaryOp::Subtract => {
+                        self.emit(
+                            Opcode::Multiply,
+                            vec![rhs, Operand::immediate(-1), scratch.clone()],
+                        );
+                        self.emit(Opcode::Add, vec![lhs, scratch, destination_operand]);
+                    }
+                    BinaryOp::Less => {
+                        self.emit(Opcode::LessThan, vec![lhs, rhs, destination_operand])
+                    }
+                    BinaryOp::Greater => {
+                        self.emit(Opcode::LessThan, vec![rhs, lhs, destination_operand])
+                    }
+                    BinaryOp::Equal => {
+                        self.emit(Opcode::Equals, vec![lhs, rhs, destination_operand])
+                    }
+                    BinaryOp::LessOrEqual | BinaryOp::GreaterOrEqual | BinaryOp::NotEqual => {
+                        let (opcode, operands) = match op {
+                            BinaryOp::LessOrEqual => (Opcode::LessThan, vec![rhs, lhs]),
+                            BinaryOp::GreaterOrEqual => (Opcode::LessThan, vec![lhs, rhs]),
+                            _ => (Opcode::Equals, vec![lhs, rhs]),
+                        };
+                        let mut operands = operands;
+                        operands.push(scratch.clone());
+                        self.emit(opcode, operands);
+                        self.emit(
+                            Opcode::Equals,
+                            vec![scratch, Operand::immediate(0), destination_operand],
+                        );
+                    }
+                }
+            }
+            Expr::Call(ref name, ref args, line) => {
+                match self.arities.get(name) {
+                    Some(arity) if *arity == args.len() => {}
+                    Some(arity) => {
+                        return error(
+                            line,
+                            format!("{} takes {} arguments, {} given", name, arity, args.len()),
+                        )
+                    }
+                    None => return error(line, format!("{} is not defined", name)),
+                }
+
+                // the new frame starts at `top`
+                let arguments_end = top + 1 + args.len() as i64;
+                for (i, arg) in args.iter().enumerate() {
+                    self.expression(arg, top + 1 + i as i64, arguments_end)?;
+                }
+                let back = self.new_label();
+                self.emit(
+                    Opcode::Add,
+                    vec![
+                        Operand::label(&back),
+                        Operand::immediate(0),
+                        Operand::relative(top),
+                    ],
+                );
+                self.emit(Opcode::AdjustBase, vec![Operand::immediate(top)]);
+                self.jump(name);
+                self.items.push(Item::Label(back));
+                self.emit(Opcode::AdjustBase, vec![Operand::immediate(-top)]);
+                self.emit(
+                    Opcode::Add,
+                    vec![
+                        Operand::relative(top + 1),
+                        Operand::immediate(0),
+                        destination_operand,
+                    ],
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run_program, ProgramState};
+
+    fn run(source: &str, inputs: Vec<i64>) -> Vec<i64> {
+        let program = compile_program(source).unwrap();
+        let mut state = ProgramState::new(&program, inputs);
+        let mut outputs = Vec::new();
+        run_program(&mut state, true, |_, output| {
+            outputs.push(output);
+            false
+        });
+        assert!(state.finished);
+        outputs
+    }
+
+    #[test]
+    fn test_arithmetic_and_comparisons() {
+        let source = "
+            fn main() {
+                var a = read();
+                var b = read();
+                write(a + b);
+                write(a - b);
+                write(a * b - -3);
+                write(-(a + 1));
+                write(a < b);
+                write(a > b);
+                write(a <= b);
+                write(a >= b);
+                write(a == b);
+                write(a != b);
+                write(!a);
+            }
+        ";
+        assert_eq!(
+            run(source, vec![7, 3]),
+            vec![10, 4, 24, -8, 0, 1, 0, 1, 0, 1, 0]
+        );
+    }
+
+    #[test]
+    fn test_recursion_and_loops() {
+        let source = "
+            // prints fib(n) for n from the input down to 1
+            fn fib(n) {
+                if n < 2 { return n; }
+                return fib(n - 1) + fib(n - 2);
+            }
+
+            fn main() {
+                var n = read();
+                while n > 0 {
+                    write(fib(n));
+                    n = n - 1;
+                }
+            }
+        ";
+        assert_eq!(
+            run(source, vec![10]),
+            vec![55, 34, 21, 13, 8, 5, 3, 2, 1, 1]
+        );
+    }
+
+    #[test]
+    fn test_else_if_and_multiple_arguments() {
+        let source = "
+            fn clamp(value, low, high) {
+                if value < low { return low; }
+                else if value > high { return high; }
+                else { return value; }
+            }
+
+            fn main() {
+                write(clamp(read(), 0, 10));
+                write(clamp(read(), 0, 10));
+                write(clamp(read(), 0, 10));
+            }
+        ";
+        assert_eq!(run(source, vec![-5, 5, 50]), vec![0, 5, 10]);
+    }
+
+    #[test]
+    fn test_calls_show_up_in_backtraces() {
+        let source = "
+            fn down(n) {
+                if n == 0 { return read(); }
+                return down(n - 1);
+            }
+
+            fn main() {
+                write(down(3));
+            }
+        ";
+        let program = compile_program(source).unwrap();
+        let mut state = ProgramState::new(&program, vec![]);
+        state.track_calls();
+        // stops at the read with no input left
+        run_program(&mut state, true, |_, _| false);
+        assert!(!state.finished);
+        assert_eq!(state.backtrace().len(), 5);
+    }
+
+    #[test]
+    fn test_reports_errors_with_lines() {
+        let errors = [
+            ("fn main() {\n  x = 1;\n}", "line 2: x is not declared"),
+            ("fn main() {\n  write(f(1));\n}", "line 2: f is not defined"),
+            (
+                "fn f(a) { return a; }\nfn main() {\n  f();\n}",
+                "line 3: f takes 1 arguments, 0 given",
+            ),
+            (
+                "fn main() {\n  write(1)\n}",
+                "line 3: expected ';', found }",
+            ),
+            ("fn helper() {}", "line 1: there is no main function"),
+        ];
+        for (source, message) in errors.iter() {
+            assert_eq!(compile(source).unwrap_err().to_string(), *message);
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Mode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+impl Mode {
+    fn from_digit(digit: i64) -> Option<Self> {
+        match digit {
+            0 => Some(Mode::Position),
+            1 => Some(Mode::Immediate),
+            2 => Some(Mode::Relative),
+            _ => None,
+        }
+    }
+
+    pub fn digit(self) -> i64 {
+        match self {
+            Mode::Position => 0,
+            Mode::Immediate => 1,
+            Mode::Relative => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    Add,
+    Multiply,
+    Input,
+    Output,
+    JumpIfTrue,
+    JumpIfFalse,
+    LessThan,
+    Equals,
+    AdjustBase,
+    Halt,
+}
+
+impl Opcode {
+    pub fn from_number(number: i64) -> Option<Self> {
+        match number {
+            1 => Some(Opcode::Add),
+            2 => Some(Opcode::Multiply),
+            3 => Some(Opcode::Input),
+            4 => Some(Opcode::Output),
+            5 => Some(Opcode::JumpIfTrue),
+            6 => Some(Opcode::JumpIfFalse),
+            7 => Some(Opcode::LessThan),
+            8 => Some(Opcode::Equals),
+            9 => Some(Opcode::AdjustBase),
+            99 => Some(Opcode::Halt),
+            _ => None,
+        }
+    }
+
+    pub fn number(self) -> i64 {
+        match self {
+            Opcode::Add => 1,
+            Opcode::Multiply => 2,
+            Opcode::Input => 3,
+            Opcode::Output => 4,
+            Opcode::JumpIfTrue => 5,
+            Opcode::JumpIfFalse => 6,
+            Opcode::LessThan => 7,
+            Opcode::Equals => 8,
+            Opcode::AdjustBase => 9,
+            Opcode::Halt => 99,
+        }
+    }
+
+    pub fn arity(self) -> usize {
+        match self {
+            Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals => 3,
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => 2,
+            Opcode::Input | Opcode::Output | Opcode::AdjustBase => 1,
+            Opcode::Halt => 0,
+        }
+    }
+
+    /// Index of the parameter this opcode writes to, if any
+    pub fn write_param(self) -> Option<usize> {
+        match self {
+            Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals => Some(2),
+            Opcode::Input => Some(0),
+            _ => None,
+        }
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Opcode::Add => "add",
+            Opcode::Multiply => "mul",
+            Opcode::Input => "in",
+            Opcode::Output => "out",
+            Opcode::JumpIfTrue => "jnz",
+            Opcode::JumpIfFalse => "jz",
+            Opcode::LessThan => "lt",
+            Opcode::Equals => "eq",
+            Opcode::AdjustBase => "arb",
+            Opcode::Halt => "hlt",
+        }
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Self> {
+        [
+            Opcode::Add,
+            Opcode::Multiply,
+            Opcode::Input,
+            Opcode::Output,
+            Opcode::JumpIfTrue,
+            Opcode::JumpIfFalse,
+            Opcode::LessThan,
+            Opcode::Equals,
+            Opcode::AdjustBase,
+            Opcode::Halt,
+        ]
+        .iter()
+        .cloned()
+        .find(|opcode| opcode.mnemonic() == mnemonic)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Param {
+    pub mode: Mode,
+    pub value: i64,
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mode {
+            Mode::Position => write!(f, "[{}]", self.value),
+            Mode::Immediate => write!(f, "#{}", self.value),
+            Mode::Relative => write!(f, "[rb{:+}]", self.value),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Instruction {
+    pub address: usize,
+    pub opcode: Opcode,
+    pub params: Vec<Param>,
+}
+
+impl Instruction {
+    pub fn len(&self) -> usize {
+        self.params.len() + 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    pub fn next(&self) -> usize {
+        self.address + self.len()
+    }
+
+    /// The address written by this instruction when it is known without running it
+    pub fn static_write_target(&self) -> Option<usize> {
+        let param = self.params[self.opcode.write_param()?];
+        if param.mode == Mode::Position && param.value >= 0 {
+            Some(param.value as usize)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.opcode.mnemonic())?;
+        for (i, param) in self.params.iter().enumerate() {
+            write!(f, "{}{}", if i == 0 { " " } else { ", " }, param)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes the instruction at `address`, or `None` if the word there is not a valid instruction
+pub fn decode(program: &[i64], address: usize) -> Option<Instruction> {
+    decode_with(|a| program.get(a).cloned(), address)
+}
+
+/// Like `decode`, reading words through `word_at`, which returns `None` past the end of memory
+pub fn decode_with<F>(word_at: F, address: usize) -> Option<Instruction>
+where
+    F: Fn(usize) -> Option<i64>,
+{
+    let word = word_at(address)?;
+    if word < 0 {
+        return None;
+    }
+    let opcode = Opcode::from_number(word % 100)?;
+    let mut modes = word / 100;
+    let mut params = Vec::with_capacity(opcode.arity());
+    for i in 0..opcode.arity() {
+        let mode = Mode::from_digit(modes % 10)?;
+        modes /= 10;
+        if Some(i) == opcode.write_param() && mode == Mode::Immediate {
+            return None;
+        }
+        params.push(Param {
+            mode,
+            value: word_at(address + 1 + i)?,
+        });
+    }
+    if modes != 0 {
+        return None;
+    }
+
+    Some(Instruction {
+        address,
+        opcode,
+        params,
+    })
+}
+
+/// Decodes every instruction reachable from address 0 by following fall through and
+/// immediate jump targets. Immediate values the program stores that point at decodable code,
+/// like return addresses pushed before a call, are followed as well, so long as they do not
+/// overlap code that was already found.
+pub fn reachable(program: &[i64]) -> BTreeMap<usize, Instruction> {
+    let mut found = BTreeMap::new();
+    let mut covered = BTreeSet::new();
+    trace_from(program, 0, &mut found, &mut covered);
+
+    loop {
+        let candidates: Vec<usize> = found
+            .values()
+            .filter(|instruction| {
+                instruction.opcode == Opcode::Add || instruction.opcode == Opcode::Multiply
+            })
+            .flat_map(|instruction| instruction.params[..2].to_vec())
+            .filter(|param| param.mode == Mode::Immediate && param.value > 0)
+            .map(|param| param.value as usize)
+            .filter(|address| *address < program.len() && !covered.contains(address))
+            .collect();
+
+        let before = found.len();
+        for address in candidates {
+            if !covered.contains(&address) {
+                trace_from(program, address, &mut found, &mut covered);
+            }
+        }
+        if found.len() == before {
+            break;
+        }
+    }
+
+    found
+}
+
+fn trace_from(
+    program: &[i64],
+    start: usize,
+    found: &mut BTreeMap<usize, Instruction>,
+    covered: &mut BTreeSet<usize>,
+) {
+    let mut work = vec![start];
+    while let Some(address) = work.pop() {
+        if found.contains_key(&address) {
+            continue;
+        }
+        let instruction = match decode(program, address) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        if (address..instruction.next()).any(|a| covered.contains(&a)) {
+            continue;
+        }
+        covered.extend(address..instruction.next());
+
+        match instruction.opcode {
+            Opcode::Halt => {}
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                let condition = instruction.params[0];
+                let target = instruction.params[1];
+                let always = condition.mode == Mode::Immediate
+                    && ((condition.value != 0) == (instruction.opcode == Opcode::JumpIfTrue));
+                if target.mode == Mode::Immediate && target.value >= 0 {
+                    work.push(target.value as usize);
+                }
+                if !always {
+                    work.push(instruction.next());
+                }
+            }
+            _ => work.push(instruction.next()),
+        }
+        found.insert(address, instruction);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_modes() {
+        let instruction = decode(&[21101, 11, 0, 0], 0).unwrap();
+        assert_eq!(instruction.opcode, Opcode::Add);
+        assert_eq!(format!("{}", instruction), "add #11, #0, [rb+0]");
+        assert_eq!(decode(&[11101, 1, 1, 1], 0), None);
+        assert_eq!(decode(&[42], 0), None);
+    }
+
+    #[test]
+    fn test_reachable_follows_calls_and_return_addresses() {
+        // main: push return address 11 at [rb+0], jump to 12, output, halt
+        // 12: return through [rb+0]
+        let program = vec![109, 20, 21101, 11, 0, 0, 1105, 1, 12, 4, 0, 99, 2106, 0, 0];
+        let found = reachable(&program);
+        let addresses: Vec<usize> = found.keys().cloned().collect();
+        assert_eq!(addresses, vec![0, 2, 6, 11, 12]);
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::VecDeque;
+
+use crate::{try_run_step, Fault, ProgramState};
+
+/// Something on the other end of a machine's input and output instructions
+pub trait Device {
+    /// Next value for an input instruction, or `None` to block until one is available
+    fn input(&mut self) -> Option<i64>;
+
+    fn output(&mut self, value: i64);
+}
+
+/// Feeds a fixed list of inputs and collects every output
+#[derive(Clone, Debug, Default)]
+pub struct BufferDevice {
+    pub inputs: VecDeque<i64>,
+    pub outputs: Vec<i64>,
+}
+
+impl BufferDevice {
+    pub fn new(inputs: Vec<i64>) -> Self {
+        BufferDevice {
+            inputs: inputs.into(),
+            outputs: Vec::new(),
+        }
+    }
+}
+
+impl Device for BufferDevice {
+    fn input(&mut self) -> Option<i64> {
+        self.inputs.pop_front()
+    }
+
+    fn output(&mut self, value: i64) {
+        self.outputs.push(value);
+    }
+}
+
+/// Runs the machine with `device` on the other end of its input and output instructions, until
+/// it halts or needs input the device hasn't got. Returns whether it halted.
+pub fn run_device<D: Device + ?Sized>(state: &mut ProgramState, device: &mut D) -> bool {
+    try_run_device(state, device).unwrap_or_else(|fault| panic!("{}", state.fault_report(&fault)))
+}
+
+pub fn try_run_device<D: Device + ?Sized>(
+    state: &mut ProgramState,
+    device: &mut D,
+) -> Result<bool, Fault> {
+    loop {
+        if state.finished {
+            return Ok(true);
+        }
+        if state.is_starving() {
+            match device.input() {
+                Some(value) => state.inputs.push(value),
+                None => return Ok(false),
+            }
+        }
+        if let (Some(output), _) = try_run_step(state, true)? {
+            device.output(output);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runs_until_the_device_runs_dry() {
+        // reads a number and prints it back until it reads 0
+        let echo = vec![3, 11, 1006, 11, 10, 4, 11, 1105, 1, 0, 99, 0];
+        let mut state = ProgramState::new(&echo, vec![]);
+        let mut device = BufferDevice::new(vec![4, 5]);
+        assert!(!run_device(&mut state, &mut device));
+        assert_eq!(device.outputs, vec![4, 5]);
+
+        device.inputs.push_back(0);
+        assert!(run_device(&mut state, &mut device));
+        assert_eq!(device.outputs, vec![4, 5]);
+    }
+}
@@ -0,0 +1,221 @@
+//! Differences between two snapshots of a machine.
+//!
+//! Clone a `ProgramState`, run it on, and diff the clone against it to see which cells the run
+//! touched. Changed cells are grouped into runs of neighbouring addresses, since a program's
+//! variables and arrays tend to sit together.
+
+use std::fmt;
+
+use crate::ProgramState;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CellChange {
+    pub address: usize,
+    pub old: i64,
+    pub new: i64,
+}
+
+/// Neighbouring cells that all changed
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub old: Vec<i64>,
+    pub new: Vec<i64>,
+}
+
+impl Span {
+    pub fn end(&self) -> usize {
+        self.start + self.old.len()
+    }
+
+    pub fn cells(&self) -> impl Iterator<Item = CellChange> + '_ {
+        self.old
+            .iter()
+            .zip(self.new.iter())
+            .enumerate()
+            .map(move |(i, (old, new))| CellChange {
+                address: self.start + i,
+                old: *old,
+                new: *new,
+            })
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let list = |values: &[i64]| {
+            values
+                .iter()
+                .map(i64::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        if self.old.len() == 1 {
+            write!(f, "[{}] {} -> {}", self.start, self.old[0], self.new[0])
+        } else {
+            write!(
+                f,
+                "[{}..{}] {} -> {}",
+                self.start,
+                self.end(),
+                list(&self.old),
+                list(&self.new)
+            )
+        }
+    }
+}
+
+/// Everything that differs between two snapshots. Registers that didn't change are `None`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Diff {
+    pub index: Option<(usize, usize)>,
+    pub relative_base: Option<(i64, i64)>,
+    pub finished: Option<(bool, bool)>,
+    pub pending_inputs: Option<(usize, usize)>,
+    pub spans: Vec<Span>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        *self == Diff::default()
+    }
+
+    /// Every changed cell, lowest address first
+    pub fn cells(&self) -> impl Iterator<Item = CellChange> + '_ {
+        self.spans.iter().flat_map(Span::cells)
+    }
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some((old, new)) = self.index {
+            writeln!(f, "ip {} -> {}", old, new)?;
+        }
+        if let Some((old, new)) = self.relative_base {
+            writeln!(f, "rb {} -> {}", old, new)?;
+        }
+        if let Some((old, new)) = self.finished {
+            writeln!(f, "finished {} -> {}", old, new)?;
+        }
+        if let Some((old, new)) = self.pending_inputs {
+            writeln!(f, "pending inputs {} -> {}", old, new)?;
+        }
+        for span in &self.spans {
+            writeln!(f, "{}", span)?;
+        }
+        Ok(())
+    }
+}
+
+fn changed<T: PartialEq + Copy>(old: T, new: T) -> Option<(T, T)> {
+    if old == new {
+        None
+    } else {
+        Some((old, new))
+    }
+}
+
+impl ProgramState {
+    /// What changed between this snapshot and `later`. Memory past the end of either one reads
+    /// as zero, the same as it would to the program.
+    pub fn diff(&self, later: &ProgramState) -> Diff {
+        let mut spans: Vec<Span> = Vec::new();
+        for address in 0..self.program.len().max(later.program.len()) {
+            let (old, new) = (self.program.get(address), later.program.get(address));
+            if old == new {
+                continue;
+            }
+            match spans.last_mut() {
+                Some(span) if span.end() == address => {
+                    span.old.push(old);
+                    span.new.push(new);
+                }
+                _ => spans.push(Span {
+                    start: address,
+                    old: vec![old],
+                    new: vec![new],
+                }),
+            }
+        }
+
+        Diff {
+            index: changed(self.index, later.index),
+            relative_base: changed(self.relative_base, later.relative_base),
+            finished: changed(self.finished, later.finished),
+            pending_inputs: changed(self.pending_inputs(), later.pending_inputs()),
+            spans,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_program, run_program, run_step};
+
+    #[test]
+    fn test_groups_neighbouring_cells() {
+        // writes 7 and 8 next to each other, 9 further on, then moves the base and halts
+        let program = vec![1101, 3, 4, 20, 1101, 4, 4, 21, 1101, 4, 5, 30, 109, 6, 99];
+        let before = ProgramState::new(&program, vec![]);
+        let mut after = before.clone();
+        run_program(&mut after, false, |_, _| false);
+
+        let diff = before.diff(&after);
+        assert_eq!(diff.index, Some((0, 14)));
+        assert_eq!(diff.relative_base, Some((0, 6)));
+        assert_eq!(diff.finished, Some((false, true)));
+        assert_eq!(diff.pending_inputs, None);
+        assert_eq!(
+            diff.cells().map(|cell| cell.address).collect::<Vec<_>>(),
+            vec![20, 21, 30]
+        );
+        assert_eq!(
+            diff.to_string(),
+            "ip 0 -> 14\nrb 0 -> 6\nfinished false -> true\n[20..22] 0, 0 -> 7, 8\n[30] 0 -> 9\n"
+        );
+        assert!(after.diff(&after).is_empty());
+    }
+
+    #[test]
+    fn test_arcade_state_after_one_move() {
+        let mut state = ProgramState::new(
+            &parse_program(include_str!("../../13/input.txt")).unwrap(),
+            vec![],
+        );
+        state.write(0, 2);
+        let run_to_input = |state: &mut ProgramState| {
+            while !state.is_starving() {
+                run_step(state, true);
+            }
+        };
+        run_to_input(&mut state);
+
+        let before = state.clone();
+        state.inputs.push(0);
+        run_step(&mut state, true);
+        run_to_input(&mut state);
+        let diff = before.diff(&state);
+
+        // back at the joystick, having read one input and moved the ball one step diagonally
+        assert_eq!(diff.index, None);
+        assert_eq!(diff.pending_inputs, None);
+        assert!(
+            diff.spans.contains(&Span {
+                start: 388,
+                old: vec![20, 21],
+                new: vec![21, 22]
+            }),
+            "{}",
+            diff
+        );
+        // and redrawn it on the screen, which is stored between the code and the stack
+        let ball: Vec<_> = diff
+            .cells()
+            .filter(|cell| (cell.address as i64) < state.relative_base)
+            .filter(|cell| cell.old == 4 || cell.new == 4)
+            .map(|cell| (cell.address, cell.old, cell.new))
+            .collect();
+        assert_eq!(ball, vec![(1604, 4, 0), (1650, 0, 4)]);
+    }
+}
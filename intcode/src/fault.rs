@@ -0,0 +1,83 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::protect::Protection;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fault {
+    InvalidOpcode {
+        ip: usize,
+        opcode: i64,
+    },
+    Overflow {
+        ip: usize,
+        opcode: i64,
+        lhs: i64,
+        rhs: i64,
+    },
+    /// A write to a negative address, or one past `MAX_ADDRESS`. With the `bigint` feature, also
+    /// a jump to one.
+    InvalidAddress {
+        ip: usize,
+        address: i64,
+    },
+    ProtectedRead {
+        ip: usize,
+        address: usize,
+    },
+    ProtectedWrite {
+        ip: usize,
+        address: usize,
+        protection: Protection,
+    },
+    ProtectedExecute {
+        ip: usize,
+        protection: Protection,
+    },
+}
+
+impl Fault {
+    pub fn ip(&self) -> usize {
+        match *self {
+            Fault::InvalidOpcode { ip, .. } => ip,
+            Fault::Overflow { ip, .. } => ip,
+            Fault::InvalidAddress { ip, .. } => ip,
+            Fault::ProtectedRead { ip, .. } => ip,
+            Fault::ProtectedWrite { ip, .. } => ip,
+            Fault::ProtectedExecute { ip, .. } => ip,
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Fault::InvalidOpcode { ip, opcode } => write!(f, "Invalid opcode {} at {}", opcode, ip),
+            Fault::Overflow {
+                ip,
+                opcode,
+                lhs,
+                rhs,
+            } => {
+                let symbol = if opcode == 2 { "*" } else { "+" };
+                write!(f, "Overflow at {}: {} {} {}", ip, lhs, symbol, rhs)
+            }
+            Fault::InvalidAddress { ip, address } => {
+                write!(f, "Invalid address {} used at {}", address, ip)
+            }
+            Fault::ProtectedRead { ip, address } => {
+                write!(f, "Read of no access memory at {} from {}", address, ip)
+            }
+            Fault::ProtectedWrite {
+                ip,
+                address,
+                protection,
+            } => write!(f, "Write to {} at {} from {}", protection, address, ip),
+            Fault::ProtectedExecute { ip, protection } => {
+                write!(f, "Execution of {} at {}", protection, ip)
+            }
+        }
+    }
+}
+
+impl Error for Fault {}
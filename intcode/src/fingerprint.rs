@@ -0,0 +1,346 @@
+//! Program fingerprints and puzzle family detection.
+//!
+//! Every player gets a different input for a day, but the inputs come out of the same compiler:
+//! the code is the same shape and only the constants and data differ. The compiler also picks at
+//! random between equivalent encodings, writing a copy as `add #0, x, y` or `mul #1, x, y` and a
+//! jump as `jnz #1, t` or `jz #0, t`. So programs are compared by the shape of their code with
+//! those choices smoothed out, not by their exact words.
+//!
+//! A family's signature is the shape of the first few instructions its programs run. That is the
+//! prologue of the compiled `main`, so it depends on how the puzzle's program is written, not on
+//! the constants a particular input was generated with.
+
+use std::fmt;
+
+use crate::decode::{self, Instruction, Mode, Opcode};
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// An instruction with its operand values dropped, and copies and unconditional jumps written
+/// the same way however they were encoded
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Shape {
+    pub mnemonic: &'static str,
+    pub modes: Vec<Mode>,
+}
+
+impl Shape {
+    pub fn of(instruction: &Instruction) -> Self {
+        let params = &instruction.params;
+        let modes: Vec<Mode> = params.iter().map(|param| param.mode).collect();
+        let is_constant =
+            |i: usize, value: i64| params[i].mode == Mode::Immediate && params[i].value == value;
+
+        let (mnemonic, modes) = match instruction.opcode {
+            Opcode::Add | Opcode::Multiply => {
+                let unit = if instruction.opcode == Opcode::Add {
+                    0
+                } else {
+                    1
+                };
+                if is_constant(0, unit) {
+                    ("mov", vec![modes[1], modes[2]])
+                } else if is_constant(1, unit) {
+                    ("mov", vec![modes[0], modes[2]])
+                } else {
+                    (instruction.opcode.mnemonic(), modes)
+                }
+            }
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                let taken = params[0].mode == Mode::Immediate
+                    && (params[0].value != 0) == (instruction.opcode == Opcode::JumpIfTrue);
+                if taken {
+                    ("jmp", vec![modes[1]])
+                } else {
+                    (instruction.opcode.mnemonic(), modes)
+                }
+            }
+            opcode => (opcode.mnemonic(), modes),
+        };
+        Shape { mnemonic, modes }
+    }
+}
+
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mnemonic)?;
+        for mode in &self.modes {
+            let letter = match mode {
+                Mode::Position => 'p',
+                Mode::Immediate => 'i',
+                Mode::Relative => 'r',
+            };
+            write!(f, " {}", letter)?;
+        }
+        Ok(())
+    }
+}
+
+/// The shapes of the instructions laid out from address 0, up to `limit` of them, stopping at
+/// the first word that doesn't decode
+pub fn prologue(program: &[i64], limit: usize) -> Vec<Shape> {
+    let mut shapes = Vec::new();
+    let mut address = 0;
+    while shapes.len() < limit {
+        match decode::decode(program, address) {
+            Some(instruction) => {
+                shapes.push(Shape::of(&instruction));
+                address = instruction.next();
+            }
+            None => break,
+        }
+    }
+    shapes
+}
+
+/// 64 bit FNV-1a of every word, the same only for identical images. Unlike the standard
+/// library's hasher it is the same on every run and platform, so it can be written down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Fingerprint(pub u64);
+
+impl Fingerprint {
+    pub fn of(program: &[i64]) -> Self {
+        let mut hash = FNV_OFFSET;
+        for word in program {
+            for byte in word.to_le_bytes().iter() {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(FNV_PRIME);
+            }
+        }
+        Fingerprint(hash)
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// The puzzle programs, one per day that uses the VM
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Family {
+    GravityAssist,
+    Diagnostics,
+    Amplifier,
+    Boost,
+    HullPainter,
+    Arcade,
+    RepairDroid,
+    Scaffold,
+    TractorBeam,
+    Springdroid,
+    Nic,
+    Cryostasis,
+}
+
+impl Family {
+    pub const ALL: [Family; 12] = [
+        Family::GravityAssist,
+        Family::Diagnostics,
+        Family::Amplifier,
+        Family::Boost,
+        Family::HullPainter,
+        Family::Arcade,
+        Family::RepairDroid,
+        Family::Scaffold,
+        Family::TractorBeam,
+        Family::Springdroid,
+        Family::Nic,
+        Family::Cryostasis,
+    ];
+
+    pub fn day(self) -> u32 {
+        match self {
+            Family::GravityAssist => 2,
+            Family::Diagnostics => 5,
+            Family::Amplifier => 7,
+            Family::Boost => 9,
+            Family::HullPainter => 11,
+            Family::Arcade => 13,
+            Family::RepairDroid => 15,
+            Family::Scaffold => 17,
+            Family::TractorBeam => 19,
+            Family::Springdroid => 21,
+            Family::Nic => 23,
+            Family::Cryostasis => 25,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Family::GravityAssist => "gravity assist",
+            Family::Diagnostics => "thermal environment diagnostics",
+            Family::Amplifier => "amplifier controller",
+            Family::Boost => "BOOST",
+            Family::HullPainter => "hull painting robot",
+            Family::Arcade => "arcade cabinet",
+            Family::RepairDroid => "repair droid",
+            Family::Scaffold => "vacuum robot",
+            Family::TractorBeam => "drone system",
+            Family::Springdroid => "springdroid",
+            Family::Nic => "network interface controller",
+            Family::Cryostasis => "droid text adventure",
+        }
+    }
+
+    /// Whether the program talks in lines of ASCII text, prompting for its input
+    pub fn is_ascii(self) -> bool {
+        matches!(
+            self,
+            Family::Scaffold | Family::Springdroid | Family::Cryostasis
+        )
+    }
+
+    /// How the family's programs start, which is enough to tell them apart
+    pub fn signature(self) -> &'static str {
+        match self {
+            // no I/O or stack, just the unrolled arithmetic on position operands
+            Family::GravityAssist => "add p p p; add p p p; add p p p; add p p p; mul p p p",
+            // reads the system ID, then goes on to write over its next instruction
+            Family::Diagnostics => "in p; add p p p",
+            // reads the phase setting and jumps through a table indexed by it
+            Family::Amplifier => "in p; add p i p; jmp p",
+            // checks that multiplying two constants gives the expected product before anything
+            // else, then sets up the stack
+            Family::Boost => "mul i i p; lt p i p; jnz p i; mov i p; arb i",
+            // reads the first panel colour and branches on it
+            Family::HullPainter => "in p; jnz p i; jmp i",
+            // halts unless a sum of two cells matches a constant, then sets up the stack
+            Family::Arcade => "add p p p; eq p i p; jnz p i; hlt; arb i",
+            // reads a movement command and compares it with each direction in turn
+            Family::RepairDroid => "in p; eq p i p; jnz p i; eq p i p",
+            // sets up the stack and stores two routine addresses before its main loop
+            Family::Scaffold => "add p p p; arb i; mov i p; mov i p",
+            // reads the x coordinate into a local and calls the beam test
+            Family::TractorBeam => "arb i; in r; mov i r; jmp i",
+            // calls the text printer with a message, then a second routine with no argument
+            Family::Springdroid => "arb i; mov i r; mov i r; jmp i; mov i r; jmp i",
+            // reads the network address, sets up the stack and jumps through a table
+            Family::Nic => "in p; add p i p; arb i; jmp p",
+            // calls the text printer with a message, then a second routine with one argument
+            Family::Cryostasis => "arb i; mov i r; mov i r; jmp i; mov i r; mov i r; jmp i",
+        }
+    }
+
+    fn matches(self, prologue: &[String]) -> bool {
+        let signature: Vec<&str> = self.signature().split("; ").collect();
+        prologue.len() >= signature.len() && signature.iter().zip(prologue).all(|(a, b)| a == b)
+    }
+
+    /// The family `program` belongs to, if it looks like any of them
+    pub fn classify(program: &[i64]) -> Option<Family> {
+        let prologue: Vec<String> = prologue(program, 16).iter().map(Shape::to_string).collect();
+        Family::ALL
+            .iter()
+            .cloned()
+            .filter(|family| family.matches(&prologue))
+            .max_by_key(|family| family.signature().len())
+    }
+
+    /// A warning if `program` is recognisably some other day's program
+    pub fn check(self, program: &[i64]) -> Option<String> {
+        match Family::classify(program) {
+            Some(found) if found != self => Some(format!(
+                "expected the day {} {} program, but this looks like the day {} {}",
+                self.day(),
+                self.name(),
+                found.day(),
+                found.name()
+            )),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Family {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "day {} {}", self.day(), self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_program;
+
+    fn puzzle(day: u32) -> Vec<i64> {
+        let text = match day {
+            2 => include_str!("../../2/input.txt"),
+            5 => include_str!("../../5/input.txt"),
+            7 => include_str!("../../7/input.txt"),
+            9 => include_str!("../../9/input.txt"),
+            11 => include_str!("../../11/input.txt"),
+            13 => include_str!("../../13/input.txt"),
+            15 => include_str!("../../15/input.txt"),
+            17 => include_str!("../../17/input.txt"),
+            19 => include_str!("../../19/input.txt"),
+            21 => include_str!("../../21/input.txt"),
+            23 => include_str!("../../23/input.txt"),
+            25 => include_str!("../../25/input.txt"),
+            _ => unreachable!(),
+        };
+        parse_program(text).unwrap()
+    }
+
+    #[test]
+    fn test_every_puzzle_is_recognised() {
+        for family in Family::ALL.iter() {
+            assert_eq!(
+                Family::classify(&puzzle(family.day())),
+                Some(*family),
+                "{}",
+                family
+            );
+        }
+        assert_eq!(Family::classify(&[1, 0, 0, 0, 99]), None);
+    }
+
+    #[test]
+    fn test_equivalent_encodings_share_a_shape() {
+        // copies through add #0 and mul #1, then jumps with jnz #1 and jz #0
+        let one = vec![1101, 0, 7, 20, 1105, 1, 8, 99, 99];
+        let other = vec![1102, 7, 1, 20, 1106, 0, 8, 99, 99];
+        let shapes: Vec<String> = prologue(&one, 3).iter().map(Shape::to_string).collect();
+        assert_eq!(shapes, vec!["mov i p", "jmp i", "hlt"]);
+        assert_eq!(prologue(&one, 3), prologue(&other, 3));
+
+        // fixed, so fingerprints can be written down and compared between runs
+        assert_eq!(
+            Fingerprint::of(&[1, 0, 0, 0, 99]),
+            Fingerprint(0xde83cb7efcdae427)
+        );
+        assert_ne!(Fingerprint::of(&one), Fingerprint::of(&other));
+    }
+
+    #[test]
+    fn test_signatures_ignore_constants() {
+        // another input for the same day differs in its constants and data, not its code
+        for family in Family::ALL.iter() {
+            let mut program = puzzle(family.day());
+            let mut address = 0;
+            while let Some(instruction) = decode::decode(&program, address) {
+                for (i, param) in instruction.params.iter().enumerate() {
+                    if param.mode == Mode::Immediate && param.value.abs() > 1 {
+                        program[address + 1 + i] += 2;
+                    }
+                }
+                address = instruction.next();
+            }
+            assert_eq!(Family::classify(&program), Some(*family), "{}", family);
+        }
+    }
+
+    #[test]
+    fn test_warns_about_the_wrong_day() {
+        let beam = puzzle(19);
+        assert_eq!(
+            Family::Arcade.check(&beam).unwrap(),
+            "expected the day 13 arcade cabinet program, but this looks like the day 19 drone system"
+        );
+        assert_eq!(Family::TractorBeam.check(&beam), None);
+        assert!(Family::classify(&puzzle(21)).unwrap().is_ascii());
+        assert!(!Family::classify(&puzzle(23)).unwrap().is_ascii());
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::VecDeque;
+
+use crate::calls::CallUndo;
+use crate::decode::Opcode;
+use crate::ProgramState;
+
+/// Everything one instruction changed, so it can be undone.
+#[derive(Clone, Debug)]
+struct Step {
+    index: usize,
+    relative_base: i64,
+    finished: bool,
+    memory_len: usize,
+    consumed_input: Option<i64>,
+    // whether the input came off the queue, and the value read before it
+    popped_input: bool,
+    last_input: Option<i64>,
+    // address and the written value it had before, if any
+    writes: Vec<(usize, Option<i64>)>,
+    calls: Option<CallUndo>,
+}
+
+/// Undo log kept by a `ProgramState` once `enable_history` is called.
+///
+/// Every `checkpoint_interval` steps a checkpoint is marked. Only `max_checkpoints` are kept,
+/// and undo entries older than the oldest checkpoint are dropped, which bounds both memory use
+/// and how far back a run can be rewound.
+#[derive(Clone, Debug)]
+pub struct History {
+    checkpoint_interval: usize,
+    max_checkpoints: usize,
+    steps: VecDeque<Step>,
+    checkpoints: VecDeque<usize>,
+    step_count: usize,
+    current: Option<Step>,
+}
+
+impl History {
+    pub fn new(checkpoint_interval: usize, max_checkpoints: usize) -> Self {
+        History {
+            checkpoint_interval: checkpoint_interval.max(1),
+            max_checkpoints: max_checkpoints.max(1),
+            steps: VecDeque::new(),
+            checkpoints: VecDeque::new(),
+            step_count: 0,
+            current: None,
+        }
+    }
+
+    /// Number of instructions executed, less the ones stepped back over
+    pub fn step_count(&self) -> usize {
+        self.step_count
+    }
+
+    /// How many instructions can currently be undone
+    pub fn available(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn checkpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.checkpoints.iter().cloned()
+    }
+
+    pub(crate) fn begin(&mut self, state: &ProgramState) {
+        self.current = Some(Step {
+            index: state.index,
+            relative_base: state.relative_base,
+            finished: state.finished,
+            memory_len: state.program.len(),
+            consumed_input: None,
+            popped_input: false,
+            last_input: state.inputs.last(),
+            writes: Vec::new(),
+            calls: None,
+        });
+    }
+
+    pub(crate) fn record_write(&mut self, address: usize, previous: Option<i64>) {
+        if let Some(step) = self.current.as_mut() {
+            step.writes.push((address, previous));
+        }
+    }
+
+    pub(crate) fn record_input(&mut self, value: i64, popped: bool) {
+        if let Some(step) = self.current.as_mut() {
+            step.consumed_input = Some(value);
+            step.popped_input = popped;
+        }
+    }
+
+    pub(crate) fn record_calls(&mut self, undo: CallUndo) {
+        if let Some(step) = self.current.as_mut() {
+            step.calls = Some(undo);
+        }
+    }
+
+    pub(crate) fn discard(&mut self) {
+        self.current = None;
+    }
+}
+
+impl ProgramState {
+    /// Starts recording an undo log so the machine can be stepped backwards
+    pub fn enable_history(&mut self, checkpoint_interval: usize, max_checkpoints: usize) {
+        self.history = Some(History::new(checkpoint_interval, max_checkpoints));
+    }
+
+    pub(crate) fn commit_step(&mut self) {
+        let history = match self.history.as_mut() {
+            Some(history) => history,
+            None => return,
+        };
+
+        if let Some(step) = history.current.take() {
+            history.steps.push_back(step);
+            history.step_count += 1;
+
+            if history.step_count % history.checkpoint_interval == 0 {
+                history.checkpoints.push_back(history.step_count);
+                if history.checkpoints.len() > history.max_checkpoints {
+                    history.checkpoints.pop_front();
+                    let oldest = history.checkpoints[0];
+                    while history.step_count - history.steps.len() < oldest {
+                        history.steps.pop_front();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Undoes the last instruction. Returns false when there is nothing left to undo.
+    ///
+    /// Inputs read are put back on the queue and taken off the transcript, and calls and returns
+    /// are undone on the call stack. Changes made from
+    /// outside the VM, like pushing more inputs, are not undone.
+    pub fn step_back(&mut self) -> bool {
+        let step = match self
+            .history
+            .as_mut()
+            .and_then(|history| history.steps.pop_back())
+        {
+            Some(step) => step,
+            None => return false,
+        };
+
+        for (address, previous) in step.writes.iter().rev() {
+            self.program.restore(*address, *previous);
+        }
+        self.program.truncate(step.memory_len);
+        self.index = step.index;
+        self.relative_base = step.relative_base;
+        if let Some(input) = step.consumed_input {
+            self.inputs
+                .untake(input, step.popped_input, step.last_input);
+        }
+        self.finished = step.finished;
+        if let (Some(undo), Some(calls)) = (step.calls, self.calls.as_mut()) {
+            calls.undo(undo);
+        }
+        if let Some(transcript) = self.transcript.as_mut() {
+            let output = self.program.get(self.index) % 100 == Opcode::Output.number();
+            transcript.undo(step.consumed_input.is_some(), output);
+        }
+
+        let history = self.history.as_mut().unwrap();
+        history.step_count -= 1;
+        while history
+            .checkpoints
+            .back()
+            .is_some_and(|step| *step > history.step_count)
+        {
+            history.checkpoints.pop_back();
+        }
+
+        true
+    }
+
+    /// Steps back until the instruction at `address` is about to run again.
+    /// Returns false if the log ran out first.
+    pub fn run_back_to(&mut self, address: usize) -> bool {
+        while self.step_back() {
+            if self.index == address {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Steps back to just before the most recent input instruction, returning the value it read
+    pub fn run_back_to_input(&mut self) -> Option<i64> {
+        loop {
+            let consumed = self
+                .history
+                .as_ref()
+                .and_then(|history| history.steps.back())
+                .map(|step| step.consumed_input)?;
+            self.step_back();
+            if consumed.is_some() {
+                return consumed;
+            }
+        }
+    }
+
+    /// Restores the most recent checkpoint taken at or before the current step
+    pub fn rewind_to_checkpoint(&mut self) -> bool {
+        let target = match self.history.as_ref().and_then(|h| h.checkpoints.back()) {
+            Some(step) => *step,
+            None => return false,
+        };
+        let current = self.history.as_ref().unwrap().step_count;
+        for _ in target..current {
+            self.step_back();
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{run_program, run_step, ProgramState};
+
+    // reads two inputs, outputs their sum, doubles it and outputs again
+    fn program() -> Vec<i64> {
+        vec![
+            3, 17, 3, 18, 1, 17, 18, 19, 4, 19, 2, 19, 20, 19, 4, 19, 99, 0, 0, 0, 2,
+        ]
+    }
+
+    #[test]
+    fn test_step_back_restores_memory_and_registers() {
+        let mut state = ProgramState::new(&program(), vec![3, 4]);
+        state.enable_history(4, 8);
+        let mut outputs = Vec::new();
+        for _ in 0..4 {
+            if let Some(output) = run_step(&mut state, false).0 {
+                outputs.push(output);
+            }
+        }
+        assert_eq!(outputs, vec![7]);
+        assert_eq!(state.index, 10);
+
+        assert!(state.run_back_to(4));
+        assert_eq!(state.program[19], 0);
+        assert_eq!(state.pending_inputs(), 0);
+
+        assert_eq!(state.run_back_to_input(), Some(4));
+        assert_eq!(state.index, 2);
+        assert_eq!(state.program[18], 0);
+        assert_eq!(state.pending_inputs(), 1);
+        assert_eq!(state.inputs.last(), Some(3));
+    }
+
+    #[test]
+    fn test_old_steps_are_dropped_past_the_oldest_checkpoint() {
+        let mut state = ProgramState::new(&[1101, 1, 1, 5, 1105, 1, 0], vec![]);
+        state.enable_history(10, 2);
+        for _ in 0..45 {
+            run_step(&mut state, false);
+        }
+
+        let history = state.history.as_ref().unwrap();
+        assert_eq!(history.checkpoints().collect::<Vec<_>>(), vec![30, 40]);
+        assert_eq!(history.available(), 15);
+
+        assert!(state.rewind_to_checkpoint());
+        assert_eq!(state.history.as_ref().unwrap().step_count(), 40);
+        assert!(!state.run_back_to(99));
+        assert_eq!(state.history.as_ref().unwrap().step_count(), 30);
+    }
+
+    #[test]
+    fn test_rerun_after_rewind_matches() {
+        let mut state = ProgramState::new(&program(), vec![3, 4]);
+        state.enable_history(100, 1);
+        let mut first = Vec::new();
+        run_program(&mut state, false, |_, output| {
+            first.push(output);
+            false
+        });
+        while state.step_back() {}
+        assert_eq!(state.index, 0);
+
+        let mut second = Vec::new();
+        run_program(&mut state, false, |_, output| {
+            second.push(output);
+            false
+        });
+        assert_eq!(first, second);
+    }
+}
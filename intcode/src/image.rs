@@ -0,0 +1,95 @@
+use std::ops::Deref;
+
+use crate::decode::{self, Instruction, Mode, Opcode, Param};
+
+/// A decoded instruction small enough to copy out of the image on every step
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Op {
+    pub opcode: Opcode,
+    pub params: [Param; 3],
+    pub len: usize,
+}
+
+impl From<&Instruction> for Op {
+    fn from(instruction: &Instruction) -> Self {
+        let mut params = [Param {
+            mode: Mode::Immediate,
+            value: 0,
+        }; 3];
+        params[..instruction.params.len()].copy_from_slice(&instruction.params);
+        Op {
+            opcode: instruction.opcode,
+            params,
+            len: instruction.len(),
+        }
+    }
+}
+
+/// A program as loaded, with its reachable instructions decoded ahead of time.
+/// Machines share one image and only decode on the fly where they have written over its code.
+#[derive(Debug)]
+pub struct Image {
+    words: Vec<i64>,
+    ops: Vec<Option<Op>>,
+    // for each word of compiled code, the address of the instruction it belongs to
+    owners: Vec<Option<usize>>,
+}
+
+impl Image {
+    pub fn new(words: Vec<i64>) -> Self {
+        let mut ops = vec![None; words.len()];
+        let mut owners = vec![None; words.len()];
+        for (address, instruction) in decode::reachable(&words) {
+            ops[address] = Some(Op::from(&instruction));
+            for owned in owners.iter_mut().skip(address).take(instruction.len()) {
+                *owned = Some(address);
+            }
+        }
+
+        Image { words, ops, owners }
+    }
+
+    pub fn words(&self) -> &[i64] {
+        &self.words
+    }
+
+    /// The precompiled instruction starting at `address`, if there is one
+    pub fn op(&self, address: usize) -> Option<Op> {
+        self.ops.get(address).cloned().unwrap_or(None)
+    }
+
+    /// Start of the compiled instruction covering `address`, if any
+    pub fn owner(&self, address: usize) -> Option<usize> {
+        self.owners.get(address).cloned().unwrap_or(None)
+    }
+}
+
+impl Deref for Image {
+    type Target = [i64];
+
+    fn deref(&self) -> &[i64] {
+        &self.words
+    }
+}
+
+impl From<Vec<i64>> for Image {
+    fn from(words: Vec<i64>) -> Self {
+        Image::new(words)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compiles_reachable_code_only() {
+        let image = Image::new(vec![1101, 1, 2, 7, 104, 7, 99, 0]);
+        assert_eq!(image.op(0).unwrap().opcode, Opcode::Add);
+        assert_eq!(image.op(4).unwrap().len, 2);
+        assert_eq!(image.op(1), None);
+        assert_eq!(image.op(7), None);
+        assert_eq!(image.owner(5), Some(4));
+        assert_eq!(image.owner(7), None);
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::VecDeque;
+
+/// Values waiting for a machine's input instructions.
+///
+/// Values are dropped once they are read, so feeding a long running machine doesn't grow it. When
+/// the queue runs dry the last value read is handed out again, unless input use is limited.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputQueue {
+    pending: VecDeque<i64>,
+    last: Option<i64>,
+}
+
+impl InputQueue {
+    pub fn new(values: Vec<i64>) -> Self {
+        InputQueue {
+            pending: values.into(),
+            last: None,
+        }
+    }
+
+    pub fn push(&mut self, value: i64) {
+        self.pending.push_back(value);
+    }
+
+    /// Number of values not read yet
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Drops the values not read yet
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+
+    /// The values not read yet, oldest first
+    pub fn pending(&self) -> impl Iterator<Item = i64> + '_ {
+        self.pending.iter().cloned()
+    }
+
+    /// The last value read
+    pub fn last(&self) -> Option<i64> {
+        self.last
+    }
+
+    /// Takes the next value, falling back on the last one read if `reuse_last` is set.
+    /// Returns the value and whether it came off the queue.
+    pub(crate) fn take(&mut self, reuse_last: bool) -> Option<(i64, bool)> {
+        match self.pending.pop_front() {
+            Some(value) => {
+                self.last = Some(value);
+                Some((value, true))
+            }
+            None if reuse_last => self.last.map(|value| (value, false)),
+            None => None,
+        }
+    }
+
+    /// Undoes a `take`
+    pub(crate) fn untake(&mut self, value: i64, popped: bool, last: Option<i64>) {
+        if popped {
+            self.pending.push_front(value);
+        }
+        self.last = last;
+    }
+}
+
+impl From<Vec<i64>> for InputQueue {
+    fn from(values: Vec<i64>) -> Self {
+        InputQueue::new(values)
+    }
+}
+
+impl Extend<i64> for InputQueue {
+    fn extend<I: IntoIterator<Item = i64>>(&mut self, values: I) {
+        self.pending.extend(values);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_consume_and_the_last_value_sticks() {
+        let mut queue = InputQueue::new(vec![1, 2]);
+        assert_eq!(queue.take(true), Some((1, true)));
+        queue.push(3);
+        assert_eq!(queue.pending().collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(queue.take(true), Some((2, true)));
+        assert_eq!(queue.take(true), Some((3, true)));
+        assert!(queue.is_empty());
+
+        assert_eq!(queue.take(false), None);
+        assert_eq!(queue.take(true), Some((3, false)));
+        assert_eq!(InputQueue::default().take(true), None);
+
+        queue.untake(3, true, Some(2));
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.last(), Some(2));
+    }
+}
@@ -0,0 +1,295 @@
+//! Which words of an image are code and which are data.
+//!
+//! A `Layout` starts from the instructions reachable from address 0 and the cells their position
+//! operands name, then adds whatever a `Profile` saw the program do at run time. Where the two
+//! disagree code wins over data, and writes win over reads, so a cell the program patches inside
+//! its own code still counts as code.
+//!
+//! A layout prints as one line per instruction or run of data, `4..8 instruction` or
+//! `380..386 data read`, and parses back from the same text, so it can be saved and shared
+//! between tools.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+use crate::decode::{self, Instruction, Mode};
+use crate::{ParseError, Profile};
+
+/// What a word is used for. Later variants win when a word is used more than one way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WordKind {
+    Unknown,
+    DataRead,
+    DataWritten,
+    Operand,
+    /// The first word of an instruction
+    Instruction,
+}
+
+impl WordKind {
+    pub fn is_code(self) -> bool {
+        self == WordKind::Instruction || self == WordKind::Operand
+    }
+
+    pub fn is_data(self) -> bool {
+        self == WordKind::DataRead || self == WordKind::DataWritten
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            WordKind::Unknown => "unknown",
+            WordKind::DataRead => "data read",
+            WordKind::DataWritten => "data written",
+            WordKind::Operand => "operand",
+            WordKind::Instruction => "instruction",
+        }
+    }
+}
+
+impl fmt::Display for WordKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Layout {
+    kinds: Vec<WordKind>,
+}
+
+impl Layout {
+    /// Labels every word of `program`, and any memory past its end the profile saw used
+    pub fn infer(program: &[i64], profile: Option<&Profile>) -> Self {
+        let mut layout = Layout {
+            kinds: vec![WordKind::Unknown; program.len()],
+        };
+
+        for instruction in decode::reachable(program).values() {
+            layout.mark_instruction(instruction.address, instruction.len());
+            for (i, param) in instruction.params.iter().enumerate() {
+                if param.mode != Mode::Position || param.value < 0 {
+                    continue;
+                }
+                let kind = if Some(i) == instruction.opcode.write_param() {
+                    WordKind::DataWritten
+                } else {
+                    WordKind::DataRead
+                };
+                layout.mark(param.value as usize, kind);
+            }
+        }
+
+        if let Some(profile) = profile {
+            for (address, access) in profile.iter() {
+                if access.executed > 0 {
+                    layout.mark(address, WordKind::Instruction);
+                }
+                if access.fetched > 0 {
+                    layout.mark(address, WordKind::Operand);
+                }
+                if access.written > 0 {
+                    layout.mark(address, WordKind::DataWritten);
+                }
+                if access.read > 0 {
+                    layout.mark(address, WordKind::DataRead);
+                }
+            }
+        }
+
+        layout
+    }
+
+    fn mark(&mut self, address: usize, kind: WordKind) {
+        if address >= self.kinds.len() {
+            self.kinds.resize(address + 1, WordKind::Unknown);
+        }
+        self.kinds[address] = self.kinds[address].max(kind);
+    }
+
+    fn mark_instruction(&mut self, address: usize, len: usize) {
+        self.mark(address, WordKind::Instruction);
+        for operand in address + 1..address + len {
+            self.mark(operand, WordKind::Operand);
+        }
+    }
+
+    /// Number of words labelled
+    pub fn len(&self) -> usize {
+        self.kinds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kinds.is_empty()
+    }
+
+    pub fn kind(&self, address: usize) -> WordKind {
+        self.kinds
+            .get(address)
+            .cloned()
+            .unwrap_or(WordKind::Unknown)
+    }
+
+    pub fn instruction_starts(&self) -> impl Iterator<Item = usize> + '_ {
+        self.kinds
+            .iter()
+            .enumerate()
+            .filter(|(_, kind)| **kind == WordKind::Instruction)
+            .map(|(address, _)| address)
+    }
+
+    /// The instructions at each instruction start, decoded from `program`. Code that only
+    /// appears once the program has written it can't be decoded from the image and is left out.
+    pub fn instructions(&self, program: &[i64]) -> BTreeMap<usize, Instruction> {
+        self.instruction_starts()
+            .filter_map(|address| decode::decode(program, address))
+            .map(|instruction| (instruction.address, instruction))
+            .collect()
+    }
+
+    /// Runs of words used the same way. An instruction is one region with its operands, and
+    /// neighbouring data of the same kind is merged.
+    pub fn regions(&self) -> Vec<(Range<usize>, WordKind)> {
+        let mut regions: Vec<(Range<usize>, WordKind)> = Vec::new();
+        for (address, kind) in self.kinds.iter().cloned().enumerate() {
+            match regions.last_mut() {
+                Some((range, WordKind::Instruction)) if kind == WordKind::Operand => {
+                    range.end = address + 1
+                }
+                Some((range, last))
+                    if *last == kind && kind != WordKind::Instruction && range.end == address =>
+                {
+                    range.end = address + 1
+                }
+                _ => regions.push((address..address + 1, kind)),
+            }
+        }
+        regions
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (range, kind) in self.regions() {
+            writeln!(f, "{}..{} {}", range.start, range.end, kind)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Layout {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Self, ParseError> {
+        let mut layout = Layout::default();
+        for (i, line) in text.lines().enumerate() {
+            let error = |column: usize, message: String| ParseError {
+                line: i + 1,
+                column: column + 1,
+                message,
+            };
+            let line = line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (range, name) = line.split_at(line.find(' ').unwrap_or(line.len()));
+            let name = name.trim_start();
+            let bounds: Vec<Option<usize>> = range.split("..").map(|n| n.parse().ok()).collect();
+            let (start, end) = match bounds[..] {
+                [Some(start), Some(end)] if start < end => (start, end),
+                _ => return Err(error(0, format!("bad range {:?}", range))),
+            };
+            if start != layout.kinds.len() {
+                let message = format!("expected a region starting at {}", layout.kinds.len());
+                return Err(error(0, message));
+            }
+            let kind = [
+                WordKind::Unknown,
+                WordKind::DataRead,
+                WordKind::DataWritten,
+                WordKind::Operand,
+                WordKind::Instruction,
+            ]
+            .iter()
+            .cloned()
+            .find(|kind| kind.name() == name)
+            .ok_or_else(|| error(range.len() + 1, format!("unknown kind {:?}", name)))?;
+
+            if kind == WordKind::Instruction {
+                layout.mark_instruction(start, end - start);
+            } else {
+                for address in start..end {
+                    layout.mark(address, kind);
+                }
+            }
+        }
+        Ok(layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_program, run_program, ProgramState};
+
+    #[test]
+    fn test_static_layout() {
+        // the day 2 example: add [9], [10] into [3], multiply [3] by [11] into [0], halt
+        let program = vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50];
+        let layout = Layout::infer(&program, None);
+        assert_eq!(layout.kind(0), WordKind::Instruction);
+        // written, but also the operand of the first instruction
+        assert_eq!(layout.kind(3), WordKind::Operand);
+        assert_eq!(layout.kind(9), WordKind::DataRead);
+        assert_eq!(layout.kind(12), WordKind::Unknown);
+        assert_eq!(
+            layout.to_string(),
+            "0..4 instruction\n4..8 instruction\n8..9 instruction\n9..12 data read\n"
+        );
+    }
+
+    #[test]
+    fn test_profile_finds_the_stack_and_jump_tables() {
+        let boost = parse_program(include_str!("../../9/input.txt")).unwrap();
+        let static_layout = Layout::infer(&boost, None);
+        let mut state = ProgramState::new(&boost, vec![1]);
+        state.enable_profile();
+        run_program(&mut state, false, |_, _| false);
+        let layout = Layout::infer(&boost, state.profile.as_ref());
+
+        // the relative base moves past the end of the image
+        assert!(layout.len() > boost.len());
+        assert!(layout.kind(layout.len() - 1).is_data());
+        // and code reached only through jumps read from memory shows up once it has run
+        assert!(layout.instruction_starts().count() >= static_layout.instruction_starts().count());
+        for address in static_layout.instruction_starts() {
+            assert_eq!(layout.kind(address), WordKind::Instruction);
+        }
+    }
+
+    #[test]
+    fn test_round_trips_through_text() {
+        let amplifier = parse_program(include_str!("../../7/input.txt")).unwrap();
+        let mut state = ProgramState::new(&amplifier, vec![0, 0]);
+        state.enable_profile();
+        run_program(&mut state, false, |_, _| false);
+        let layout = Layout::infer(&amplifier, state.profile.as_ref());
+
+        let text = layout.to_string();
+        assert_eq!(text.parse::<Layout>(), Ok(layout));
+        assert_eq!(
+            "0..2 instruction\n3..4 unknown".parse::<Layout>(),
+            Err(ParseError {
+                line: 2,
+                column: 1,
+                message: "expected a region starting at 2".to_string()
+            })
+        );
+        assert_eq!(
+            "0..1 code".parse::<Layout>().unwrap_err().to_string(),
+            "line 1, column 6: unknown kind \"code\""
+        );
+    }
+}
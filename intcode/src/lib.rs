@@ -1,207 +1,369 @@
+use std::sync::Arc;
+
+mod arithmetic;
+pub mod asm;
+pub mod batch;
+#[cfg(feature = "bigint")]
+pub mod big;
+pub mod bus;
+pub mod calls;
+pub mod compiler;
+pub mod decode;
+mod device;
+mod diff;
+mod fault;
+pub mod fingerprint;
+mod history;
+mod image;
+mod input;
+mod layout;
+mod memory;
+pub mod optimize;
+mod parse;
+pub mod peripherals;
+mod profile;
+mod protect;
+pub mod registry;
+pub mod scheduler;
+pub mod search;
+pub mod symbolic;
+pub mod testing;
+mod transcript;
+pub mod transpile;
+
+pub use arithmetic::ArithmeticMode;
+pub use bus::{Bus, Peripheral};
+pub use calls::{CallTracker, Frame};
+pub use device::{run_device, try_run_device, BufferDevice, Device};
+pub use diff::{CellChange, Diff, Span};
+pub use fault::Fault;
+pub use fingerprint::{Family, Fingerprint};
+pub use history::History;
+pub use image::{Image, Op};
+pub use input::InputQueue;
+pub use layout::{Layout, WordKind};
+pub use memory::{Memory, MAX_ADDRESS};
+pub use parse::{parse_program, ParseError};
+pub use profile::{Access, Coverage, Profile};
+pub use protect::{MemoryMap, Protection, Region, WriteEvent};
+pub use registry::OpcodeRegistry;
+pub use scheduler::Scheduler;
+pub use search::Search;
+pub use transcript::{Transcript, TranscriptEntry, TranscriptMode};
+
+use decode::{Mode, Opcode, Param};
+
 #[derive(Clone, Debug)]
 pub struct ProgramState {
-    pub program: Vec<i64>,
+    pub program: Memory,
     pub index: usize,
-    pub inputs: Vec<i64>,
+    pub inputs: InputQueue,
     pub finished: bool,
-    pub inputs_index: usize,
     pub relative_base: i64,
+    pub arithmetic: ArithmeticMode,
+    pub history: Option<History>,
+    pub extensions: Option<Arc<OpcodeRegistry>>,
+    pub calls: Option<CallTracker>,
+    pub memory_map: Option<MemoryMap>,
+    pub profile: Option<Profile>,
+    pub transcript: Option<Transcript>,
 }
 
 impl ProgramState {
-    pub fn new(base_program: &Vec<i64>, inputs: Vec<i64>) -> Self {
+    pub fn new(base_program: &[i64], inputs: Vec<i64>) -> Self {
+        ProgramState::from_image(&Arc::new(Image::new(base_program.to_vec())), inputs)
+    }
+
+    /// Starts a machine on a shared program image without copying or recompiling it
+    pub fn from_image(image: &Arc<Image>, inputs: Vec<i64>) -> Self {
         ProgramState {
-            program: base_program.clone(),
+            program: Memory::new(image.clone()),
             index: 0,
-            inputs,
+            inputs: inputs.into(),
             finished: false,
-            inputs_index: 0,
             relative_base: 0,
+            arithmetic: ArithmeticMode::default(),
+            history: None,
+            extensions: None,
+            calls: None,
+            memory_map: None,
+            profile: None,
+            transcript: None,
         }
     }
-}
 
-fn get_mode(instructions: &Vec<char>, index: usize) -> String {
-    if let Some(val) = instructions.get(index) {
-        val.to_string()
-    } else {
-        String::from("0")
+    pub fn read(&self, address: usize) -> i64 {
+        self.program.get(address)
     }
-}
 
-fn get_from_pos(numbers: &Vec<i64>, index: usize) -> i64 {
-    *numbers.get(index).unwrap_or(&0)
-}
-
-fn get_value(
-    numbers: &Vec<i64>,
-    state: &ProgramState,
-    offset: usize,
-    instructions: &Vec<char>,
-) -> i64 {
-    // we add one here, to skip the 2nd digit of the op code
-    let mode = get_mode(instructions, offset + 1);
-    match mode.as_ref() {
-        "0" => {
-            return get_from_pos(
-                numbers,
-                get_from_pos(numbers, state.index + offset) as usize,
-            );
+    /// Writes to memory the same way an instruction would, so the write can be undone.
+    /// Panics if the memory map protects the address.
+    pub fn write(&mut self, address: usize, value: i64) {
+        if let Err(fault) = self.try_write(address, value) {
+            panic!("{}", self.fault_report(&fault));
         }
-        "1" => return get_from_pos(numbers, state.index + offset),
-        "2" => {
-            return get_from_pos(
-                numbers,
-                (get_from_pos(numbers, state.index + offset) + state.relative_base) as usize,
-            )
+    }
+
+    pub fn try_write(&mut self, address: usize, value: i64) -> Result<(), Fault> {
+        if address > MAX_ADDRESS {
+            return Err(Fault::InvalidAddress {
+                ip: self.index,
+                address: address.min(i64::MAX as usize) as i64,
+            });
         }
-        _ => panic!(
-            "unrecognized mode in instructions {:?} for index {}",
-            instructions,
-            offset + 1
-        ),
+        insert_into_program(self, address, value)
+    }
+
+    /// Number of inputs given but not read yet
+    pub fn pending_inputs(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// Whether the next instruction is an input and nothing new has been given for it
+    pub fn is_starving(&self) -> bool {
+        !self.finished
+            && self.inputs.is_empty()
+            && self.program.get(self.index) % 100 == Opcode::Input.number()
     }
 }
 
-// used for opcodes where 0 and 1 always use immediate mode
-fn get_insert_value(
-    numbers: &Vec<i64>,
-    state: &ProgramState,
-    offset: usize,
-    instructions: &Vec<char>,
-) -> i64 {
-    // we add one here, to skip the 2nd digit of the op code
-    let mode = get_mode(instructions, offset + 1);
-    match mode.as_ref() {
-        "0" | "1" => return get_from_pos(numbers, state.index + offset),
-        "2" => return get_from_pos(numbers, state.index + offset) + state.relative_base,
-        _ => panic!(
-            "unrecognized mode in instructions {:?} for index {}",
-            instructions,
-            offset + 1
-        ),
+pub(crate) fn read_param(state: &ProgramState, param: Param) -> Result<i64, Fault> {
+    let address = match param.mode {
+        Mode::Position => param.value as usize,
+        Mode::Immediate => return Ok(param.value),
+        Mode::Relative => offset_base(state, param.value)? as usize,
+    };
+    if let Some(map) = state.memory_map.as_ref() {
+        map.check_read(state.index, address)?;
     }
+    Ok(state.program.get(address))
 }
 
-fn insert_into_program(program: &mut Vec<i64>, position: usize, value: i64) {
-    if position >= program.len() {
-        let len = position - program.len() + 1;
-        let mut append_vec = vec![0; len];
-        program.append(&mut append_vec);
+// position and relative parameters are addresses for the opcodes that write
+pub(crate) fn write_address(state: &ProgramState, param: Param) -> Result<usize, Fault> {
+    let address = match param.mode {
+        Mode::Relative => offset_base(state, param.value)?,
+        _ => param.value,
+    };
+    if address < 0 || address as u64 > MAX_ADDRESS as u64 {
+        return Err(Fault::InvalidAddress {
+            ip: state.index,
+            address,
+        });
     }
+    Ok(address as usize)
+}
 
-    program[position] = value;
+// relative addresses and opcode 9 use the same arithmetic as opcodes 1 and 2, so in checked
+// mode leaving the i64 range is an overflow of whichever instruction did it
+fn offset_base(state: &ProgramState, offset: i64) -> Result<i64, Fault> {
+    let base = state.relative_base;
+    match state.arithmetic.add(state.index, base, offset) {
+        Ok(sum) => Ok(sum),
+        Err(_) => Err(Fault::Overflow {
+            ip: state.index,
+            opcode: state.program.get(state.index) % 100,
+            lhs: base,
+            rhs: offset,
+        }),
+    }
 }
 
-pub fn get_base_program(text: &String) -> Vec<i64> {
-    text.split(",")
-        .map(|n| n.parse().expect("Could not parse number"))
-        .collect()
+fn insert_into_program(state: &mut ProgramState, position: usize, value: i64) -> Result<(), Fault> {
+    if let Some(map) = state.memory_map.as_mut() {
+        map.check_write(state.index, position)?;
+        map.record_write(state.index, position, state.program.get(position), value);
+    }
+    if let Some(history) = state.history.as_mut() {
+        history.record_write(position, state.program.written_at(position));
+    }
+    state.program.set(position, value);
+    Ok(())
+}
+
+pub fn get_base_program(text: &str) -> Vec<i64> {
+    parse_program(text).unwrap_or_else(|error| panic!("{}", error))
 }
 
 pub fn run_step(state: &mut ProgramState, limit_input_use: bool) -> (Option<i64>, bool) {
-    let instructions_string = format!("{}", state.program[state.index]);
-    let mut instructions: Vec<char> = instructions_string.chars().collect();
-    instructions.reverse();
-
-    let op_code = instructions[0].to_digit(10).unwrap();
-
-    match op_code {
-        1 => {
-            let sum = get_value(&state.program, &state, 1, &instructions)
-                + get_value(&state.program, &state, 2, &instructions);
-
-            let sum_position = get_insert_value(&state.program, &state, 3, &instructions);
-            insert_into_program(&mut state.program, sum_position as usize, sum);
-            state.index += 4;
-        }
-        2 => {
-            let product = get_value(&state.program, &state, 1, &instructions)
-                * get_value(&state.program, &state, 2, &instructions);
-            let product_position = get_insert_value(&state.program, &state, 3, &instructions);
-            insert_into_program(&mut state.program, product_position as usize, product);
-            state.index += 4;
-        }
-        3 => {
-            let value_pos = get_insert_value(&state.program, &state, 1, &instructions) as usize;
-            if state.inputs_index >= state.inputs.len() && limit_input_use {
-                return (None, true);
-            }
-            let input = state
-                .inputs
-                .get(state.inputs_index)
-                .unwrap_or(state.inputs.last().unwrap());
+    try_run_step(state, limit_input_use)
+        .unwrap_or_else(|fault| panic!("{}", state.fault_report(&fault)))
+}
 
-            insert_into_program(&mut state.program, value_pos, *input);
+pub fn try_run_step(
+    state: &mut ProgramState,
+    limit_input_use: bool,
+) -> Result<(Option<i64>, bool), Fault> {
+    if let Some(mut history) = state.history.take() {
+        history.begin(state);
+        state.history = Some(history);
 
-            if state.inputs_index < state.inputs.len() {
-                state.inputs_index += 1;
+        let start = state.index;
+        let result = execute_step(state, limit_input_use);
+        match result {
+            // blocked on input, nothing ran
+            Ok((None, true)) if !state.finished && state.index == start => {
+                state.history.as_mut().unwrap().discard()
             }
-            state.index += 2;
-        }
-        4 => {
-            let output = get_value(&state.program, &state, 1, &instructions);
-            state.index += 2;
-            return (Some(output), false);
-        }
-        5 => {
-            if get_value(&state.program, &state, 1, &instructions) != 0 {
-                state.index = get_value(&state.program, &state, 2, &instructions) as usize;
-            } else {
-                state.index += 3;
+            Ok(_) => state.commit_step(),
+            Err(_) => state.history.as_mut().unwrap().discard(),
+        }
+
+        return result;
+    }
+
+    execute_step(state, limit_input_use)
+}
+
+fn execute_step(
+    state: &mut ProgramState,
+    limit_input_use: bool,
+) -> Result<(Option<i64>, bool), Fault> {
+    if let Some(map) = state.memory_map.as_ref() {
+        map.check_execute(state.index)?;
+    }
+
+    let op = match state.program.compiled(state.index) {
+        Some(op) => op,
+        // written over since the image was compiled, or never reached by the static decode
+        None => {
+            let program = &state.program;
+            match decode::decode_with(|a| Some(program.get(a)), state.index) {
+                Some(instruction) => Op::from(&instruction),
+                None => {
+                    if let Some(registry) = state.extensions.clone() {
+                        if let Some(result) = registry::execute(state, &registry) {
+                            return result;
+                        }
+                    }
+                    return Err(Fault::InvalidOpcode {
+                        ip: state.index,
+                        opcode: state.program.get(state.index),
+                    });
+                }
             }
         }
-        6 => {
-            if get_value(&state.program, &state, 1, &instructions) == 0 {
-                state.index = get_value(&state.program, &state, 2, &instructions) as usize;
-            } else {
-                state.index += 3;
+    };
+
+    if state.calls.is_some() || state.profile.is_some() {
+        let (ip, relative_base) = (state.index, state.relative_base);
+        let result = execute_op(state, op, limit_input_use);
+        calls::observe(state, &op, ip, relative_base);
+        profile::observe(state, &op, ip, relative_base, &result);
+        return result;
+    }
+
+    execute_op(state, op, limit_input_use)
+}
+
+fn execute_op(
+    state: &mut ProgramState,
+    op: Op,
+    limit_input_use: bool,
+) -> Result<(Option<i64>, bool), Fault> {
+    let params = op.params;
+
+    match op.opcode {
+        Opcode::Add => {
+            let sum = state.arithmetic.add(
+                state.index,
+                read_param(state, params[0])?,
+                read_param(state, params[1])?,
+            )?;
+            let sum_position = write_address(state, params[2])?;
+            insert_into_program(state, sum_position, sum)?;
+        }
+        Opcode::Multiply => {
+            let product = state.arithmetic.mul(
+                state.index,
+                read_param(state, params[0])?,
+                read_param(state, params[1])?,
+            )?;
+            let product_position = write_address(state, params[2])?;
+            insert_into_program(state, product_position, product)?;
+        }
+        Opcode::Input => {
+            let value_pos = write_address(state, params[0])?;
+            // check before the input is taken, so a fault doesn't lose it
+            if let Some(map) = state.memory_map.as_ref() {
+                map.check_write(state.index, value_pos)?;
+            }
+            let (input, popped) = match state.inputs.take(!limit_input_use) {
+                Some(input) => input,
+                None => return Ok((None, true)),
+            };
+
+            if let Some(history) = state.history.as_mut() {
+                history.record_input(input, popped);
+            }
+            insert_into_program(state, value_pos, input)?;
+            if let Some(transcript) = state.transcript.as_mut() {
+                transcript.record_input(input);
             }
         }
-        7 => {
-            let pos = get_insert_value(&state.program, &state, 3, &instructions) as usize;
-            if get_value(&state.program, &state, 1, &instructions)
-                < get_value(&state.program, &state, 2, &instructions)
-            {
-                insert_into_program(&mut state.program, pos, 1);
-            } else {
-                insert_into_program(&mut state.program, pos, 0);
+        Opcode::Output => {
+            let output = read_param(state, params[0])?;
+            if let Some(transcript) = state.transcript.as_mut() {
+                transcript.record_output();
             }
-            state.index += 4;
-        }
-        8 => {
-            let pos = get_insert_value(&state.program, &state, 3, &instructions) as usize;
-            if get_value(&state.program, &state, 1, &instructions)
-                == get_value(&state.program, &state, 2, &instructions)
-            {
-                insert_into_program(&mut state.program, pos, 1);
-            } else {
-                insert_into_program(&mut state.program, pos, 0);
+            state.index += op.len;
+            return Ok((Some(output), false));
+        }
+        Opcode::JumpIfTrue => {
+            if read_param(state, params[0])? != 0 {
+                state.index = read_param(state, params[1])? as usize;
+                return Ok((None, false));
             }
-            state.index += 4;
-        }
-        9 => {
-            let second_digit = instructions.get(1).unwrap_or(&'0');
-            if *second_digit == '9' {
-                state.finished = true;
-                return (None, true);
-            } else {
-                state.relative_base += get_value(&state.program, &state, 1, &instructions);
+        }
+        Opcode::JumpIfFalse => {
+            if read_param(state, params[0])? == 0 {
+                state.index = read_param(state, params[1])? as usize;
+                return Ok((None, false));
             }
-            state.index += 2;
         }
-        _ => panic!("Invalid opcode {} at {}", op_code, state.index),
+        Opcode::LessThan => {
+            let pos = write_address(state, params[2])?;
+            let value = read_param(state, params[0])? < read_param(state, params[1])?;
+            insert_into_program(state, pos, value as i64)?;
+        }
+        Opcode::Equals => {
+            let pos = write_address(state, params[2])?;
+            let value = read_param(state, params[0])? == read_param(state, params[1])?;
+            insert_into_program(state, pos, value as i64)?;
+        }
+        Opcode::AdjustBase => {
+            let adjustment = read_param(state, params[0])?;
+            state.relative_base = offset_base(state, adjustment)?;
+        }
+        Opcode::Halt => {
+            state.finished = true;
+            return Ok((None, true));
+        }
     }
 
-    (None, false)
+    state.index += op.len;
+    Ok((None, false))
 }
 
-pub fn run_program<F>(state: &mut ProgramState, limit_input_use: bool, mut handle_output: F)
+pub fn run_program<F>(state: &mut ProgramState, limit_input_use: bool, handle_output: F)
+where
+    F: FnMut(&mut ProgramState, i64) -> bool,
+{
+    if let Err(fault) = try_run_program(state, limit_input_use, handle_output) {
+        panic!("{}", state.fault_report(&fault));
+    }
+}
+
+pub fn try_run_program<F>(
+    state: &mut ProgramState,
+    limit_input_use: bool,
+    mut handle_output: F,
+) -> Result<(), Fault>
 where
     F: FnMut(&mut ProgramState, i64) -> bool,
 {
     loop {
-        let result = run_step(state, limit_input_use);
+        let result = try_run_step(state, limit_input_use)?;
         if let Some(output) = result.0 {
             let exit_now = handle_output(state, output);
             if exit_now {
@@ -212,4 +374,145 @@ where
             break;
         }
     }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outputs_of(program: &[i64], arithmetic: ArithmeticMode) -> Result<Vec<i64>, Fault> {
+        let mut state = ProgramState::new(program, vec![]);
+        state.arithmetic = arithmetic;
+        let mut outputs = Vec::new();
+        try_run_program(&mut state, false, |_, output| {
+            outputs.push(output);
+            false
+        })?;
+
+        Ok(outputs)
+    }
+
+    #[test]
+    fn test_large_product_is_the_same_in_every_mode() {
+        let program = vec![1102, 34915192, 34915192, 7, 4, 7, 99, 0];
+        assert_eq!(
+            outputs_of(&program, ArithmeticMode::Wrapping),
+            Ok(vec![1219070632396864])
+        );
+        assert_eq!(
+            outputs_of(&program, ArithmeticMode::Checked),
+            Ok(vec![1219070632396864])
+        );
+    }
+
+    #[test]
+    fn test_checked_overflow_reports_ip_and_operands() {
+        let program = vec![1101, 0, 0, 3, 1102, i64::MAX, 3, 9, 99, 0];
+        assert_eq!(
+            outputs_of(&program, ArithmeticMode::Checked),
+            Err(Fault::Overflow {
+                ip: 4,
+                opcode: 2,
+                lhs: i64::MAX,
+                rhs: 3,
+            })
+        );
+        assert!(outputs_of(&program, ArithmeticMode::Wrapping).is_ok());
+    }
+
+    #[test]
+    fn test_immediate_writes_and_extra_modes_are_invalid() {
+        // the old interpreter wrote to [4] for both
+        let immediate_write = vec![11101, 1, 2, 4, 99];
+        let extra_mode = vec![101101, 1, 2, 4, 99];
+        for program in &[immediate_write, extra_mode] {
+            assert_eq!(
+                outputs_of(program, ArithmeticMode::Wrapping),
+                Err(Fault::InvalidOpcode {
+                    ip: 0,
+                    opcode: program[0],
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_writes_to_negative_and_far_addresses_fault() {
+        let negative = vec![1101, 1, 2, -1, 99];
+        let relative = vec![109, -5, 21101, 1, 2, 0, 99];
+        let far = vec![1101, 1, 2, 1 << 40, 99];
+        for (program, address) in &[(negative, -1), (relative, -5), (far, 1 << 40)] {
+            let ip = if program[0] == 109 { 2 } else { 0 };
+            assert_eq!(
+                outputs_of(program, ArithmeticMode::Wrapping),
+                Err(Fault::InvalidAddress {
+                    ip,
+                    address: *address,
+                })
+            );
+        }
+
+        let mut state = ProgramState::new(&[99], vec![]);
+        assert!(state.try_write(usize::MAX, 1).is_err());
+        assert_eq!(state.program.len(), 1);
+    }
+
+    #[test]
+    fn test_checked_overflow_of_the_relative_base() {
+        let adjust = vec![109, i64::MAX, 109, 1, 99];
+        assert_eq!(
+            outputs_of(&adjust, ArithmeticMode::Checked),
+            Err(Fault::Overflow {
+                ip: 2,
+                opcode: 9,
+                lhs: i64::MAX,
+                rhs: 1,
+            })
+        );
+        assert!(outputs_of(&adjust, ArithmeticMode::Wrapping).is_ok());
+
+        let relative_output = vec![109, i64::MAX, 204, 1, 99];
+        assert_eq!(
+            outputs_of(&relative_output, ArithmeticMode::Checked),
+            Err(Fault::Overflow {
+                ip: 2,
+                opcode: 4,
+                lhs: i64::MAX,
+                rhs: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn test_inputs_are_consumed_and_starving_is_reported() {
+        // echoes inputs forever
+        let program = vec![3, 5, 4, 5, 1105, 1, 0];
+        let mut state = ProgramState::new(&program, vec![1, 2]);
+        let mut outputs = Vec::new();
+        run_program(&mut state, true, |_, output| {
+            outputs.push(output);
+            false
+        });
+        assert_eq!(outputs, vec![1, 2]);
+        assert!(state.is_starving());
+        assert_eq!(state.pending_inputs(), 0);
+
+        state.inputs.extend(vec![3, 4, 5]);
+        assert!(!state.is_starving());
+        assert_eq!(state.pending_inputs(), 3);
+        assert_eq!(run_step(&mut state, true), (None, false));
+        assert_eq!(state.pending_inputs(), 2);
+
+        // without the limit the last value read keeps coming
+        let mut state = ProgramState::new(&program, vec![7]);
+        let mut outputs = Vec::new();
+        run_program(&mut state, false, |_, output| {
+            outputs.push(output);
+            outputs.len() == 3
+        });
+        assert_eq!(outputs, vec![7, 7, 7]);
+        assert!(state.inputs.is_empty());
+    }
 }
@@ -0,0 +1,180 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasherDefault, Hasher};
+use std::ops::Index;
+use std::sync::Arc;
+
+use crate::image::{Image, Op};
+
+/// Addresses are already well spread integers, so the overlay skips SipHash on every access
+#[derive(Default)]
+struct AddressHasher(u64);
+
+impl Hasher for AddressHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 << 8 | *byte as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        }
+    }
+
+    fn write_usize(&mut self, address: usize) {
+        self.0 = (address as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    }
+}
+
+/// Highest address a program may write. Snapshots and diffs walk memory up to its length, so one
+/// stray write far past the program would make them crawl.
+pub const MAX_ADDRESS: usize = (1 << 24) - 1;
+
+type Overlay = HashMap<usize, i64, BuildHasherDefault<AddressHasher>>;
+
+/// Program memory backed by a shared, immutable image plus the cells this machine has written.
+/// Cloning only copies the written cells, so many machines can start from one image cheaply.
+#[derive(Clone, Debug)]
+pub struct Memory {
+    image: Arc<Image>,
+    overlay: Overlay,
+    len: usize,
+    // compiled instructions this machine has written over
+    stale: HashSet<usize>,
+}
+
+impl Memory {
+    pub fn new(image: Arc<Image>) -> Self {
+        let len = image.len();
+        Memory {
+            image,
+            overlay: Overlay::default(),
+            len,
+            stale: HashSet::new(),
+        }
+    }
+
+    pub fn image(&self) -> &Arc<Image> {
+        &self.image
+    }
+
+    /// The image's precompiled instruction at `address`, unless this machine wrote over it
+    pub fn compiled(&self, address: usize) -> Option<Op> {
+        if !self.stale.is_empty() && self.stale.contains(&address) {
+            return None;
+        }
+        self.image.op(address)
+    }
+
+    /// Addresses written since the image was loaded, along with their current values
+    pub fn written(&self) -> impl Iterator<Item = (usize, i64)> + '_ {
+        self.overlay
+            .iter()
+            .map(|(address, value)| (*address, *value))
+    }
+
+    /// Value at `address` if this machine has written it
+    pub fn written_at(&self, address: usize) -> Option<i64> {
+        self.overlay.get(&address).cloned()
+    }
+
+    pub fn get(&self, address: usize) -> i64 {
+        self[address]
+    }
+
+    /// Writes a cell directly. Outside the crate writes go through `ProgramState::write`, which
+    /// also checks the memory map and records the write in the history.
+    pub(crate) fn set(&mut self, address: usize, value: i64) {
+        self.len = self.len.max(address.saturating_add(1));
+        self.overlay.insert(address, value);
+        if let Some(owner) = self.image.owner(address) {
+            self.stale.insert(owner);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Drops every written cell, returning to the original image
+    pub fn reset(&mut self) {
+        self.overlay.clear();
+        self.stale.clear();
+        self.len = self.image.len();
+    }
+
+    /// Puts back a written cell as it was before a write, `None` meaning it was never written
+    pub(crate) fn restore(&mut self, address: usize, previous: Option<i64>) {
+        match previous {
+            Some(value) => self.overlay.insert(address, value),
+            None => self.overlay.remove(&address),
+        };
+    }
+
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.len = len;
+    }
+
+    pub fn to_vec(&self) -> Vec<i64> {
+        (0..self.len).map(|address| self[address]).collect()
+    }
+}
+
+impl From<Vec<i64>> for Memory {
+    fn from(program: Vec<i64>) -> Self {
+        Memory::new(Arc::new(Image::new(program)))
+    }
+}
+
+impl Index<usize> for Memory {
+    type Output = i64;
+
+    fn index(&self, address: usize) -> &i64 {
+        match self.overlay.get(&address) {
+            Some(value) => value,
+            None => self.image.get(address).unwrap_or(&0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_the_image() {
+        let mut memory = Memory::from(vec![1, 2, 3]);
+        let mut copy = memory.clone();
+        copy.set(1, 20);
+        memory.set(5, 6);
+
+        assert!(Arc::ptr_eq(memory.image(), copy.image()));
+        assert_eq!(memory.to_vec(), vec![1, 2, 3, 0, 0, 6]);
+        assert_eq!(copy.to_vec(), vec![1, 20, 3]);
+        assert_eq!(copy.written().count(), 1);
+    }
+
+    #[test]
+    fn test_writing_over_code_skips_the_compiled_op() {
+        let mut memory = Memory::from(vec![1101, 1, 2, 7, 104, 7, 99, 0]);
+        memory.set(7, 3);
+        assert!(memory.compiled(4).is_some());
+
+        memory.set(5, 0);
+        assert!(memory.compiled(4).is_none());
+        assert!(memory.compiled(0).is_some());
+    }
+
+    #[test]
+    fn test_reset_returns_to_image() {
+        let mut memory = Memory::from(vec![1, 2, 3]);
+        memory.set(10, 1);
+        memory.reset();
+
+        assert_eq!(memory.len(), 3);
+        assert_eq!(memory.get(10), 0);
+    }
+}
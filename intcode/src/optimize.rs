@@ -0,0 +1,579 @@
+//! Peephole optimizer over assembler items.
+//!
+//! Instructions can be removed safely because every address in the items is a label. Raw programs
+//! go through `lift` first, which only accepts programs where every address can be turned into
+//! one: no writes into code, no relative addressing and no jumps through memory.
+
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+
+use crate::asm::{self, Item, Operand, Value};
+use crate::decode::{Mode, Opcode};
+use crate::Layout;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Report {
+    pub instructions_before: usize,
+    pub instructions_after: usize,
+    pub words_before: usize,
+    pub words_after: usize,
+    /// Arithmetic and comparisons on immediates replaced by their result
+    pub folded: usize,
+    /// Multiplications by one and additions of zero turned into plain moves
+    pub moves: usize,
+    /// Jumps to the next instruction and conditional jumps that are never taken
+    pub jumps_removed: usize,
+    /// Instructions after an unconditional jump or halt that nothing jumps to
+    pub dead_removed: usize,
+}
+
+impl Report {
+    pub fn instructions_saved(&self) -> usize {
+        self.instructions_before - self.instructions_after
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} -> {} instructions ({} -> {} words): {} folded, {} moves, {} jumps and {} dead instructions removed",
+            self.instructions_before,
+            self.instructions_after,
+            self.words_before,
+            self.words_after,
+            self.folded,
+            self.moves,
+            self.jumps_removed,
+            self.dead_removed
+        )
+    }
+}
+
+fn count(items: &[Item]) -> (usize, usize) {
+    let instructions = items
+        .iter()
+        .filter(|item| matches!(**item, Item::Instruction(..)))
+        .count();
+    let words = items.iter().map(Item::len).sum();
+    (instructions, words)
+}
+
+fn constant(operand: &Operand) -> Option<i64> {
+    match *operand {
+        Operand {
+            mode: Mode::Immediate,
+            value: Value::Number(value),
+        } => Some(value),
+        _ => None,
+    }
+}
+
+fn is_move(opcode: Opcode, operands: &[Operand]) -> bool {
+    opcode == Opcode::Add && constant(&operands[1]) == Some(0)
+}
+
+/// Optimizes until nothing changes
+pub fn optimize(items: &[Item]) -> (Vec<Item>, Report) {
+    let mut report = Report::default();
+    let (instructions, words) = count(items);
+    report.instructions_before = instructions;
+    report.words_before = words;
+
+    let mut items = items.to_vec();
+    loop {
+        let before = report.clone();
+        items = simplify(items, &mut report);
+        items = remove_jumps(items, &mut report);
+        items = remove_dead(items, &mut report);
+        if report == before {
+            break;
+        }
+    }
+
+    let (instructions, words) = count(&items);
+    report.instructions_after = instructions;
+    report.words_after = words;
+    (items, report)
+}
+
+// folds constants and rewrites moves, one instruction at a time
+fn simplify(items: Vec<Item>, report: &mut Report) -> Vec<Item> {
+    let mut simplified = Vec::with_capacity(items.len());
+    for item in items {
+        let (opcode, mut operands) = match item {
+            Item::Instruction(opcode, operands) => (opcode, operands),
+            other => {
+                simplified.push(other);
+                continue;
+            }
+        };
+
+        match opcode {
+            Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals => {
+                if let (Some(a), Some(b)) = (constant(&operands[0]), constant(&operands[1])) {
+                    let result = match opcode {
+                        Opcode::Add => a.wrapping_add(b),
+                        Opcode::Multiply => a.wrapping_mul(b),
+                        Opcode::LessThan => (a < b) as i64,
+                        _ => (a == b) as i64,
+                    };
+                    let folded = vec![
+                        Operand::immediate(result),
+                        Operand::immediate(0),
+                        operands[2].clone(),
+                    ];
+                    if opcode != Opcode::Add || operands != folded {
+                        report.folded += 1;
+                        operands = folded;
+                    }
+                    simplified.push(Item::Instruction(Opcode::Add, operands));
+                    continue;
+                }
+
+                let kept = match opcode {
+                    Opcode::Add if constant(&operands[1]) == Some(0) => Some(operands[0].clone()),
+                    Opcode::Add if constant(&operands[0]) == Some(0) => Some(operands[1].clone()),
+                    Opcode::Multiply if constant(&operands[1]) == Some(1) => {
+                        Some(operands[0].clone())
+                    }
+                    Opcode::Multiply if constant(&operands[0]) == Some(1) => {
+                        Some(operands[1].clone())
+                    }
+                    _ => None,
+                };
+                if let Some(kept) = kept {
+                    // a cell moved onto itself
+                    if kept.mode != Mode::Immediate && kept == operands[2] {
+                        report.moves += 1;
+                        continue;
+                    }
+                    if !is_move(opcode, &operands) {
+                        report.moves += 1;
+                        operands = vec![kept, Operand::immediate(0), operands[2].clone()];
+                    }
+                    simplified.push(Item::Instruction(Opcode::Add, operands));
+                    continue;
+                }
+                if opcode == Opcode::Multiply
+                    && (constant(&operands[0]) == Some(0) || constant(&operands[1]) == Some(0))
+                {
+                    report.folded += 1;
+                    operands = vec![
+                        Operand::immediate(0),
+                        Operand::immediate(0),
+                        operands[2].clone(),
+                    ];
+                    simplified.push(Item::Instruction(Opcode::Add, operands));
+                    continue;
+                }
+            }
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                if let Some(condition) = constant(&operands[0]) {
+                    let taken = (condition != 0) == (opcode == Opcode::JumpIfTrue);
+                    if !taken {
+                        report.jumps_removed += 1;
+                        continue;
+                    }
+                    if opcode == Opcode::JumpIfTrue {
+                        report.folded += 1;
+                        operands[0] = Operand::immediate(0);
+                        simplified.push(Item::Instruction(Opcode::JumpIfFalse, operands));
+                        continue;
+                    }
+                }
+            }
+            _ => {}
+        }
+        simplified.push(Item::Instruction(opcode, operands));
+    }
+    simplified
+}
+
+// jumps whose target label comes before any other instruction or data
+fn remove_jumps(items: Vec<Item>, report: &mut Report) -> Vec<Item> {
+    let mut kept = Vec::with_capacity(items.len());
+    for (i, item) in items.iter().enumerate() {
+        if let Item::Instruction(Opcode::JumpIfTrue, ref operands)
+        | Item::Instruction(Opcode::JumpIfFalse, ref operands) = *item
+        {
+            if let Value::Label(ref target, 0) = operands[1].value {
+                let to_next = operands[1].mode == Mode::Immediate
+                    && items[i + 1..]
+                        .iter()
+                        .take_while(|item| matches!(**item, Item::Label(_)))
+                        .any(|item| *item == Item::Label(target.clone()));
+                if to_next {
+                    report.jumps_removed += 1;
+                    continue;
+                }
+            }
+        }
+        kept.push(item.clone());
+    }
+    kept
+}
+
+// instructions after an unconditional jump or halt, up to the next label or data
+fn remove_dead(items: Vec<Item>, report: &mut Report) -> Vec<Item> {
+    let mut kept = Vec::with_capacity(items.len());
+    let mut dead = false;
+    for item in items {
+        match item {
+            Item::Instruction(opcode, ref operands) => {
+                if dead {
+                    report.dead_removed += 1;
+                    continue;
+                }
+                dead = opcode == Opcode::Halt
+                    || (opcode == Opcode::JumpIfFalse && constant(&operands[0]) == Some(0));
+            }
+            _ => dead = false,
+        }
+        kept.push(item);
+    }
+    kept
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LiftError {
+    NoCode,
+    WritesIntoCode {
+        address: usize,
+        target: usize,
+    },
+    ReadsCode {
+        address: usize,
+        target: usize,
+    },
+    /// Relative addresses can't be moved when the code around them changes size
+    RelativeAddressing {
+        address: usize,
+    },
+    /// A jump whose target is read from memory
+    DynamicJump {
+        address: usize,
+    },
+    JumpIntoData {
+        address: usize,
+        target: usize,
+    },
+}
+
+impl fmt::Display for LiftError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LiftError::NoCode => write!(f, "no code is reachable from address 0"),
+            LiftError::WritesIntoCode { address, target } => {
+                write!(
+                    f,
+                    "instruction at {} writes into code at {}",
+                    address, target
+                )
+            }
+            LiftError::ReadsCode { address, target } => {
+                write!(f, "instruction at {} reads code at {}", address, target)
+            }
+            LiftError::RelativeAddressing { address } => {
+                write!(f, "instruction at {} uses relative addressing", address)
+            }
+            LiftError::DynamicJump { address } => {
+                write!(
+                    f,
+                    "instruction at {} jumps to an address read from memory",
+                    address
+                )
+            }
+            LiftError::JumpIntoData { address, target } => {
+                write!(
+                    f,
+                    "instruction at {} jumps into data at {}",
+                    address, target
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for LiftError {}
+
+fn label_for(address: usize, len: usize) -> Value {
+    if address < len {
+        Value::Label(format!(".a{}", address), 0)
+    } else {
+        Value::Label(".end".to_string(), (address - len) as i64)
+    }
+}
+
+/// Turns a raw program into assembler items with a label for every address it uses
+pub fn lift(program: &[i64]) -> Result<Vec<Item>, LiftError> {
+    lift_with(program, &Layout::infer(program, None))
+}
+
+/// Like `lift`, taking the instructions from `layout`, so code only a profile found is lifted
+/// as code rather than data
+pub fn lift_with(program: &[i64], layout: &Layout) -> Result<Vec<Item>, LiftError> {
+    let instructions = layout.instructions(program);
+    if instructions.is_empty() {
+        return Err(LiftError::NoCode);
+    }
+    let mut code = HashSet::new();
+    for instruction in instructions.values() {
+        code.extend(instruction.address..instruction.next());
+    }
+
+    let mut labels = BTreeSet::new();
+    for instruction in instructions.values() {
+        let address = instruction.address;
+        if instruction.opcode == Opcode::AdjustBase {
+            return Err(LiftError::RelativeAddressing { address });
+        }
+        for (i, param) in instruction.params.iter().enumerate() {
+            let is_target = i == 1
+                && (instruction.opcode == Opcode::JumpIfTrue
+                    || instruction.opcode == Opcode::JumpIfFalse);
+            match param.mode {
+                Mode::Relative => return Err(LiftError::RelativeAddressing { address }),
+                Mode::Position if is_target => return Err(LiftError::DynamicJump { address }),
+                Mode::Immediate if is_target => {
+                    let target = param.value as usize;
+                    if param.value < 0 || !instructions.contains_key(&target) {
+                        return Err(LiftError::JumpIntoData { address, target });
+                    }
+                    labels.insert(target);
+                }
+                Mode::Position => {
+                    let target = param.value as usize;
+                    if code.contains(&target) {
+                        return Err(if Some(i) == instruction.opcode.write_param() {
+                            LiftError::WritesIntoCode { address, target }
+                        } else {
+                            LiftError::ReadsCode { address, target }
+                        });
+                    }
+                    labels.insert(target);
+                }
+                Mode::Immediate => {}
+            }
+        }
+    }
+
+    let len = program.len();
+    let mut items = Vec::new();
+    let mut address = 0;
+    while address < len {
+        if labels.contains(&address) {
+            items.push(Item::Label(format!(".a{}", address)));
+        }
+        match instructions.get(&address) {
+            Some(instruction) => {
+                let operands = instruction
+                    .params
+                    .iter()
+                    .enumerate()
+                    .map(|(i, param)| match param.mode {
+                        Mode::Immediate if i == 1 && instruction.opcode.arity() == 2 => Operand {
+                            mode: Mode::Immediate,
+                            value: label_for(param.value as usize, len),
+                        },
+                        Mode::Position => Operand {
+                            mode: Mode::Position,
+                            value: label_for(param.value as usize, len),
+                        },
+                        _ => Operand {
+                            mode: param.mode,
+                            value: Value::Number(param.value),
+                        },
+                    })
+                    .collect();
+                items.push(Item::Instruction(instruction.opcode, operands));
+                address = instruction.next();
+            }
+            None => {
+                items.push(Item::Data(vec![Value::Number(program[address])]));
+                address += 1;
+            }
+        }
+    }
+    items.push(Item::Label(".end".to_string()));
+
+    Ok(items)
+}
+
+/// Lifts, optimizes and reassembles a raw program
+pub fn optimize_program(program: &[i64]) -> Result<(Vec<i64>, Report), LiftError> {
+    let (items, report) = optimize(&lift(program)?);
+    let optimized = asm::assemble(&items).expect("lifted items only use their own labels");
+    Ok((optimized, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::compile;
+    use crate::{run_program, ProgramState};
+
+    fn outputs(program: &[i64], inputs: Vec<i64>) -> Vec<i64> {
+        let mut state = ProgramState::new(program, inputs);
+        let mut outputs = Vec::new();
+        run_program(&mut state, true, |_, output| {
+            outputs.push(output);
+            false
+        });
+        assert!(state.finished);
+        outputs
+    }
+
+    #[test]
+    fn test_peephole_rules() {
+        let items = asm::parse(
+            "
+                mul #3, #4, [x]
+                mul [x], #1, [y]
+                add #0, [y], [y]
+                jnz #0, #skip
+                jnz #7, #next
+            next:
+                out [y]
+                hlt
+                out [x]
+            skip:
+                hlt
+            x:  data 0
+            y:  data 0
+            ",
+        )
+        .unwrap();
+        let (optimized, report) = optimize(&items);
+        let printed: Vec<String> = optimized.iter().map(|item| item.to_string()).collect();
+        assert_eq!(
+            printed,
+            vec![
+                "    add #12, #0, [x]",
+                "    add [x], #0, [y]",
+                "next:",
+                "    out [y]",
+                "    hlt",
+                "skip:",
+                "    hlt",
+                "x:",
+                "    data 0",
+                "y:",
+                "    data 0",
+            ]
+        );
+        assert_eq!(report.folded, 2);
+        assert_eq!(report.moves, 2);
+        assert_eq!(report.jumps_removed, 2);
+        assert_eq!(report.dead_removed, 1);
+        assert_eq!(report.instructions_saved(), 4);
+    }
+
+    #[test]
+    fn test_compiled_programs_behave_the_same() {
+        let sources = [
+            "
+            fn fib(n) {
+                if n < 2 { return n; }
+                return fib(n - 1) + fib(n - 2);
+            }
+            fn main() { write(fib(read())); }
+            ",
+            "
+            fn main() {
+                var n = read();
+                var total = 0;
+                while n != 0 {
+                    total = total + n * 1 - 0;
+                    if n >= 3 { write(total); } else { write(-total); }
+                    n = n - 1;
+                }
+                write(2 * 3 + 4 < 11);
+                return;
+            }
+            ",
+        ];
+        for source in sources.iter() {
+            let items = compile(source).unwrap();
+            let (optimized, report) = optimize(&items);
+            assert!(report.instructions_saved() > 0, "{}", report);
+            let original = asm::assemble(&items).unwrap();
+            let optimized = asm::assemble(&optimized).unwrap();
+            assert!(optimized.len() < original.len());
+            for input in 0..12 {
+                assert_eq!(
+                    outputs(&optimized, vec![input]),
+                    outputs(&original, vec![input])
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_lifted_program_behaves_the_same() {
+        // day 5 example with a never taken jump and jumps to the next instruction spliced in
+        let program = asm::assemble(
+            &asm::parse(
+                "
+                    in [input]
+                    eq [input], #8, [flag]
+                    jz #1, #below
+                    jnz [flag], #eight
+                    jz #0, #compare
+                compare:
+                    lt #8, [input], [flag]
+                    jz [flag], #below
+                    jz #0, #above
+                eight:
+                    mul [input], #125, [flag]
+                    out [flag]
+                    jz #0, #done
+                below:
+                    out #999
+                    jz #0, #done
+                above:
+                    add #1000, #1, [flag]
+                    out [flag]
+                    jz #0, #done
+                done:
+                    hlt
+                flag: data 0
+                input: data 0
+                ",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let (optimized, report) = optimize_program(&program).unwrap();
+        assert_eq!(report.jumps_removed, 3);
+        assert_eq!(optimized.len(), program.len() - 9);
+        for input in 0..16 {
+            assert_eq!(
+                outputs(&optimized, vec![input]),
+                outputs(&program, vec![input])
+            );
+        }
+    }
+
+    #[test]
+    fn test_lift_refuses_what_it_cannot_relocate() {
+        assert_eq!(
+            lift(&[1, 0, 0, 3, 99]),
+            Err(LiftError::ReadsCode {
+                address: 0,
+                target: 0
+            })
+        );
+        assert_eq!(
+            lift(&[1101, 1, 1, 2, 99]),
+            Err(LiftError::WritesIntoCode {
+                address: 0,
+                target: 2
+            })
+        );
+        assert_eq!(
+            lift(&[109, 1, 99]),
+            Err(LiftError::RelativeAddressing { address: 0 })
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use std::error::Error;
+use std::fmt;
+
+/// Where a program text stops making sense. Lines and columns count from 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl Error for ParseError {}
+
+/// Parses comma separated numbers. Whitespace and line endings are ignored, `#` starts a comment
+/// that runs to the end of the line, and a line break can stand in for a comma, so a program can be
+/// spread over several lines with or without trailing commas.
+pub fn parse_program(text: &str) -> Result<Vec<i64>, ParseError> {
+    let mut program = Vec::new();
+    // the last comma, while it is still waiting for its number
+    let mut comma: Option<(usize, usize)> = None;
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap();
+        let error = |column: usize, message: String| ParseError {
+            line: number + 1,
+            column: column + 1,
+            message,
+        };
+        let mut value_on_line = false;
+        let mut chars = line.char_indices().enumerate().peekable();
+
+        while let Some((column, (start, c))) = chars.next() {
+            if c.is_whitespace() {
+                continue;
+            }
+            if c == ',' {
+                if comma.is_some() || !value_on_line && program.is_empty() {
+                    return Err(error(column, "expected a number before \",\"".to_string()));
+                }
+                comma = Some((number, column));
+                value_on_line = false;
+                continue;
+            }
+
+            let mut end = start + c.len_utf8();
+            while let Some(&(_, (next, c))) = chars.peek() {
+                if c.is_whitespace() || c == ',' {
+                    break;
+                }
+                end = next + c.len_utf8();
+                chars.next();
+            }
+            let token = &line[start..end];
+            if value_on_line {
+                return Err(error(column, format!("expected \",\" before {:?}", token)));
+            }
+            let value = token
+                .parse()
+                .map_err(|_| error(column, format!("bad number {:?}", token)))?;
+            program.push(value);
+            comma = None;
+            value_on_line = true;
+        }
+    }
+
+    if let Some((line, column)) = comma {
+        return Err(ParseError {
+            line: line + 1,
+            column: column + 1,
+            message: "expected a number after \",\"".to_string(),
+        });
+    }
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tolerates_layout() {
+        assert_eq!(
+            parse_program("1,9,10,3,2,3,11,0,99,30,40,50\n")
+                .unwrap()
+                .len(),
+            12
+        );
+        assert_eq!(
+            parse_program("1, 0 ,0,0,\r\n99\r\n"),
+            Ok(vec![1, 0, 0, 0, 99])
+        );
+        assert_eq!(
+            parse_program(
+                "# day 5's echo\n\
+                 3, 0   # read\n\
+                 4, 0,  # write\n\
+                 \n\
+                 99\n"
+            ),
+            Ok(vec![3, 0, 4, 0, 99])
+        );
+        assert_eq!(parse_program("  \n# nothing\n"), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_reports_locations() {
+        let error = |line, column, message: &str| ParseError {
+            line,
+            column,
+            message: message.to_string(),
+        };
+        assert_eq!(
+            parse_program("1,2,\n3,x4,99"),
+            Err(error(2, 3, "bad number \"x4\""))
+        );
+        assert_eq!(
+            parse_program("1,,2"),
+            Err(error(1, 3, "expected a number before \",\""))
+        );
+        assert_eq!(
+            parse_program("1,2 3"),
+            Err(error(1, 5, "expected \",\" before \"3\""))
+        );
+        assert_eq!(
+            parse_program("1,2,\n\n"),
+            Err(error(1, 4, "expected a number after \",\""))
+        );
+        assert_eq!(
+            parse_program(",1").unwrap_err().to_string(),
+            "line 1, column 1: expected a number before \",\""
+        );
+    }
+}
@@ -0,0 +1,412 @@
+//! The peripherals the puzzle programs talk to.
+//!
+//! - `Canvas` is a grid of numbers, drawn by points (the arcade cabinet), by lines of text (the
+//!   scaffold camera) or by a robot that paints as it goes (the hull painter).
+//! - `Motor` moves a droid one square at a time and hears back whether it could (the repair
+//!   droid).
+//! - `Terminal` types lines of text in and collects the text and numbers that come out (the
+//!   springdroid and the cryostasis adventure).
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::bus::Peripheral;
+
+pub type Point = (i64, i64);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Protocol {
+    /// Frames of x, y and the value to put there
+    Points,
+    /// One character at a time, with rows ended by newlines
+    Text { cursor: Point },
+    /// Frames of the value to paint under the robot and which way it turns, 0 for left and 1
+    /// for right, after which it moves forward. It reads the value under itself.
+    Turtle { position: Point, heading: Point },
+}
+
+/// A grid of numbers drawn by a program. Cells never drawn read as 0.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Canvas {
+    cells: HashMap<Point, i64>,
+    protocol: Protocol,
+}
+
+impl Canvas {
+    /// Drawn by frames of three values, x, y and the value
+    pub fn points() -> Self {
+        Canvas::with(Protocol::Points)
+    }
+
+    /// Drawn one character at a time, left to right and then down a row at each newline. Values
+    /// outside ASCII aren't for the canvas and are left for other peripherals.
+    pub fn text() -> Self {
+        Canvas::with(Protocol::Text { cursor: (0, 0) })
+    }
+
+    /// Drawn by a robot starting at 0, 0 facing up, with frames of two values, the value to
+    /// paint and the way to turn
+    pub fn turtle() -> Self {
+        Canvas::with(Protocol::Turtle {
+            position: (0, 0),
+            heading: (0, -1),
+        })
+    }
+
+    fn with(protocol: Protocol) -> Self {
+        Canvas {
+            cells: HashMap::new(),
+            protocol,
+        }
+    }
+
+    pub fn get(&self, point: Point) -> i64 {
+        self.cells.get(&point).cloned().unwrap_or(0)
+    }
+
+    pub fn set(&mut self, point: Point, value: i64) {
+        self.cells.insert(point, value);
+    }
+
+    /// Number of cells drawn at least once
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn cells(&self) -> impl Iterator<Item = (Point, i64)> + '_ {
+        self.cells.iter().map(|(point, value)| (*point, *value))
+    }
+
+    /// Number of cells holding `value`
+    pub fn count(&self, value: i64) -> usize {
+        self.cells.values().filter(|cell| **cell == value).count()
+    }
+
+    /// The top left and bottom right cells drawn
+    pub fn bounds(&self) -> Option<(Point, Point)> {
+        let mut points = self.cells.keys();
+        let first = *points.next()?;
+        Some(points.fold((first, first), |(min, max), point| {
+            (
+                (min.0.min(point.0), min.1.min(point.1)),
+                (max.0.max(point.0), max.1.max(point.1)),
+            )
+        }))
+    }
+
+    /// The drawn part of the grid as text, one line per row
+    pub fn render<F>(&self, palette: F) -> String
+    where
+        F: Fn(i64) -> char,
+    {
+        let mut text = String::new();
+        if let Some((min, max)) = self.bounds() {
+            for y in min.1..=max.1 {
+                for x in min.0..=max.0 {
+                    text.push(palette(self.get((x, y))));
+                }
+                text.push('\n');
+            }
+        }
+        text
+    }
+}
+
+impl Peripheral for Canvas {
+    fn accepts(&self, frame: &[i64]) -> bool {
+        match self.protocol {
+            Protocol::Points => frame.len() == 3,
+            Protocol::Text { .. } => frame.len() == 1 && (0..128).contains(&frame[0]),
+            Protocol::Turtle { .. } => frame.len() == 2,
+        }
+    }
+
+    fn receive(&mut self, frame: &[i64]) {
+        match &mut self.protocol {
+            Protocol::Points => {
+                self.cells.insert((frame[0], frame[1]), frame[2]);
+            }
+            Protocol::Text { cursor } => {
+                if frame[0] == '\n' as i64 {
+                    *cursor = (0, cursor.1 + 1);
+                } else {
+                    self.cells.insert(*cursor, frame[0]);
+                    cursor.0 += 1;
+                }
+            }
+            Protocol::Turtle { position, heading } => {
+                self.cells.insert(*position, frame[0]);
+                *heading = if frame[1] == 0 {
+                    (heading.1, -heading.0)
+                } else {
+                    (-heading.1, heading.0)
+                };
+                *position = (position.0 + heading.0, position.1 + heading.1);
+            }
+        }
+    }
+
+    fn send(&mut self) -> Option<i64> {
+        match self.protocol {
+            Protocol::Turtle { position, .. } => Some(self.get(position)),
+            _ => None,
+        }
+    }
+}
+
+/// Drives a droid with movement commands, 1 to 4 for north, south, west and east, each answered
+/// with 0 if the droid hit a wall and stayed put, or 1 or more if it moved
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Motor {
+    position: Point,
+    command: Option<i64>,
+    last_command: Option<i64>,
+    status: Option<i64>,
+}
+
+impl Motor {
+    pub fn new() -> Self {
+        Motor::default()
+    }
+
+    /// The square one move from `point`
+    pub fn step(point: Point, command: i64) -> Point {
+        match command {
+            1 => (point.0, point.1 - 1),
+            2 => (point.0, point.1 + 1),
+            3 => (point.0 - 1, point.1),
+            4 => (point.0 + 1, point.1),
+            _ => panic!("unknown movement command {}", command),
+        }
+    }
+
+    /// Queues a move for the program to read
+    pub fn go(&mut self, command: i64) {
+        self.command = Some(command);
+    }
+
+    /// Where the droid is, counting from where it started
+    pub fn position(&self) -> Point {
+        self.position
+    }
+
+    /// The answer to the last move, if it has been answered
+    pub fn status(&self) -> Option<i64> {
+        self.status
+    }
+}
+
+impl Peripheral for Motor {
+    fn accepts(&self, frame: &[i64]) -> bool {
+        frame.len() == 1
+    }
+
+    fn receive(&mut self, frame: &[i64]) {
+        let command = self
+            .last_command
+            .take()
+            .expect("the droid answered a move it wasn't given");
+        if frame[0] != 0 {
+            self.position = Motor::step(self.position, command);
+        }
+        self.status = Some(frame[0]);
+    }
+
+    fn send(&mut self) -> Option<i64> {
+        let command = self.command.take()?;
+        self.last_command = Some(command);
+        self.status = None;
+        Some(command)
+    }
+}
+
+/// Types lines of ASCII text in and collects what comes out. Values too big for ASCII are
+/// answers rather than text, and are kept apart.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Terminal {
+    typed: VecDeque<i64>,
+    text: String,
+    values: Vec<i64>,
+    echo: bool,
+}
+
+impl Terminal {
+    pub fn new() -> Self {
+        Terminal::default()
+    }
+
+    /// Prints everything that comes out as it arrives, with values too big for ASCII printed as
+    /// numbers in line with the text
+    pub fn echo(mut self) -> Self {
+        self.echo = true;
+        self
+    }
+
+    /// Queues a line for the program to read, adding the newline
+    pub fn send_line(&mut self, line: &str) {
+        self.typed.extend(line.bytes().map(i64::from));
+        self.typed.push_back('\n' as i64);
+    }
+
+    /// Characters queued but not read yet
+    pub fn pending(&self) -> usize {
+        self.typed.len()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The text that has come out since the last call
+    pub fn take_text(&mut self) -> String {
+        std::mem::take(&mut self.text)
+    }
+
+    pub fn values(&self) -> &[i64] {
+        &self.values
+    }
+}
+
+impl Peripheral for Terminal {
+    fn accepts(&self, frame: &[i64]) -> bool {
+        frame.len() == 1
+    }
+
+    fn receive(&mut self, frame: &[i64]) {
+        let value = frame[0];
+        if (0..128).contains(&value) {
+            let c = value as u8 as char;
+            self.text.push(c);
+            if self.echo {
+                print!("{}", c);
+            }
+        } else {
+            self.values.push(value);
+            if self.echo {
+                print!("{}", value);
+            }
+        }
+    }
+
+    fn send(&mut self) -> Option<i64> {
+        self.typed.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+    use crate::{run_device, Bus, ProgramState};
+
+    fn outputs(values: &[i64]) -> Vec<i64> {
+        let mut program: Vec<i64> = values.iter().flat_map(|value| vec![104, *value]).collect();
+        program.push(99);
+        program
+    }
+
+    #[test]
+    fn test_canvas_points() {
+        let mut state =
+            ProgramState::new(&outputs(&[1, 0, 2, 0, 1, 3, 1, 0, 4, -1, 0, 500]), vec![]);
+        let mut canvas = Canvas::points();
+        run_device(&mut state, &mut Bus::new(3).attach(&mut canvas));
+
+        assert_eq!(canvas.get((1, 0)), 4);
+        assert_eq!(canvas.get((0, 1)), 3);
+        // a score kept off the screen
+        assert_eq!(canvas.get((-1, 0)), 500);
+        assert_eq!(canvas.count(4), 1);
+        assert_eq!(canvas.bounds(), Some(((-1, 0), (1, 1))));
+    }
+
+    #[test]
+    fn test_canvas_text_leaves_numbers_for_the_terminal() {
+        let mut values: Vec<i64> = "#.\n.^\n".bytes().map(i64::from).collect();
+        values.push(1234);
+        let mut state = ProgramState::new(&outputs(&values), vec![]);
+        let mut canvas = Canvas::text();
+        let mut terminal = Terminal::new();
+        let mut bus = Bus::new(1).attach(&mut canvas).attach(&mut terminal);
+        run_device(&mut state, &mut bus);
+
+        assert_eq!(canvas.get((1, 1)), '^' as i64);
+        assert_eq!(canvas.render(|value| value as u8 as char), "#.\n.^\n");
+        assert_eq!(terminal.text(), "#.\n.^\n");
+        assert_eq!(terminal.values(), &[1234]);
+    }
+
+    #[test]
+    fn test_canvas_turtle() {
+        // paints 1 and turns left, twice, then reads the cell it is on and paints it again
+        let program = asm::assemble(
+            &asm::parse(
+                "in [cell]
+                 out #1
+                 out #0
+                 out #1
+                 out #0
+                 in [cell]
+                 out [cell]
+                 out #1
+                 hlt
+                 cell: data 9",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let mut state = ProgramState::new(&program, vec![]);
+        let mut canvas = Canvas::turtle();
+        canvas.set((0, 0), 5);
+        run_device(&mut state, &mut Bus::new(2).attach(&mut canvas));
+
+        assert_eq!(state.read(program.len() - 1), 0);
+        assert_eq!(canvas.get((0, 0)), 1);
+        assert_eq!(canvas.get((-1, 0)), 1);
+        // it read the unpainted cell it had moved to, and painted it with what it read
+        assert_eq!(canvas.get((-1, 1)), 0);
+        assert_eq!(canvas.len(), 3);
+        assert_eq!(
+            canvas.render(|value| (b'0' + value as u8) as char),
+            "11\n00\n"
+        );
+    }
+
+    #[test]
+    fn test_motor_moves_unless_it_hits_a_wall() {
+        // answers 1 for north and 0 for anything else, forever
+        let program = vec![3, 11, 1008, 11, 1, 12, 4, 12, 1105, 1, 0, 0, 0];
+        let mut state = ProgramState::new(&program, vec![]);
+        let mut motor = Motor::new();
+        for (command, status, position) in [(1, 1, (0, -1)), (4, 0, (0, -1)), (1, 1, (0, -2))] {
+            motor.go(command);
+            assert!(!run_device(&mut state, &mut Bus::new(1).attach(&mut motor)));
+            assert_eq!(motor.status(), Some(status));
+            assert_eq!(motor.position(), position);
+        }
+        assert_eq!(Motor::step((3, 3), 3), (2, 3));
+    }
+
+    #[test]
+    fn test_terminal_types_lines() {
+        // prints back what it reads until it reads a newline, then prints 200 and halts
+        let program = vec![
+            3, 15, 4, 15, 1008, 15, 10, 16, 1006, 16, 0, 104, 200, 99, 0, 0,
+        ];
+        let mut state = ProgramState::new(&program, vec![]);
+        let mut terminal = Terminal::new();
+        terminal.send_line("hi");
+        assert_eq!(terminal.pending(), 3);
+        assert!(run_device(
+            &mut state,
+            &mut Bus::new(1).attach(&mut terminal)
+        ));
+
+        assert_eq!(terminal.take_text(), "hi\n");
+        assert_eq!(terminal.text(), "");
+        assert_eq!(terminal.values(), &[200]);
+    }
+}
@@ -0,0 +1,217 @@
+//! Counts of how a run touched each address.
+//!
+//! Once `enable_profile` is called every completed instruction is counted against the address it
+//! ran from, the operand words it was fetched with, and the cells it read and wrote. A `Layout`
+//! built from the profile then knows about code and data that can't be found without running.
+
+use std::fmt;
+
+use crate::decode::{Mode, Opcode};
+use crate::{Fault, Layout, Op, ProgramState};
+
+/// How many times one address was used each way
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Access {
+    /// Instructions started here
+    pub executed: u64,
+    /// Times the word was fetched as an operand of an instruction
+    pub fetched: u64,
+    pub read: u64,
+    pub written: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    words: Vec<Access>,
+    steps: u64,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Profile::default()
+    }
+
+    pub fn get(&self, address: usize) -> Access {
+        self.words.get(address).cloned().unwrap_or_default()
+    }
+
+    /// One past the highest address touched
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Instructions run while profiling
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Every address touched at all, lowest first
+    pub fn iter(&self) -> impl Iterator<Item = (usize, Access)> + '_ {
+        self.words
+            .iter()
+            .cloned()
+            .enumerate()
+            .filter(|(_, access)| *access != Access::default())
+    }
+
+    fn at(&mut self, address: usize) -> &mut Access {
+        if address >= self.words.len() {
+            self.words.resize(address + 1, Access::default());
+        }
+        &mut self.words[address]
+    }
+
+    /// Which of the layout's instructions have run
+    pub fn coverage(&self, layout: &Layout) -> Coverage {
+        let (run, never_run): (Vec<usize>, Vec<usize>) = layout
+            .instruction_starts()
+            .partition(|address| self.get(*address).executed > 0);
+        Coverage {
+            instructions: run.len() + never_run.len(),
+            executed: run.len(),
+            never_run,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Coverage {
+    pub instructions: usize,
+    pub executed: usize,
+    /// Instruction addresses that never ran, lowest first
+    pub never_run: Vec<usize>,
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let percent = if self.instructions == 0 {
+            0.0
+        } else {
+            self.executed as f64 * 100.0 / self.instructions as f64
+        };
+        write!(
+            f,
+            "{} of {} instructions run ({:.1}%)",
+            self.executed, self.instructions, percent
+        )
+    }
+}
+
+impl ProgramState {
+    /// Starts counting what each address is used for
+    pub fn enable_profile(&mut self) {
+        self.profile = Some(Profile::new());
+    }
+}
+
+fn address_of(param_value: i64, mode: Mode, relative_base: i64) -> Option<usize> {
+    let address = match mode {
+        Mode::Position => param_value,
+        Mode::Relative => param_value + relative_base,
+        Mode::Immediate => return None,
+    };
+    if address < 0 {
+        None
+    } else {
+        Some(address as usize)
+    }
+}
+
+/// Counts an instruction that has just run from `ip` with the relative base it started with
+pub(crate) fn observe(
+    state: &mut ProgramState,
+    op: &Op,
+    ip: usize,
+    relative_base: i64,
+    result: &Result<(Option<i64>, bool), Fault>,
+) {
+    let blocked = op.opcode == Opcode::Input && !state.finished && state.index == ip;
+    let mut profile = match state.profile.take() {
+        Some(profile) if result.is_ok() && !blocked => profile,
+        profile => {
+            state.profile = profile;
+            return;
+        }
+    };
+
+    profile.steps += 1;
+    profile.at(ip).executed += 1;
+    for address in ip + 1..ip + op.len {
+        profile.at(address).fetched += 1;
+    }
+
+    let jumped = state.index != ip + op.len;
+    for (i, param) in op.params[..op.len - 1].iter().enumerate() {
+        let address = match address_of(param.value, param.mode, relative_base) {
+            Some(address) => address,
+            None => continue,
+        };
+        let is_target =
+            i == 1 && (op.opcode == Opcode::JumpIfTrue || op.opcode == Opcode::JumpIfFalse);
+        if Some(i) == op.opcode.write_param() {
+            profile.at(address).written += 1;
+        } else if !is_target || jumped {
+            profile.at(address).read += 1;
+        }
+    }
+
+    state.profile = Some(profile);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parse_program, run_program, ProgramState};
+
+    #[test]
+    fn test_counts_each_kind_of_access() {
+        // counts [20] down from 3, testing it through the relative base and jumping back to the
+        // address in [21]
+        let program = vec![
+            109, 20, 1001, 20, -1, 20, 205, 0, 21, 99, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3, 2,
+        ];
+        let mut state = ProgramState::new(&program, vec![]);
+        state.enable_profile();
+        run_program(&mut state, false, |_, _| false);
+
+        let profile = state.profile.as_ref().unwrap();
+        assert_eq!(profile.steps(), 8);
+        assert_eq!(profile.get(0).executed, 1);
+        assert_eq!(profile.get(2).executed, 3);
+        assert_eq!(profile.get(3).fetched, 3);
+        assert_eq!(profile.get(20).read, 6);
+        assert_eq!(profile.get(20).written, 3);
+        // the jump target is only read when the jump is taken
+        assert_eq!(profile.get(21).read, 2);
+        assert_eq!(profile.get(10).executed, 0);
+    }
+
+    #[test]
+    fn test_blocked_input_is_not_counted() {
+        let mut state = ProgramState::new(&[3, 3, 99, 0], vec![]);
+        state.enable_profile();
+        crate::run_step(&mut state, false);
+        assert!(state.profile.as_ref().unwrap().is_empty());
+
+        state.inputs.push(1);
+        crate::run_step(&mut state, false);
+        assert_eq!(state.profile.as_ref().unwrap().get(3).written, 1);
+    }
+
+    #[test]
+    fn test_boost_coverage() {
+        let boost = parse_program(include_str!("../../9/input.txt")).unwrap();
+        let mut state = ProgramState::new(&boost, vec![1]);
+        state.enable_profile();
+        run_program(&mut state, false, |_, _| false);
+
+        let profile = state.profile.as_ref().unwrap();
+        let coverage = profile.coverage(&crate::Layout::infer(&boost, Some(profile)));
+        // the self test passes, so the branches that report failures never run
+        assert_eq!(coverage.never_run.len(), 95);
+        assert_eq!(coverage.to_string(), "210 of 305 instructions run (68.9%)");
+    }
+}
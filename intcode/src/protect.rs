@@ -0,0 +1,317 @@
+//! Memory protection and write watches.
+//!
+//! A `MemoryMap` marks address ranges as code, data or off limits. Once one is attached to a
+//! machine, writing to code, running data or touching an off limits cell stops the machine with a
+//! fault instead of carrying on. Watched ranges don't stop anything, they log every write.
+
+use std::fmt;
+use std::ops::Range;
+
+use crate::{Fault, Layout, ProgramState};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protection {
+    /// Read and run, but not written
+    Code,
+    /// Read and written, but not run
+    Data,
+    /// Not read, written or run
+    NoAccess,
+}
+
+impl fmt::Display for Protection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Protection::Code => write!(f, "code"),
+            Protection::Data => write!(f, "data"),
+            Protection::NoAccess => write!(f, "no access memory"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Region {
+    pub range: Range<usize>,
+    pub protection: Protection,
+}
+
+/// A write into a watched range
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WriteEvent {
+    /// The instruction that wrote, or where the machine was stopped for a write from outside
+    pub ip: usize,
+    pub address: usize,
+    pub old: i64,
+    pub new: i64,
+}
+
+impl fmt::Display for WriteEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} wrote {} over {} at {}",
+            self.ip, self.new, self.old, self.address
+        )
+    }
+}
+
+/// Protected regions and watched ranges. Addresses outside every region are unrestricted, and
+/// where regions overlap the one added last wins.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryMap {
+    regions: Vec<Region>,
+    watches: Vec<Range<usize>>,
+    writes: Vec<WriteEvent>,
+}
+
+impl MemoryMap {
+    pub fn new() -> Self {
+        MemoryMap::default()
+    }
+
+    /// Marks every instruction reachable from the start of `program` as code
+    pub fn for_code(program: &[i64]) -> Self {
+        MemoryMap::from(&Layout::infer(program, None))
+    }
+
+    pub fn protect(&mut self, range: Range<usize>, protection: Protection) -> &mut Self {
+        self.regions.push(Region { range, protection });
+        self
+    }
+
+    pub fn watch(&mut self, range: Range<usize>) -> &mut Self {
+        self.watches.push(range);
+        self
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    pub fn protection(&self, address: usize) -> Option<Protection> {
+        self.regions
+            .iter()
+            .rev()
+            .find(|region| region.range.contains(&address))
+            .map(|region| region.protection)
+    }
+
+    pub fn is_watched(&self, address: usize) -> bool {
+        self.watches.iter().any(|range| range.contains(&address))
+    }
+
+    /// Writes into watched ranges so far, oldest first
+    pub fn writes(&self) -> &[WriteEvent] {
+        &self.writes
+    }
+
+    pub fn take_writes(&mut self) -> Vec<WriteEvent> {
+        std::mem::take(&mut self.writes)
+    }
+
+    pub(crate) fn check_read(&self, ip: usize, address: usize) -> Result<(), Fault> {
+        match self.protection(address) {
+            Some(Protection::NoAccess) => Err(Fault::ProtectedRead { ip, address }),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn check_write(&self, ip: usize, address: usize) -> Result<(), Fault> {
+        match self.protection(address) {
+            Some(protection) if protection != Protection::Data => Err(Fault::ProtectedWrite {
+                ip,
+                address,
+                protection,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn check_execute(&self, ip: usize) -> Result<(), Fault> {
+        match self.protection(ip) {
+            Some(protection) if protection != Protection::Code => {
+                Err(Fault::ProtectedExecute { ip, protection })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn record_write(&mut self, ip: usize, address: usize, old: i64, new: i64) {
+        if self.is_watched(address) {
+            self.writes.push(WriteEvent {
+                ip,
+                address,
+                old,
+                new,
+            });
+        }
+    }
+}
+
+/// Marks the layout's code as code, leaving data unrestricted
+impl From<&Layout> for MemoryMap {
+    fn from(layout: &Layout) -> Self {
+        let mut map = MemoryMap::new();
+        let mut run: Option<Range<usize>> = None;
+        for (range, kind) in layout.regions() {
+            if !kind.is_code() {
+                continue;
+            }
+            run = match run {
+                Some(run) if run.end == range.start => Some(run.start..range.end),
+                Some(run) => {
+                    map.protect(run, Protection::Code);
+                    Some(range)
+                }
+                None => Some(range),
+            };
+        }
+        if let Some(run) = run {
+            map.protect(run, Protection::Code);
+        }
+        map
+    }
+}
+
+impl ProgramState {
+    /// Protects every instruction reachable from the start of the program, so self modifying
+    /// code faults on its first write to itself
+    pub fn protect_code(&mut self) {
+        let program: Vec<i64> = (0..self.program.len())
+            .map(|address| self.program.get(address))
+            .collect();
+        self.memory_map = Some(MemoryMap::for_code(&program));
+    }
+
+    pub fn protect(&mut self, range: Range<usize>, protection: Protection) {
+        self.memory_map
+            .get_or_insert_with(MemoryMap::new)
+            .protect(range, protection);
+    }
+
+    pub fn watch(&mut self, range: Range<usize>) {
+        self.memory_map
+            .get_or_insert_with(MemoryMap::new)
+            .watch(range);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_program, run_program, try_run_program, ProgramState};
+
+    fn run(state: &mut ProgramState) -> Result<Vec<i64>, Fault> {
+        let mut outputs = Vec::new();
+        try_run_program(state, false, |_, output| {
+            outputs.push(output);
+            false
+        })?;
+        Ok(outputs)
+    }
+
+    #[test]
+    fn test_reachable_code_is_protected() {
+        // the day 2 example writes its result over its own first instruction
+        let mut state = ProgramState::new(&[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50], vec![]);
+        state.protect_code();
+        assert_eq!(
+            state.memory_map.as_ref().unwrap().regions(),
+            &[Region {
+                range: 0..9,
+                protection: Protection::Code
+            }]
+        );
+        assert_eq!(
+            run(&mut state),
+            Err(Fault::ProtectedWrite {
+                ip: 0,
+                address: 3,
+                protection: Protection::Code
+            })
+        );
+        assert_eq!(state.program[3], 3);
+    }
+
+    #[test]
+    fn test_which_puzzles_modify_themselves() {
+        let boost = parse_program(include_str!("../../9/input.txt")).unwrap();
+        let mut state = ProgramState::new(&boost, vec![1]);
+        state.protect_code();
+        assert_eq!(run(&mut state).unwrap().len(), 1);
+
+        // the amplifier controller reads its phase setting into the target of a jump
+        let amplifier = parse_program(include_str!("../../7/input.txt")).unwrap();
+        let mut state = ProgramState::new(&amplifier, vec![0, 0]);
+        state.protect_code();
+        assert_eq!(
+            run(&mut state),
+            Err(Fault::ProtectedWrite {
+                ip: 0,
+                address: 8,
+                protection: Protection::Code
+            })
+        );
+    }
+
+    #[test]
+    fn test_no_access_and_data_regions() {
+        // reads cell 9, outputs it, then jumps into the data after the halt
+        let program = vec![1001, 9, 1, 9, 4, 9, 1105, 1, 10, 5, 99];
+        let mut state = ProgramState::new(&program, vec![]);
+        state.protect(9..10, Protection::NoAccess);
+        assert_eq!(
+            run(&mut state),
+            Err(Fault::ProtectedRead { ip: 0, address: 9 })
+        );
+
+        let mut state = ProgramState::new(&program, vec![]);
+        state.protect(9..11, Protection::Data);
+        assert_eq!(
+            run(&mut state),
+            Err(Fault::ProtectedExecute {
+                ip: 10,
+                protection: Protection::Data
+            })
+        );
+        assert_eq!(state.program[9], 6);
+    }
+
+    #[test]
+    fn test_watched_writes_are_logged() {
+        let program = vec![1101, 2, 3, 9, 1102, 2, 3, 10, 99, 0, 0];
+        let mut state = ProgramState::new(&program, vec![]);
+        state.watch(10..11);
+        run_program(&mut state, false, |_, _| false);
+        state.write(10, 1);
+
+        let writes = state.memory_map.as_mut().unwrap().take_writes();
+        assert_eq!(
+            writes,
+            vec![
+                WriteEvent {
+                    ip: 4,
+                    address: 10,
+                    old: 0,
+                    new: 6
+                },
+                WriteEvent {
+                    ip: 8,
+                    address: 10,
+                    old: 6,
+                    new: 1
+                }
+            ]
+        );
+        assert_eq!(writes[0].to_string(), "4 wrote 6 over 0 at 10");
+    }
+
+    #[test]
+    #[should_panic(expected = "Write to code at 0 from 8")]
+    fn test_patching_code_from_outside_panics() {
+        let mut state = ProgramState::new(&[1101, 2, 3, 9, 1102, 2, 3, 10, 99, 0, 0], vec![]);
+        run_program(&mut state, false, |_, _| false);
+        state.protect_code();
+        state.write(0, 2);
+    }
+}
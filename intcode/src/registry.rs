@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::decode::{Mode, Opcode, Param};
+use crate::{read_param, write_address, Fault, ProgramState};
+
+/// How an extension opcode uses each of its parameters
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParamKind {
+    /// Resolved to a value; position, immediate and relative modes are all allowed
+    Read,
+    /// Resolved to an address; immediate mode is rejected like it is for built in writes
+    Write,
+}
+
+/// What the machine does after an extension opcode runs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    Continue,
+    Output(i64),
+    Jump(usize),
+    /// Stop running and hand control back to the caller, like blocking on input
+    Yield,
+    Halt,
+}
+
+pub type Handler = dyn Fn(&mut ProgramState, &[i64]) -> Result<Outcome, Fault> + Send + Sync;
+
+#[derive(Clone)]
+pub struct Extension {
+    pub name: String,
+    pub params: Vec<ParamKind>,
+    handler: Arc<Handler>,
+}
+
+impl fmt::Debug for Extension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Extension({}, {:?})", self.name, self.params)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RegistryError {
+    /// Opcodes must fit in the two low digits and can't be 0
+    OutOfRange(i64),
+    BuiltIn(i64),
+    AlreadyRegistered(i64),
+    /// Modes only go three digits deep
+    TooManyParams(usize),
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RegistryError::OutOfRange(opcode) => write!(f, "opcode {} is not in 1..=98", opcode),
+            RegistryError::BuiltIn(opcode) => write!(f, "opcode {} is built in", opcode),
+            RegistryError::AlreadyRegistered(opcode) => {
+                write!(f, "opcode {} is already registered", opcode)
+            }
+            RegistryError::TooManyParams(count) => {
+                write!(f, "{} parameters given, at most 3 are supported", count)
+            }
+        }
+    }
+}
+
+/// Extra opcodes a machine understands on top of the built in ones.
+/// Share one registry between machines with `ProgramState::extensions`.
+#[derive(Clone, Debug, Default)]
+pub struct OpcodeRegistry {
+    extensions: HashMap<i64, Extension>,
+}
+
+impl OpcodeRegistry {
+    pub fn new() -> Self {
+        OpcodeRegistry::default()
+    }
+
+    pub fn register<F>(
+        &mut self,
+        opcode: i64,
+        name: &str,
+        params: Vec<ParamKind>,
+        handler: F,
+    ) -> Result<(), RegistryError>
+    where
+        F: Fn(&mut ProgramState, &[i64]) -> Result<Outcome, Fault> + Send + Sync + 'static,
+    {
+        if !(1..=98).contains(&opcode) {
+            return Err(RegistryError::OutOfRange(opcode));
+        }
+        if Opcode::from_number(opcode).is_some() {
+            return Err(RegistryError::BuiltIn(opcode));
+        }
+        if self.extensions.contains_key(&opcode) {
+            return Err(RegistryError::AlreadyRegistered(opcode));
+        }
+        if params.len() > 3 {
+            return Err(RegistryError::TooManyParams(params.len()));
+        }
+
+        self.extensions.insert(
+            opcode,
+            Extension {
+                name: name.to_string(),
+                params,
+                handler: Arc::new(handler),
+            },
+        );
+
+        Ok(())
+    }
+
+    pub fn get(&self, opcode: i64) -> Option<&Extension> {
+        self.extensions.get(&opcode)
+    }
+}
+
+/// Runs the extension at `state.index`, returning the same tuple as `run_step`
+pub(crate) fn execute(
+    state: &mut ProgramState,
+    registry: &OpcodeRegistry,
+) -> Option<Result<(Option<i64>, bool), Fault>> {
+    let ip = state.index;
+    let word = state.program.get(ip);
+    let extension = registry.get(word % 100)?;
+
+    let invalid = Fault::InvalidOpcode { ip, opcode: word };
+    let mut modes = word / 100;
+    let mut operands = Vec::with_capacity(extension.params.len());
+    for (i, kind) in extension.params.iter().enumerate() {
+        let mode = match modes % 10 {
+            0 => Mode::Position,
+            1 if *kind == ParamKind::Read => Mode::Immediate,
+            2 => Mode::Relative,
+            _ => return Some(Err(invalid)),
+        };
+        modes /= 10;
+        let param = Param {
+            mode,
+            value: state.program.get(ip + 1 + i),
+        };
+        operands.push(match kind {
+            ParamKind::Read => match read_param(state, param) {
+                Ok(value) => value,
+                Err(fault) => return Some(Err(fault)),
+            },
+            ParamKind::Write => match write_address(state, param) {
+                Ok(address) => address as i64,
+                Err(fault) => return Some(Err(fault)),
+            },
+        });
+    }
+    if modes != 0 {
+        return Some(Err(invalid));
+    }
+
+    let outcome = match (extension.handler)(state, &operands) {
+        Ok(outcome) => outcome,
+        Err(fault) => return Some(Err(fault)),
+    };
+    let next = ip + 1 + operands.len();
+    Some(Ok(match outcome {
+        Outcome::Continue => {
+            state.index = next;
+            (None, false)
+        }
+        Outcome::Output(value) => {
+            state.index = next;
+            (Some(value), false)
+        }
+        Outcome::Jump(address) => {
+            state.index = address;
+            (None, false)
+        }
+        Outcome::Yield => {
+            state.index = next;
+            (None, true)
+        }
+        Outcome::Halt => {
+            state.finished = true;
+            (None, true)
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{run_program, run_step};
+
+    #[test]
+    fn test_rejects_built_in_and_duplicate_opcodes() {
+        let mut registry = OpcodeRegistry::new();
+        let noop = |_: &mut ProgramState, _: &[i64]| Ok(Outcome::Continue);
+        assert_eq!(
+            registry.register(4, "print", vec![], noop),
+            Err(RegistryError::BuiltIn(4))
+        );
+        assert_eq!(
+            registry.register(100, "big", vec![], noop),
+            Err(RegistryError::OutOfRange(100))
+        );
+        assert!(registry.register(50, "nop", vec![], noop).is_ok());
+        assert_eq!(
+            registry.register(50, "nop", vec![], noop),
+            Err(RegistryError::AlreadyRegistered(50))
+        );
+    }
+
+    #[test]
+    fn test_debug_print_and_store_extensions() {
+        let printed = Arc::new(Mutex::new(Vec::new()));
+        let log = printed.clone();
+        let mut registry = OpcodeRegistry::new();
+        registry
+            .register(50, "dbg", vec![ParamKind::Read], move |_, operands| {
+                log.lock().unwrap().push(operands[0]);
+                Ok(Outcome::Continue)
+            })
+            .unwrap();
+        // stores the square of its first operand
+        registry
+            .register(
+                51,
+                "sqr",
+                vec![ParamKind::Read, ParamKind::Write],
+                |state, operands| {
+                    state.write(operands[1] as usize, operands[0] * operands[0]);
+                    Ok(Outcome::Continue)
+                },
+            )
+            .unwrap();
+
+        let program = vec![150, 7, 151, 7, 9, 4, 9, 99, 0, 0];
+        let mut state = ProgramState::new(&program, vec![]);
+        state.extensions = Some(Arc::new(registry));
+        let mut outputs = Vec::new();
+        run_program(&mut state, false, |_, output| {
+            outputs.push(output);
+            false
+        });
+
+        assert_eq!(*printed.lock().unwrap(), vec![7]);
+        assert_eq!(outputs, vec![49]);
+    }
+
+    #[test]
+    fn test_yield_returns_control_without_finishing() {
+        let mut registry = OpcodeRegistry::new();
+        registry
+            .register(60, "yield", vec![], |_, _| Ok(Outcome::Yield))
+            .unwrap();
+        let mut state = ProgramState::new(&[60, 104, 1, 99], vec![]);
+        state.extensions = Some(Arc::new(registry));
+
+        assert_eq!(run_step(&mut state, false), (None, true));
+        assert!(!state.finished);
+        assert_eq!(run_step(&mut state, false), (Some(1), false));
+    }
+
+    #[test]
+    fn test_unregistered_opcode_still_faults() {
+        let mut state = ProgramState::new(&[60, 99], vec![]);
+        state.extensions = Some(Arc::new(OpcodeRegistry::new()));
+        assert_eq!(
+            crate::try_run_step(&mut state, false),
+            Err(Fault::InvalidOpcode { ip: 0, opcode: 60 })
+        );
+    }
+}
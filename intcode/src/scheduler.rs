@@ -0,0 +1,365 @@
+//! Running many machines that talk to each other.
+//!
+//! A `Scheduler` takes turns between its machines, running each for a time slice of at most so
+//! many instructions. A slice ends early when the machine halts, faults, needs input it hasn't
+//! been given, or finishes a frame of output, which is however many values make up one message,
+//! so whatever drives the scheduler can pass each message on before the next slice runs.
+//!
+//! Machines that poll for input, like the network controllers that read -1 when no packet has
+//! arrived, are given a poll value instead of blocking. Such a machine is idle once it has polled
+//! without sending or being sent anything since.
+
+use std::fmt;
+
+use crate::{try_run_step, Fault, ProgramState};
+
+/// Why a slice ended
+#[derive(Clone, Debug, PartialEq)]
+pub enum Preempted {
+    /// Ran for the whole slice
+    Expired,
+    /// Needs input that hasn't been given
+    Blocked,
+    /// Produced a complete frame of output
+    Frame(Vec<i64>),
+    Halted,
+    Fault(Fault),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Slice {
+    pub machine: usize,
+    pub steps: usize,
+    pub preempted: Preempted,
+}
+
+/// Why `Scheduler::run` returned
+#[derive(Clone, Debug, PartialEq)]
+pub enum Stop {
+    /// Every machine has halted
+    Halted,
+    /// Every machine still running is waiting for input that nothing will send
+    Deadlock,
+    /// Every machine still running is polling with nothing to do
+    Idle,
+    Fault {
+        machine: usize,
+        fault: Fault,
+    },
+    /// The frame handler asked to stop
+    Stopped,
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Halted => write!(f, "every machine halted"),
+            Stop::Deadlock => write!(f, "every machine is waiting for input"),
+            Stop::Idle => write!(f, "every machine is idle"),
+            Stop::Fault { machine, fault } => write!(f, "machine {}: {}", machine, fault),
+            Stop::Stopped => write!(f, "stopped"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Slot {
+    state: ProgramState,
+    frame: Vec<i64>,
+    idle: bool,
+    fault: Option<Fault>,
+    steps: u64,
+}
+
+impl Slot {
+    fn is_stopped(&self) -> bool {
+        self.state.finished || self.fault.is_some()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Scheduler {
+    slots: Vec<Slot>,
+    slice: usize,
+    frame_len: usize,
+    poll: Option<i64>,
+}
+
+impl Scheduler {
+    /// Runs each machine for up to `slice` instructions a turn, with frames of one output
+    pub fn new(slice: usize) -> Self {
+        Scheduler {
+            slots: Vec::new(),
+            slice: slice.max(1),
+            frame_len: 1,
+            poll: None,
+        }
+    }
+
+    pub fn frame_len(mut self, frame_len: usize) -> Self {
+        self.frame_len = frame_len.max(1);
+        self
+    }
+
+    /// Gives machines `value` when they need input and have none, instead of blocking them
+    pub fn poll_with(mut self, value: i64) -> Self {
+        self.poll = Some(value);
+        self
+    }
+
+    /// Adds a machine, returning its number
+    pub fn add(&mut self, state: ProgramState) -> usize {
+        self.slots.push(Slot {
+            state,
+            frame: Vec::new(),
+            idle: false,
+            fault: None,
+            steps: 0,
+        });
+        self.slots.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn machine(&self, machine: usize) -> &ProgramState {
+        &self.slots[machine].state
+    }
+
+    pub fn machine_mut(&mut self, machine: usize) -> &mut ProgramState {
+        &mut self.slots[machine].state
+    }
+
+    /// Instructions the machine has run
+    pub fn steps(&self, machine: usize) -> u64 {
+        self.slots[machine].steps
+    }
+
+    /// Queues input for a machine, which stops it being idle
+    pub fn send(&mut self, machine: usize, values: &[i64]) {
+        let slot = &mut self.slots[machine];
+        slot.state.inputs.extend(values.iter().cloned());
+        slot.idle = false;
+    }
+
+    fn is_blocked(&self, slot: &Slot) -> bool {
+        self.poll.is_none() && slot.state.is_starving()
+    }
+
+    /// Whether every machine has halted or faulted
+    pub fn is_halted(&self) -> bool {
+        self.slots.iter().all(Slot::is_stopped)
+    }
+
+    /// Whether nothing can run until more input is sent
+    pub fn is_deadlocked(&self) -> bool {
+        let mut running = self
+            .slots
+            .iter()
+            .filter(|slot| !slot.is_stopped())
+            .peekable();
+        running.peek().is_some() && running.all(|slot| self.is_blocked(slot))
+    }
+
+    /// Whether every machine still running has polled without sending or being sent anything
+    pub fn is_idle(&self) -> bool {
+        self.poll.is_some()
+            && self
+                .slots
+                .iter()
+                .filter(|slot| !slot.is_stopped())
+                .all(|slot| slot.idle)
+            && !self.is_halted()
+    }
+
+    /// Runs one machine for up to one slice
+    pub fn run_slice(&mut self, machine: usize) -> Slice {
+        let (slice, frame_len, poll) = (self.slice, self.frame_len, self.poll);
+        let slot = &mut self.slots[machine];
+        let mut steps = 0;
+
+        let preempted = loop {
+            if let Some(fault) = slot.fault.clone() {
+                break Preempted::Fault(fault);
+            }
+            if slot.state.finished {
+                break Preempted::Halted;
+            }
+            if steps == slice {
+                break Preempted::Expired;
+            }
+            if slot.state.is_starving() {
+                match poll {
+                    // a polling machine gets one empty read a turn
+                    Some(value) if steps == 0 => {
+                        slot.state.inputs.push(value);
+                        slot.idle = true;
+                    }
+                    _ => break Preempted::Blocked,
+                }
+            }
+
+            let result = try_run_step(&mut slot.state, true);
+            steps += 1;
+            slot.steps += 1;
+            match result {
+                Err(fault) => slot.fault = Some(fault),
+                Ok((Some(output), _)) => {
+                    slot.idle = false;
+                    slot.frame.push(output);
+                    if slot.frame.len() == frame_len {
+                        break Preempted::Frame(std::mem::take(&mut slot.frame));
+                    }
+                }
+                Ok(_) => {}
+            }
+        };
+
+        Slice {
+            machine,
+            steps,
+            preempted,
+        }
+    }
+
+    /// Gives every machine that can run one slice, in order
+    pub fn round(&mut self) -> Vec<Slice> {
+        let mut slices = Vec::new();
+        for machine in 0..self.slots.len() {
+            let slot = &self.slots[machine];
+            if !slot.is_stopped() && !self.is_blocked(slot) {
+                slices.push(self.run_slice(machine));
+            }
+        }
+        slices
+    }
+
+    /// Runs rounds, passing each frame to `on_frame` as it is made, until every machine has
+    /// halted, the machines deadlock or go idle, one faults, or `on_frame` returns true
+    pub fn run<F>(&mut self, mut on_frame: F) -> Stop
+    where
+        F: FnMut(&mut Scheduler, usize, &[i64]) -> bool,
+    {
+        loop {
+            if self.is_halted() {
+                return Stop::Halted;
+            }
+            if self.is_deadlocked() {
+                return Stop::Deadlock;
+            }
+            if self.is_idle() {
+                return Stop::Idle;
+            }
+
+            for slice in self.round() {
+                match slice.preempted {
+                    Preempted::Frame(frame) if on_frame(self, slice.machine, &frame) => {
+                        return Stop::Stopped
+                    }
+                    Preempted::Fault(fault) => {
+                        return Stop::Fault {
+                            machine: slice.machine,
+                            fault,
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // adds one to each number it reads and prints it, halting once it has printed 11 or more
+    fn increment() -> Vec<i64> {
+        vec![
+            3, 16, 1001, 16, 1, 16, 4, 16, 1007, 16, 11, 17, 1005, 17, 0, 99,
+        ]
+    }
+
+    #[test]
+    fn test_ring_of_machines_passes_frames_on() {
+        let program = increment();
+        let mut scheduler = Scheduler::new(100);
+        for _ in 0..3 {
+            scheduler.add(ProgramState::new(&program, vec![]));
+        }
+        scheduler.send(0, &[0]);
+
+        let mut last = Vec::new();
+        let stop = scheduler.run(|scheduler, machine, frame| {
+            last = frame.to_vec();
+            scheduler.send((machine + 1) % 3, frame);
+            false
+        });
+        // each machine adds one on the way round, and halts once the number it passes on is 11 or
+        // more, which each of them does on its last turn
+        assert_eq!(stop, Stop::Halted);
+        assert_eq!(last, vec![13]);
+        let steps: Vec<u64> = (0..3).map(|machine| scheduler.steps(machine)).collect();
+        assert_eq!(steps, vec![26, 21, 21]);
+    }
+
+    #[test]
+    fn test_deadlock_and_expired_slices() {
+        let mut scheduler = Scheduler::new(2);
+        let machine = scheduler.add(ProgramState::new(&increment(), vec![]));
+        assert!(scheduler.is_deadlocked());
+        assert_eq!(scheduler.run(|_, _, _| false), Stop::Deadlock);
+
+        scheduler.send(machine, &[4]);
+        assert!(!scheduler.is_deadlocked());
+        let slice = scheduler.run_slice(machine);
+        assert_eq!((slice.steps, slice.preempted), (2, Preempted::Expired));
+        let slice = scheduler.run_slice(machine);
+        assert_eq!(
+            (slice.steps, slice.preempted),
+            (1, Preempted::Frame(vec![5]))
+        );
+        assert_eq!(scheduler.run(|_, _, _| false), Stop::Deadlock);
+    }
+
+    #[test]
+    fn test_polling_machines_go_idle() {
+        let mut scheduler = Scheduler::new(50).frame_len(2).poll_with(-1);
+        // reads until it gets something other than -1, then prints it twice
+        let echo = vec![3, 15, 1008, 15, -1, 16, 1005, 16, 0, 4, 15, 4, 15, 99];
+        let machine = scheduler.add(ProgramState::new(&echo, vec![]));
+        assert!(!scheduler.is_idle());
+        assert_eq!(scheduler.round()[0].preempted, Preempted::Blocked);
+        assert!(scheduler.is_idle());
+        assert_eq!(scheduler.run(|_, _, _| false), Stop::Idle);
+
+        scheduler.send(machine, &[7]);
+        let mut frames = Vec::new();
+        let stop = scheduler.run(|_, _, frame| {
+            frames.push(frame.to_vec());
+            false
+        });
+        assert_eq!(frames, vec![vec![7, 7]]);
+        assert_eq!(stop, Stop::Halted);
+    }
+
+    #[test]
+    fn test_faults_stop_the_run() {
+        let mut scheduler = Scheduler::new(10);
+        scheduler.add(ProgramState::new(&[1101, 1, 1, 5, 77, 0], vec![]));
+        scheduler.add(ProgramState::new(&[99], vec![]));
+        assert_eq!(
+            scheduler.run(|_, _, _| false),
+            Stop::Fault {
+                machine: 0,
+                fault: Fault::InvalidOpcode { ip: 4, opcode: 77 }
+            }
+        );
+        assert!(scheduler.is_halted());
+    }
+}
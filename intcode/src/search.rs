@@ -0,0 +1,285 @@
+//! Brute force search for the inputs that make a program produce what you want.
+//!
+//! Each variable is a memory cell patched before the run, or one of the inputs, with a range of
+//! values to try. Every combination is run to completion and checked against a predicate.
+
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{try_run_step, Image, ProgramState};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    /// Memory cell overwritten before the program starts
+    Cell(usize),
+    /// The next input, in the order the variables were added
+    Input,
+}
+
+/// A finished candidate run, handed to the predicate
+pub struct Run<'a> {
+    pub values: &'a [i64],
+    pub state: &'a ProgramState,
+    pub outputs: &'a [i64],
+    /// False when the run blocked waiting for more input than the candidate provided
+    pub halted: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match {
+    /// One value per variable, in the order they were added
+    pub values: Vec<i64>,
+    pub outputs: Vec<i64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchStats {
+    pub candidates: usize,
+    pub matches: usize,
+    /// Runs that blocked on input before halting
+    pub blocked: usize,
+    pub faulted: usize,
+    /// Runs stopped by the step limit
+    pub step_limited: usize,
+    pub steps: u64,
+    pub elapsed: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    /// Matches in enumeration order, with the last variable changing fastest
+    pub matches: Vec<Match>,
+    pub stats: SearchStats,
+}
+
+#[derive(Clone)]
+pub struct Search {
+    image: Arc<Image>,
+    variables: Vec<(Target, RangeInclusive<i64>)>,
+    threads: usize,
+    step_limit: Option<u64>,
+}
+
+impl Search {
+    pub fn new(image: &Arc<Image>) -> Self {
+        Search {
+            image: image.clone(),
+            variables: Vec::new(),
+            threads: 1,
+            step_limit: None,
+        }
+    }
+
+    pub fn cell(mut self, address: usize, values: RangeInclusive<i64>) -> Self {
+        self.variables.push((Target::Cell(address), values));
+        self
+    }
+
+    pub fn input(mut self, values: RangeInclusive<i64>) -> Self {
+        self.variables.push((Target::Input, values));
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Gives up on a candidate after this many instructions, for programs that might not halt
+    pub fn step_limit(mut self, steps: u64) -> Self {
+        self.step_limit = Some(steps);
+        self
+    }
+
+    /// Number of combinations `run` will try
+    pub fn candidates(&self) -> usize {
+        self.variables
+            .iter()
+            .map(|(_, values)| values.clone().count())
+            .product()
+    }
+
+    pub fn run<F>(&self, predicate: F) -> SearchResult
+    where
+        F: Fn(&Run) -> bool + Sync,
+    {
+        let start = Instant::now();
+        let candidates = self.candidates();
+        let predicate = &predicate;
+
+        let parts: Vec<(Vec<(usize, Match)>, SearchStats)> = if self.threads == 1 {
+            vec![self.run_part(0, 1, candidates, predicate)]
+        } else {
+            thread::scope(|scope| {
+                let handles: Vec<_> = (0..self.threads)
+                    .map(|part| {
+                        scope
+                            .spawn(move || self.run_part(part, self.threads, candidates, predicate))
+                    })
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().unwrap())
+                    .collect()
+            })
+        };
+
+        let mut matches = Vec::new();
+        let mut stats = SearchStats::default();
+        for (part_matches, part_stats) in parts {
+            matches.extend(part_matches);
+            stats.candidates += part_stats.candidates;
+            stats.matches += part_stats.matches;
+            stats.blocked += part_stats.blocked;
+            stats.faulted += part_stats.faulted;
+            stats.step_limited += part_stats.step_limited;
+            stats.steps += part_stats.steps;
+        }
+        matches.sort_by_key(|(candidate, _)| *candidate);
+        stats.elapsed = start.elapsed();
+
+        SearchResult {
+            matches: matches.into_iter().map(|(_, found)| found).collect(),
+            stats,
+        }
+    }
+
+    // every `stride`th candidate starting from `first`
+    fn run_part<F>(
+        &self,
+        first: usize,
+        stride: usize,
+        candidates: usize,
+        predicate: &F,
+    ) -> (Vec<(usize, Match)>, SearchStats)
+    where
+        F: Fn(&Run) -> bool,
+    {
+        let mut matches = Vec::new();
+        let mut stats = SearchStats::default();
+        let mut values = vec![0; self.variables.len()];
+
+        for candidate in (first..candidates).step_by(stride) {
+            stats.candidates += 1;
+            self.values_of(candidate, &mut values);
+
+            let mut state = ProgramState::from_image(&self.image, Vec::new());
+            for (value, (target, _)) in values.iter().zip(self.variables.iter()) {
+                match *target {
+                    Target::Cell(address) => state.write(address, *value),
+                    Target::Input => state.inputs.push(*value),
+                }
+            }
+
+            let mut outputs = Vec::new();
+            let mut steps = 0;
+            let halted = loop {
+                if self.step_limit.is_some_and(|limit| steps >= limit) {
+                    stats.step_limited += 1;
+                    break None;
+                }
+                steps += 1;
+                match try_run_step(&mut state, true) {
+                    Ok((Some(output), _)) => outputs.push(output),
+                    Ok((None, true)) => break Some(state.finished),
+                    Ok((None, false)) => {}
+                    Err(_) => {
+                        stats.faulted += 1;
+                        break None;
+                    }
+                }
+            };
+            stats.steps += steps;
+
+            let halted = match halted {
+                Some(halted) => halted,
+                None => continue,
+            };
+            if !halted {
+                stats.blocked += 1;
+            }
+
+            let run = Run {
+                values: &values,
+                state: &state,
+                outputs: &outputs,
+                halted,
+            };
+            if predicate(&run) {
+                stats.matches += 1;
+                matches.push((
+                    candidate,
+                    Match {
+                        values: values.clone(),
+                        outputs,
+                    },
+                ));
+            }
+        }
+
+        (matches, stats)
+    }
+
+    fn values_of(&self, mut candidate: usize, values: &mut [i64]) {
+        for (value, (_, range)) in values.iter_mut().zip(self.variables.iter()).rev() {
+            let count = range.clone().count();
+            *value = range.start() + (candidate % count) as i64;
+            candidate /= count;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_finds_every_matching_cell_pair() {
+        // [0] = [5] * [6]
+        let image = Arc::new(Image::new(vec![2, 5, 6, 0, 99, 0, 0]));
+        let search = Search::new(&image).cell(5, 0..=12).cell(6, 0..=12);
+        assert_eq!(search.candidates(), 169);
+
+        let result = search.threads(3).run(|run| run.state.read(0) == 12);
+        let pairs: Vec<Vec<i64>> = result.matches.into_iter().map(|m| m.values).collect();
+        assert_eq!(
+            pairs,
+            vec![
+                vec![1, 12],
+                vec![2, 6],
+                vec![3, 4],
+                vec![4, 3],
+                vec![6, 2],
+                vec![12, 1]
+            ]
+        );
+        assert_eq!(result.stats.candidates, 169);
+        assert_eq!(result.stats.matches, 6);
+    }
+
+    #[test]
+    fn test_inputs_and_outputs() {
+        // day 5 example: outputs 1 when the input equals 8
+        let image = Arc::new(Image::new(vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8]));
+        let result = Search::new(&image)
+            .input(-20..=20)
+            .run(|run| run.halted && run.outputs == [1]);
+        assert_eq!(result.matches.len(), 1);
+        assert_eq!(result.matches[0].values, vec![8]);
+    }
+
+    #[test]
+    fn test_blocked_and_runaway_candidates_are_counted() {
+        // loops forever when the input is zero, otherwise asks for a second input
+        let image = Arc::new(Image::new(vec![3, 10, 1006, 10, 2, 3, 10, 99, 0, 0, 0]));
+        let result = Search::new(&image)
+            .input(0..=2)
+            .step_limit(1000)
+            .run(|_| true);
+        assert_eq!(result.stats.step_limited, 1);
+        assert_eq!(result.stats.blocked, 2);
+        assert_eq!(result.matches.len(), 2);
+    }
+}
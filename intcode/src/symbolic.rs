@@ -0,0 +1,761 @@
+//! Symbolic execution of intcode programs.
+//!
+//! Chosen memory cells and inputs are variables instead of numbers, and arithmetic builds
+//! expressions over them. Sums and constant multiples stay in a flat linear form, so for programs
+//! like day 2 the final value of a cell comes out as `a*noun + b*verb + c`, which `solve` can
+//! answer directly. A jump on a symbolic condition forks the run, with each side recording the
+//! condition as a path constraint.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::decode::{Mode, Opcode};
+
+/// `constant + sum(coefficient * variable)`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Linear {
+    pub constant: i64,
+    pub terms: BTreeMap<String, i64>,
+}
+
+impl Linear {
+    fn plus(&self, other: &Linear) -> Linear {
+        let mut terms = self.terms.clone();
+        for (name, coefficient) in &other.terms {
+            let entry = terms.entry(name.clone()).or_insert(0);
+            *entry = entry.wrapping_add(*coefficient);
+        }
+        terms.retain(|_, coefficient| *coefficient != 0);
+
+        Linear {
+            constant: self.constant.wrapping_add(other.constant),
+            terms,
+        }
+    }
+
+    fn scale(&self, factor: i64) -> Linear {
+        let mut terms: BTreeMap<String, i64> = self
+            .terms
+            .iter()
+            .map(|(name, coefficient)| (name.clone(), coefficient.wrapping_mul(factor)))
+            .collect();
+        terms.retain(|_, coefficient| *coefficient != 0);
+
+        Linear {
+            constant: self.constant.wrapping_mul(factor),
+            terms,
+        }
+    }
+
+    fn evaluate(&self, values: &BTreeMap<String, i64>) -> Option<i64> {
+        let mut total = self.constant;
+        for (name, coefficient) in &self.terms {
+            total = total.wrapping_add(coefficient.wrapping_mul(*values.get(name)?));
+        }
+        Some(total)
+    }
+}
+
+impl fmt::Display for Linear {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut first = true;
+        for (name, coefficient) in &self.terms {
+            match (first, *coefficient < 0) {
+                (true, true) => write!(f, "-")?,
+                (true, false) => {}
+                (false, true) => write!(f, " - ")?,
+                (false, false) => write!(f, " + ")?,
+            }
+            if coefficient.abs() != 1 {
+                write!(f, "{}*", coefficient.abs())?;
+            }
+            write!(f, "{}", name)?;
+            first = false;
+        }
+        if first {
+            write!(f, "{}", self.constant)
+        } else if self.constant > 0 {
+            write!(f, " + {}", self.constant)
+        } else if self.constant < 0 {
+            write!(f, " - {}", -self.constant)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Linear(Linear),
+    Add(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    LessThan(Box<Expr>, Box<Expr>),
+    Equals(Box<Expr>, Box<Expr>),
+    /// A value that could not be tracked, like a read from an address that depends on a variable
+    Opaque(String),
+}
+
+impl Expr {
+    pub fn constant(value: i64) -> Self {
+        Expr::Linear(Linear {
+            constant: value,
+            terms: BTreeMap::new(),
+        })
+    }
+
+    pub fn var(name: &str) -> Self {
+        let mut terms = BTreeMap::new();
+        terms.insert(name.to_string(), 1);
+        Expr::Linear(Linear { constant: 0, terms })
+    }
+
+    pub fn as_constant(&self) -> Option<i64> {
+        match *self {
+            Expr::Linear(ref linear) if linear.terms.is_empty() => Some(linear.constant),
+            _ => None,
+        }
+    }
+
+    pub fn as_linear(&self) -> Option<&Linear> {
+        match *self {
+            Expr::Linear(ref linear) => Some(linear),
+            _ => None,
+        }
+    }
+
+    pub fn sum(lhs: Expr, rhs: Expr) -> Expr {
+        match (lhs, rhs) {
+            (Expr::Linear(a), Expr::Linear(b)) => Expr::Linear(a.plus(&b)),
+            (a, b) => {
+                if a.as_constant() == Some(0) {
+                    b
+                } else if b.as_constant() == Some(0) {
+                    a
+                } else {
+                    Expr::Add(Box::new(a), Box::new(b))
+                }
+            }
+        }
+    }
+
+    pub fn product(lhs: Expr, rhs: Expr) -> Expr {
+        match (lhs.as_constant(), rhs.as_constant()) {
+            (Some(0), _) | (_, Some(0)) => Expr::constant(0),
+            (Some(1), _) => rhs,
+            (_, Some(1)) => lhs,
+            (Some(factor), _) => match rhs {
+                Expr::Linear(linear) => Expr::Linear(linear.scale(factor)),
+                other => Expr::Mul(Box::new(Expr::constant(factor)), Box::new(other)),
+            },
+            (_, Some(factor)) => match lhs {
+                Expr::Linear(linear) => Expr::Linear(linear.scale(factor)),
+                other => Expr::Mul(Box::new(other), Box::new(Expr::constant(factor))),
+            },
+            (None, None) => Expr::Mul(Box::new(lhs), Box::new(rhs)),
+        }
+    }
+
+    pub fn less_than(lhs: Expr, rhs: Expr) -> Expr {
+        if let Some(difference) = Expr::difference(&lhs, &rhs) {
+            return Expr::constant((difference < 0) as i64);
+        }
+        Expr::LessThan(Box::new(lhs), Box::new(rhs))
+    }
+
+    pub fn equals(lhs: Expr, rhs: Expr) -> Expr {
+        if let Some(difference) = Expr::difference(&lhs, &rhs) {
+            return Expr::constant((difference == 0) as i64);
+        }
+        Expr::Equals(Box::new(lhs), Box::new(rhs))
+    }
+
+    // lhs - rhs when the variables cancel out
+    fn difference(lhs: &Expr, rhs: &Expr) -> Option<i64> {
+        let difference = lhs.as_linear()?.plus(&rhs.as_linear()?.scale(-1));
+        if difference.terms.is_empty() {
+            Some(difference.constant)
+        } else {
+            None
+        }
+    }
+
+    pub fn evaluate(&self, values: &BTreeMap<String, i64>) -> Option<i64> {
+        match *self {
+            Expr::Linear(ref linear) => linear.evaluate(values),
+            Expr::Add(ref a, ref b) => Some(a.evaluate(values)?.wrapping_add(b.evaluate(values)?)),
+            Expr::Mul(ref a, ref b) => Some(a.evaluate(values)?.wrapping_mul(b.evaluate(values)?)),
+            Expr::LessThan(ref a, ref b) => {
+                Some((a.evaluate(values)? < b.evaluate(values)?) as i64)
+            }
+            Expr::Equals(ref a, ref b) => Some((a.evaluate(values)? == b.evaluate(values)?) as i64),
+            Expr::Opaque(_) => None,
+        }
+    }
+
+    pub fn variables(&self) -> BTreeSet<String> {
+        let mut variables = BTreeSet::new();
+        self.collect_variables(&mut variables);
+        variables
+    }
+
+    fn collect_variables(&self, variables: &mut BTreeSet<String>) {
+        match *self {
+            Expr::Linear(ref linear) => variables.extend(linear.terms.keys().cloned()),
+            Expr::Add(ref a, ref b)
+            | Expr::Mul(ref a, ref b)
+            | Expr::LessThan(ref a, ref b)
+            | Expr::Equals(ref a, ref b) => {
+                a.collect_variables(variables);
+                b.collect_variables(variables);
+            }
+            Expr::Opaque(_) => {}
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Expr::Linear(ref linear) => write!(f, "{}", linear),
+            Expr::Add(ref a, ref b) => write!(f, "({} + {})", a, b),
+            Expr::Mul(ref a, ref b) => write!(f, "({} * {})", a, b),
+            Expr::LessThan(ref a, ref b) => write!(f, "({} < {})", a, b),
+            Expr::Equals(ref a, ref b) => write!(f, "({} == {})", a, b),
+            Expr::Opaque(ref description) => write!(f, "?{}", description),
+        }
+    }
+}
+
+/// `expr` is non zero when `nonzero` is true, and zero otherwise
+#[derive(Clone, Debug, PartialEq)]
+pub struct Constraint {
+    pub expr: Expr,
+    pub nonzero: bool,
+}
+
+impl Constraint {
+    pub fn equals(expr: Expr, value: i64) -> Self {
+        Constraint {
+            expr: Expr::equals(expr, Expr::constant(value)),
+            nonzero: true,
+        }
+    }
+
+    pub fn holds(&self, values: &BTreeMap<String, i64>) -> Option<bool> {
+        Some((self.expr.evaluate(values)? != 0) == self.nonzero)
+    }
+
+    // a linear expression that has to be zero for this constraint to hold
+    fn linear_zero(&self) -> Option<Linear> {
+        match (self.expr.clone(), self.nonzero) {
+            (Expr::Linear(linear), false) => Some(linear),
+            (Expr::Equals(a, b), true) => Some(a.as_linear()?.plus(&b.as_linear()?.scale(-1))),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} 0",
+            self.expr,
+            if self.nonzero { "!=" } else { "==" }
+        )
+    }
+}
+
+/// Finds values within `domains` for which every constraint holds.
+///
+/// When one of the constraints is a linear equation, its variable with the smallest coefficient
+/// is solved for directly and only the others are enumerated. Otherwise every combination is tried.
+pub fn solve(
+    constraints: &[Constraint],
+    domains: &BTreeMap<String, RangeInclusive<i64>>,
+) -> Option<BTreeMap<String, i64>> {
+    let mut variables = BTreeSet::new();
+    for constraint in constraints {
+        constraint.expr.collect_variables(&mut variables);
+    }
+    if variables.iter().any(|name| !domains.contains_key(name)) {
+        return None;
+    }
+
+    let equation = constraints
+        .iter()
+        .filter_map(|constraint| constraint.linear_zero())
+        .find(|linear| !linear.terms.is_empty());
+    let solved = equation.as_ref().map(|linear| {
+        let (name, coefficient) = linear
+            .terms
+            .iter()
+            .min_by_key(|(_, coefficient)| coefficient.abs())
+            .unwrap();
+        (name.clone(), *coefficient)
+    });
+
+    let enumerated: Vec<String> = variables
+        .into_iter()
+        .filter(|name| solved.as_ref().is_none_or(|(solved, _)| solved != name))
+        .collect();
+    let mut values = BTreeMap::new();
+
+    search(
+        &enumerated,
+        0,
+        &mut values,
+        &mut |values| {
+            if let (Some(equation), Some((name, coefficient))) = (&equation, &solved) {
+                let mut rest = equation.clone();
+                rest.terms.remove(name);
+                let rest = rest.evaluate(values)?;
+                if rest % coefficient != 0 {
+                    return None;
+                }
+                let value = -rest / coefficient;
+                if !domains[name].contains(&value) {
+                    return None;
+                }
+                values.insert(name.clone(), value);
+            }
+            if constraints
+                .iter()
+                .all(|constraint| constraint.holds(values) == Some(true))
+            {
+                Some(values.clone())
+            } else {
+                None
+            }
+        },
+        domains,
+    )
+}
+
+fn search<F>(
+    names: &[String],
+    depth: usize,
+    values: &mut BTreeMap<String, i64>,
+    check: &mut F,
+    domains: &BTreeMap<String, RangeInclusive<i64>>,
+) -> Option<BTreeMap<String, i64>>
+where
+    F: FnMut(&mut BTreeMap<String, i64>) -> Option<BTreeMap<String, i64>>,
+{
+    if depth == names.len() {
+        let mut candidate = values.clone();
+        return check(&mut candidate);
+    }
+
+    for value in domains[&names[depth]].clone() {
+        values.insert(names[depth].clone(), value);
+        if let Some(found) = search(names, depth + 1, values, check, domains) {
+            return Some(found);
+        }
+    }
+
+    None
+}
+
+/// Why a path stopped
+#[derive(Clone, Debug, PartialEq)]
+pub enum Stop {
+    Halted,
+    NeedsInput,
+    StepLimit,
+    /// Reached a symbolic branch at `ip` when there was no room left for another path
+    PathLimit {
+        ip: usize,
+    },
+    InvalidOpcode {
+        ip: usize,
+        opcode: i64,
+    },
+    /// The word at `ip` to be run as an instruction depends on a variable
+    SymbolicCode {
+        ip: usize,
+    },
+    /// A jump target depends on a variable
+    SymbolicJump {
+        ip: usize,
+    },
+    /// A write address or relative base adjustment depends on a variable
+    SymbolicAddress {
+        ip: usize,
+    },
+}
+
+/// One way through the program
+#[derive(Clone, Debug)]
+pub struct Path {
+    pub outputs: Vec<Expr>,
+    pub constraints: Vec<Constraint>,
+    pub stop: Stop,
+    base: Vec<i64>,
+    memory: HashMap<usize, Expr>,
+}
+
+impl Path {
+    /// Final value of a memory cell
+    pub fn cell(&self, address: usize) -> Expr {
+        read_cell(&self.base, &self.memory, address)
+    }
+}
+
+fn read_cell(base: &[i64], memory: &HashMap<usize, Expr>, address: usize) -> Expr {
+    match memory.get(&address) {
+        Some(expr) => expr.clone(),
+        None => Expr::constant(*base.get(address).unwrap_or(&0)),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SymbolicMachine {
+    base: Vec<i64>,
+    memory: HashMap<usize, Expr>,
+    ip: usize,
+    relative_base: i64,
+    inputs: VecDeque<Expr>,
+    outputs: Vec<Expr>,
+    constraints: Vec<Constraint>,
+    steps: usize,
+}
+
+enum Step {
+    Continue,
+    Fork(Constraint),
+    Stop(Stop),
+}
+
+impl SymbolicMachine {
+    pub fn new(program: &[i64]) -> Self {
+        SymbolicMachine {
+            base: program.to_vec(),
+            memory: HashMap::new(),
+            ip: 0,
+            relative_base: 0,
+            inputs: VecDeque::new(),
+            outputs: Vec::new(),
+            constraints: Vec::new(),
+            steps: 0,
+        }
+    }
+
+    /// Replaces the memory cell at `address` with a variable
+    pub fn symbolic_cell(&mut self, address: usize, name: &str) {
+        self.memory.insert(address, Expr::var(name));
+    }
+
+    /// Queues an input, either a constant or an expression over variables
+    pub fn input(&mut self, value: Expr) {
+        self.inputs.push_back(value);
+    }
+
+    /// Runs every path, forking on symbolic branches, until each stops or `step_limit`
+    /// instructions have run on it. At most `max_paths` paths are returned; a path that would
+    /// fork past that stops at the branch with `Stop::PathLimit`.
+    pub fn explore(self, step_limit: usize, max_paths: usize) -> Vec<Path> {
+        let mut paths = Vec::new();
+        let mut pending = vec![self];
+
+        while let Some(mut machine) = pending.pop() {
+            let stop = loop {
+                if machine.steps >= step_limit {
+                    break Stop::StepLimit;
+                }
+                machine.steps += 1;
+                match machine.step() {
+                    Step::Continue => {}
+                    Step::Stop(stop) => break stop,
+                    Step::Fork(taken) => {
+                        // this path and the other side of the branch both need room
+                        if paths.len() + pending.len() + 2 > max_paths {
+                            break Stop::PathLimit { ip: machine.ip };
+                        }
+                        let mut other = machine.clone();
+                        other.constraints.push(Constraint {
+                            expr: taken.expr.clone(),
+                            nonzero: !taken.nonzero,
+                        });
+                        other.ip = other.fallthrough();
+                        pending.push(other);
+                        machine.ip = machine.jump_target().unwrap();
+                        machine.constraints.push(taken);
+                    }
+                }
+            };
+            paths.push(machine.finish(stop));
+        }
+
+        paths
+    }
+
+    fn finish(self, stop: Stop) -> Path {
+        Path {
+            outputs: self.outputs,
+            constraints: self.constraints,
+            stop,
+            base: self.base,
+            memory: self.memory,
+        }
+    }
+
+    fn cell(&self, address: usize) -> Expr {
+        read_cell(&self.base, &self.memory, address)
+    }
+
+    fn concrete_cell(&self, address: usize) -> Option<i64> {
+        self.cell(address).as_constant()
+    }
+
+    fn mode(&self, param: usize) -> Option<Mode> {
+        let word = self.concrete_cell(self.ip)?;
+        match (word / 10i64.pow(param as u32 + 2)) % 10 {
+            0 => Some(Mode::Position),
+            1 => Some(Mode::Immediate),
+            2 => Some(Mode::Relative),
+            _ => None,
+        }
+    }
+
+    fn read(&self, param: usize) -> Expr {
+        let raw = self.cell(self.ip + 1 + param);
+        let mode = self.mode(param).unwrap();
+        if mode == Mode::Immediate {
+            return raw;
+        }
+        match raw.as_constant() {
+            Some(value) => {
+                let base = if mode == Mode::Relative {
+                    self.relative_base
+                } else {
+                    0
+                };
+                self.cell((value + base) as usize)
+            }
+            None => Expr::Opaque(format!("[{}] read by {}", raw, self.ip)),
+        }
+    }
+
+    fn write_address(&self, param: usize) -> Option<usize> {
+        let raw = self.concrete_cell(self.ip + 1 + param)?;
+        match self.mode(param)? {
+            Mode::Relative => Some((raw + self.relative_base) as usize),
+            _ => Some(raw as usize),
+        }
+    }
+
+    fn jump_target(&self) -> Option<usize> {
+        self.read(1).as_constant().map(|target| target as usize)
+    }
+
+    fn fallthrough(&self) -> usize {
+        self.ip + 3
+    }
+
+    fn store(&mut self, param: usize, value: Expr) -> Step {
+        match self.write_address(param) {
+            Some(address) => {
+                self.memory.insert(address, value);
+                Step::Continue
+            }
+            None => Step::Stop(Stop::SymbolicAddress { ip: self.ip }),
+        }
+    }
+
+    fn step(&mut self) -> Step {
+        let ip = self.ip;
+        let word = match self.concrete_cell(ip) {
+            Some(word) => word,
+            None => return Step::Stop(Stop::SymbolicCode { ip }),
+        };
+        let opcode = match Opcode::from_number(word % 100) {
+            Some(opcode) if (0..opcode.arity()).all(|param| self.mode(param).is_some()) => opcode,
+            _ => return Step::Stop(Stop::InvalidOpcode { ip, opcode: word }),
+        };
+
+        let result = match opcode {
+            Opcode::Add => {
+                let value = Expr::sum(self.read(0), self.read(1));
+                self.store(2, value)
+            }
+            Opcode::Multiply => {
+                let value = Expr::product(self.read(0), self.read(1));
+                self.store(2, value)
+            }
+            Opcode::LessThan => {
+                let value = Expr::less_than(self.read(0), self.read(1));
+                self.store(2, value)
+            }
+            Opcode::Equals => {
+                let value = Expr::equals(self.read(0), self.read(1));
+                self.store(2, value)
+            }
+            Opcode::Input => match self.inputs.pop_front() {
+                Some(value) => self.store(0, value),
+                None => return Step::Stop(Stop::NeedsInput),
+            },
+            Opcode::Output => {
+                let value = self.read(0);
+                self.outputs.push(value);
+                Step::Continue
+            }
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                let condition = self.read(0);
+                let jump_when_nonzero = opcode == Opcode::JumpIfTrue;
+                let taken = match condition.as_constant() {
+                    Some(value) => (value != 0) == jump_when_nonzero,
+                    None => {
+                        if self.jump_target().is_none() {
+                            return Step::Stop(Stop::SymbolicJump { ip });
+                        }
+                        return Step::Fork(Constraint {
+                            expr: condition,
+                            nonzero: jump_when_nonzero,
+                        });
+                    }
+                };
+                if taken {
+                    return match self.jump_target() {
+                        Some(target) => {
+                            self.ip = target;
+                            Step::Continue
+                        }
+                        None => Step::Stop(Stop::SymbolicJump { ip }),
+                    };
+                }
+                Step::Continue
+            }
+            Opcode::AdjustBase => match self.read(0).as_constant() {
+                Some(value) => {
+                    self.relative_base += value;
+                    Step::Continue
+                }
+                None => return Step::Stop(Stop::SymbolicAddress { ip }),
+            },
+            Opcode::Halt => return Step::Stop(Stop::Halted),
+        };
+
+        if let Step::Continue = result {
+            self.ip = ip + opcode.arity() + 1;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn domains(
+        names: &[&str],
+        range: RangeInclusive<i64>,
+    ) -> BTreeMap<String, RangeInclusive<i64>> {
+        names
+            .iter()
+            .map(|name| (name.to_string(), range.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_simplifier_keeps_linear_form() {
+        let x = Expr::var("x");
+        let expr = Expr::sum(
+            Expr::product(Expr::constant(3), x.clone()),
+            Expr::constant(4),
+        );
+        let expr = Expr::sum(Expr::product(expr, Expr::constant(2)), x);
+        assert_eq!(expr.to_string(), "7*x + 8");
+        assert_eq!(Expr::equals(expr.clone(), expr).as_constant(), Some(1));
+    }
+
+    #[test]
+    fn test_day_two_is_solved_without_brute_force() {
+        let program = crate::get_base_program(include_str!("../../2/input.txt"));
+        let mut machine = SymbolicMachine::new(&program);
+        machine.symbolic_cell(1, "noun");
+        machine.symbolic_cell(2, "verb");
+        let paths = machine.explore(10_000, 4);
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].stop, Stop::Halted);
+
+        let output = paths[0].cell(0);
+        assert_eq!(output.variables().len(), 2);
+        let target = 19690720;
+        let solution = solve(
+            &[Constraint::equals(output, target)],
+            &domains(&["noun", "verb"], 0..=99),
+        )
+        .unwrap();
+
+        let mut state = crate::ProgramState::new(&program, vec![]);
+        state.write(1, solution["noun"]);
+        state.write(2, solution["verb"]);
+        crate::run_program(&mut state, false, |_, _| false);
+        assert_eq!(state.program[0], target);
+    }
+
+    #[test]
+    fn test_finds_the_input_a_diagnostic_accepts() {
+        // day 5 example: 999 below eight, 1000 for eight and 1001 above
+        let program = vec![
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0,
+            0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4,
+            20, 1105, 1, 46, 98, 99,
+        ];
+        let mut machine = SymbolicMachine::new(&program);
+        machine.input(Expr::var("input"));
+        let paths = machine.explore(1000, 16);
+        assert_eq!(paths.len(), 3);
+
+        // the eight branch outputs 125 * input, so the output has to be part of the question
+        let solutions: Vec<i64> = paths
+            .iter()
+            .filter_map(|path| {
+                let mut constraints = path.constraints.clone();
+                constraints.push(Constraint::equals(path.outputs[0].clone(), 1000));
+                solve(&constraints, &domains(&["input"], -100..=100))
+            })
+            .map(|solution| solution["input"])
+            .collect();
+        assert_eq!(solutions, vec![8]);
+
+        let above = paths
+            .iter()
+            .find(|path| path.outputs[0].as_constant() == Some(1001))
+            .unwrap();
+        let solution = solve(&above.constraints, &domains(&["input"], -100..=100)).unwrap();
+        assert!(solution["input"] > 8);
+    }
+
+    #[test]
+    fn test_forking_stops_at_the_path_limit() {
+        // the day 5 example above, which has three ways through
+        let program = vec![
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0,
+            0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4,
+            20, 1105, 1, 46, 98, 99,
+        ];
+        let mut machine = SymbolicMachine::new(&program);
+        machine.input(Expr::var("input"));
+        let paths = machine.clone().explore(1000, 2);
+        assert_eq!(paths.len(), 2);
+        let stops: Vec<&Stop> = paths.iter().map(|path| &path.stop).collect();
+        assert!(stops.contains(&&Stop::Halted));
+        assert!(stops.contains(&&Stop::PathLimit { ip: 13 }));
+
+        let paths = machine.explore(1000, 1);
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].stop, Stop::PathLimit { ip: 6 });
+    }
+
+    #[test]
+    fn test_self_modifying_code_stops_the_path() {
+        // day 5 style: the input is added to an opcode before it runs
+        let mut machine = SymbolicMachine::new(&[3, 9, 1, 9, 6, 6, 104, 0, 99, 0]);
+        machine.input(Expr::var("x"));
+        let paths = machine.explore(100, 4);
+        assert_eq!(paths[0].stop, Stop::SymbolicCode { ip: 6 });
+    }
+}
@@ -0,0 +1,317 @@
+//! Helpers for testing programs.
+//!
+//! `intcode_test!` runs a small program, usually one of the examples from a puzzle statement,
+//! and checks what it printed and what it left in memory:
+//!
+//! ```
+//! #[macro_use]
+//! extern crate intcode;
+//!
+//! # fn main() {
+//! intcode_test!(program: "1,9,10,3,2,3,11,0,99,30,40,50", memory_after: {0 => 3500, 3 => 70});
+//! intcode_test!(program: "3,0,4,0,99", inputs: [7], outputs: [7]);
+//! # }
+//! ```
+//!
+//! Only the parts given are checked. The fields have to come in that order, `program`, then
+//! `inputs`, `outputs` and `memory_after`.
+//!
+//! `puzzle_examples` lists the examples from the statements of days 2, 5 and 9, for this crate's
+//! tests and for checking the other interpreters in the workspace against.
+//!
+//! Programs that talk in ASCII are easier to check against a golden file, the expected text
+//! kept next to the tests. `assert_golden` compares text with the file, and rewrites the file
+//! instead when `UPDATE_GOLDEN` is set, so a deliberate change can be reviewed as a diff.
+
+use std::collections::VecDeque;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use crate::bus::Peripheral;
+use crate::{parse_program, run_device, try_run_program, Bus, ProgramState};
+
+#[macro_export]
+macro_rules! intcode_test {
+    (program: $program:expr
+     $(, inputs: [$($input:expr),* $(,)?])?
+     $(, outputs: [$($output:expr),* $(,)?])?
+     $(, memory_after: {$($address:expr => $value:expr),* $(,)?})?
+     $(,)?) => {
+        $crate::testing::Example::new($program)
+            $(.inputs(&[$($input),*]))?
+            $(.outputs(&[$($output),*]))?
+            $(.memory_after(&[$(($address, $value)),*]))?
+            .check()
+    };
+}
+
+/// A program to run and what it should do, as built by `intcode_test!`
+#[derive(Clone, Debug, Default)]
+pub struct Example {
+    pub program: String,
+    pub inputs: Vec<i64>,
+    /// `None` when the outputs aren't checked
+    pub outputs: Option<Vec<i64>>,
+    pub memory_after: Vec<(usize, i64)>,
+}
+
+impl Example {
+    pub fn new(program: &str) -> Self {
+        Example {
+            program: program.to_string(),
+            ..Example::default()
+        }
+    }
+
+    pub fn inputs(mut self, inputs: &[i64]) -> Self {
+        self.inputs = inputs.to_vec();
+        self
+    }
+
+    pub fn outputs(mut self, outputs: &[i64]) -> Self {
+        self.outputs = Some(outputs.to_vec());
+        self
+    }
+
+    pub fn memory_after(mut self, cells: &[(usize, i64)]) -> Self {
+        self.memory_after = cells.to_vec();
+        self
+    }
+
+    /// Runs the program until it halts, and panics if it printed anything other than the
+    /// outputs or left other values in the cells given
+    pub fn check(&self) {
+        let program = &self.program;
+        let base_program =
+            parse_program(program).unwrap_or_else(|error| panic!("{}\n  in {}", error, program));
+        let mut state = ProgramState::new(&base_program, self.inputs.clone());
+        let mut printed = Vec::new();
+        if let Err(fault) = try_run_program(&mut state, true, |_, output| {
+            printed.push(output);
+            false
+        }) {
+            panic!("{}\n  in {}", state.fault_report(&fault), program);
+        }
+
+        if !state.finished {
+            panic!(
+                "waiting for more input at {} after reading {:?}\n  in {}",
+                state.index, self.inputs, program
+            );
+        }
+        if let Some(outputs) = &self.outputs {
+            assert_eq!(&printed, outputs, "outputs of {}", program);
+        }
+        for &(address, value) in &self.memory_after {
+            assert_eq!(
+                state.read(address),
+                value,
+                "memory at {} after running {}",
+                address,
+                program
+            );
+        }
+    }
+}
+
+/// The examples from the puzzle statements of days 2, 5 and 9, each with a name
+pub fn puzzle_examples() -> Vec<(&'static str, Example)> {
+    let mut examples = Vec::new();
+    for (name, program, cells) in &[
+        (
+            "day 2 example",
+            "1,9,10,3,2,3,11,0,99,30,40,50",
+            vec![(0, 3500), (3, 70)],
+        ),
+        ("day 2 add", "1,0,0,0,99", vec![(0, 2)]),
+        ("day 2 multiply", "2,3,0,3,99", vec![(3, 6)]),
+        ("day 2 square", "2,4,4,5,99,0", vec![(5, 9801)]),
+        (
+            "day 2 overwrite",
+            "1,1,1,4,99,5,6,0,99",
+            vec![(0, 30), (4, 2)],
+        ),
+        ("day 5 negative", "1101,100,-1,4,0", vec![(4, 99)]),
+    ] {
+        examples.push((*name, Example::new(program).memory_after(cells)));
+    }
+    examples.push((
+        "day 5 modes",
+        Example::new("1002,4,3,4,33")
+            .outputs(&[])
+            .memory_after(&[(4, 99)]),
+    ));
+
+    // equal to 8 and less than 8, in position mode and then immediate mode
+    let equal_position = "3,9,8,9,10,9,4,9,99,-1,8";
+    let less_position = "3,9,7,9,10,9,4,9,99,-1,8";
+    let equal_immediate = "3,3,1108,-1,8,3,4,3,99";
+    let less_immediate = "3,3,1107,-1,8,3,4,3,99";
+    // print 0 if the input was 0 and 1 otherwise
+    let jump_position = "3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9";
+    let jump_immediate = "3,3,1105,-1,9,1101,0,0,12,4,12,99,1";
+    // 999 below eight, 1000 for eight and 1001 above
+    let compare = "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,36,98,0,0,1002,21,\
+                   125,20,4,20,1105,1,46,104,999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99";
+    for &(name, program, input, output) in &[
+        ("day 5 echo", "3,0,4,0,99", 42, 42),
+        ("day 5 equal, position", equal_position, 8, 1),
+        ("day 5 not equal, position", equal_position, 7, 0),
+        ("day 5 less, position", less_position, 5, 1),
+        ("day 5 not less, position", less_position, 8, 0),
+        ("day 5 equal, immediate", equal_immediate, 8, 1),
+        ("day 5 not equal, immediate", equal_immediate, 9, 0),
+        ("day 5 less, immediate", less_immediate, 7, 1),
+        ("day 5 not less, immediate", less_immediate, 9, 0),
+        ("day 5 jump on zero, position", jump_position, 0, 0),
+        ("day 5 jump on nonzero, position", jump_position, 3, 1),
+        ("day 5 jump on negative, position", jump_position, -2, 1),
+        ("day 5 jump on zero, immediate", jump_immediate, 0, 0),
+        ("day 5 jump on nonzero, immediate", jump_immediate, 3, 1),
+        ("day 5 jump on negative, immediate", jump_immediate, -2, 1),
+        ("day 5 compare, below", compare, 7, 999),
+        ("day 5 compare, equal", compare, 8, 1000),
+        ("day 5 compare, above", compare, 9, 1001),
+    ] {
+        examples.push((
+            name,
+            Example::new(program).inputs(&[input]).outputs(&[output]),
+        ));
+    }
+
+    let quine = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+    examples.push((
+        "day 9 quine",
+        Example::new(quine)
+            .outputs(&parse_program(quine).unwrap())
+            .memory_after(&[(100, 16), (101, 1)]),
+    ));
+    for &(name, program, output) in &[
+        (
+            "day 9 big product",
+            "1102,34915192,34915192,7,4,7,99,0",
+            1_219_070_632_396_864,
+        ),
+        (
+            "day 9 big literal",
+            "104,1125899906842624,99",
+            1_125_899_906_842_624,
+        ),
+    ] {
+        examples.push((name, Example::new(program).outputs(&[output])));
+    }
+
+    examples
+}
+
+// everything a program prints, with values too big for ASCII written as numbers in line
+#[derive(Default)]
+struct Printout {
+    typed: VecDeque<i64>,
+    text: String,
+}
+
+impl Peripheral for Printout {
+    fn receive(&mut self, frame: &[i64]) {
+        match frame[0] {
+            value @ 0..=127 => self.text.push(value as u8 as char),
+            value => self.text.push_str(&value.to_string()),
+        }
+    }
+
+    fn send(&mut self) -> Option<i64> {
+        self.typed.pop_front()
+    }
+}
+
+/// Types `lines` into an ASCII program and returns everything it prints before it halts or
+/// wants more
+pub fn ascii_output(program: &[i64], lines: &[&str]) -> String {
+    let mut printout = Printout {
+        typed: lines
+            .iter()
+            .flat_map(|line| line.bytes().chain(Some(b'\n')))
+            .map(i64::from)
+            .collect(),
+        text: String::new(),
+    };
+    let mut state = ProgramState::new(program, vec![]);
+    run_device(&mut state, &mut Bus::new(1).attach(&mut printout));
+    printout.text
+}
+
+/// Panics unless `actual` is the text in the golden file at `path`, naming the first line that
+/// differs. With `UPDATE_GOLDEN` set the file is written instead.
+pub fn assert_golden<P: AsRef<Path>>(path: P, actual: &str) {
+    let path = path.as_ref();
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(path, actual)
+            .unwrap_or_else(|error| panic!("writing {}: {}", path.display(), error));
+        return;
+    }
+
+    let expected = fs::read_to_string(path).unwrap_or_else(|error| {
+        panic!(
+            "reading {}: {}\n  run with UPDATE_GOLDEN=1 to create it",
+            path.display(),
+            error
+        )
+    });
+    if expected == actual {
+        return;
+    }
+
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    for line in 1.. {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(expected), Some(actual)) if expected == actual => continue,
+            (None, None) => break,
+            (expected, actual) => panic!(
+                "{} differs at line {}\n  expected: {:?}\n    actual: {:?}\n  run with UPDATE_GOLDEN=1 to accept the new output",
+                path.display(),
+                line,
+                expected.unwrap_or("<end of file>"),
+                actual.unwrap_or("<end of output>")
+            ),
+        }
+    }
+    panic!(
+        "{} differs only in its line endings or final newline",
+        path.display()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_macro_checks_only_what_it_is_given() {
+        intcode_test!(program: "99");
+        intcode_test!(program: "1,0,0,0,99", memory_after: {0 => 2, 4 => 99,});
+        intcode_test!(program: "3,0,4,0,99", inputs: [-5], outputs: [-5]);
+    }
+
+    #[test]
+    #[should_panic(expected = "outputs of 104,1,99")]
+    fn test_macro_reports_wrong_outputs() {
+        intcode_test!(program: "104,1,99", outputs: [2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "waiting for more input at 2")]
+    fn test_example_has_to_halt() {
+        intcode_test!(program: "3,0,3,0,99", inputs: [1]);
+    }
+
+    #[test]
+    fn test_ascii_output() {
+        // prints what it reads, and 1000 at the end of the line
+        let program = vec![
+            3, 15, 4, 15, 1008, 15, 10, 16, 1006, 16, 0, 104, 1000, 99, 0, 0,
+        ];
+        assert_eq!(ascii_output(&program, &["ok", "not read"]), "ok\n1000");
+    }
+}
@@ -0,0 +1,365 @@
+//! Recording and replaying the inputs of a session.
+//!
+//! A machine recording a transcript notes every input it reads along with how many outputs it
+//! had produced when it read it. Saved to a file, the transcript can be fed to a fresh machine to
+//! play the same session again without whatever chose the inputs the first time, and the replay's
+//! own transcript shows whether it really went the same way.
+//!
+//! The file has one input per line, the output count and then the value, with `#` comments.
+//! Programs that take `record <path>` or `replay <path>` arguments read them with
+//! `TranscriptMode::from_args`.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{ParseError, ProgramState};
+
+/// An input as it was read
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TranscriptEntry {
+    /// Outputs the machine had produced before reading it
+    pub outputs: u64,
+    pub value: i64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Transcript {
+    entries: Vec<TranscriptEntry>,
+    outputs: u64,
+}
+
+impl Transcript {
+    pub fn new() -> Self {
+        Transcript::default()
+    }
+
+    pub fn entries(&self) -> &[TranscriptEntry] {
+        &self.entries
+    }
+
+    /// Outputs produced while recording
+    pub fn outputs(&self) -> u64 {
+        self.outputs
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The values in the order they were read
+    pub fn inputs(&self) -> Vec<i64> {
+        self.entries.iter().map(|entry| entry.value).collect()
+    }
+
+    /// The index of the first input that was read with a different value or after a different
+    /// number of outputs, or the length of the shorter one if one is a prefix of the other
+    pub fn first_difference(&self, other: &Transcript) -> Option<usize> {
+        if self.entries == other.entries {
+            return None;
+        }
+        let same = self
+            .entries
+            .iter()
+            .zip(other.entries.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        Some(same)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    pub(crate) fn record_output(&mut self) {
+        self.outputs += 1;
+    }
+
+    pub(crate) fn record_input(&mut self, value: i64) {
+        self.entries.push(TranscriptEntry {
+            outputs: self.outputs,
+            value,
+        });
+    }
+
+    pub(crate) fn undo(&mut self, input: bool, output: bool) {
+        if input {
+            self.entries.pop();
+        }
+        // stepping back past the point recording started has no outputs left to take off
+        if output {
+            self.outputs = self.outputs.saturating_sub(1);
+        }
+    }
+}
+
+/// What a session does with its transcript
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TranscriptMode {
+    /// Records without saving
+    Live,
+    /// Saves the transcript to the path once the machine stops
+    Record(PathBuf),
+    /// Plays back the transcript saved at the path instead of choosing inputs
+    Replay(PathBuf),
+}
+
+impl TranscriptMode {
+    /// Reads `record <path>` or `replay <path>` from the arguments after the program name.
+    /// Anything else is a live session.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Self {
+        let mut args = args.into_iter();
+        match (args.next().as_deref(), args.next()) {
+            (Some("record"), Some(path)) => TranscriptMode::Record(path.into()),
+            (Some("replay"), Some(path)) => TranscriptMode::Replay(path.into()),
+            _ => TranscriptMode::Live,
+        }
+    }
+
+    pub fn is_replay(&self) -> bool {
+        matches!(self, TranscriptMode::Replay(_))
+    }
+
+    /// Starts recording, queueing the saved inputs first when replaying. Returns the transcript
+    /// being replayed so `finish` can compare against it.
+    pub fn start(&self, state: &mut ProgramState) -> io::Result<Option<Transcript>> {
+        match self {
+            TranscriptMode::Replay(path) => {
+                let transcript = Transcript::load(path)?;
+                state.replay(&transcript);
+                Ok(Some(transcript))
+            }
+            _ => {
+                state.record_transcript();
+                Ok(None)
+            }
+        }
+    }
+
+    /// Saves what the machine recorded when recording. When replaying, returns the first input
+    /// at which the replay went differently from `replayed`.
+    pub fn finish(
+        &self,
+        state: &mut ProgramState,
+        replayed: Option<&Transcript>,
+    ) -> io::Result<Option<usize>> {
+        let transcript = state.transcript.take().unwrap_or_default();
+        match (self, replayed) {
+            (TranscriptMode::Record(path), _) => transcript.save(path).map(|_| None),
+            (TranscriptMode::Replay(_), Some(recorded)) => {
+                Ok(transcript.first_difference(recorded))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+impl fmt::Display for Transcript {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# outputs before the input, input")?;
+        for entry in &self.entries {
+            writeln!(f, "{} {}", entry.outputs, entry.value)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Transcript {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Self, ParseError> {
+        let mut transcript = Transcript::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap();
+            let mut column = 1;
+            let mut fields = Vec::new();
+            for field in line.split(' ') {
+                if !field.trim().is_empty() {
+                    fields.push((column, field.trim()));
+                }
+                column += field.len() + 1;
+            }
+            if fields.is_empty() {
+                continue;
+            }
+
+            let error = |column: usize, message: String| ParseError {
+                line: i + 1,
+                column,
+                message,
+            };
+            if fields.len() != 2 {
+                return Err(error(
+                    1,
+                    format!("expected 2 numbers, found {}", fields.len()),
+                ));
+            }
+            let (outputs, value) = (fields[0], fields[1]);
+            let outputs: u64 = outputs
+                .1
+                .parse()
+                .map_err(|_| error(outputs.0, format!("bad output count {:?}", outputs.1)))?;
+            let value = value
+                .1
+                .parse()
+                .map_err(|_| error(value.0, format!("bad number {:?}", value.1)))?;
+            if outputs < transcript.outputs {
+                let message = format!("output count went back from {}", transcript.outputs);
+                return Err(error(fields[0].0, message));
+            }
+
+            transcript.outputs = outputs;
+            transcript.record_input(value);
+        }
+        Ok(transcript)
+    }
+}
+
+impl ProgramState {
+    /// Starts recording every input the machine reads
+    pub fn record_transcript(&mut self) {
+        self.transcript = Some(Transcript::new());
+    }
+
+    /// Queues a recorded session's inputs and starts recording again, so the new transcript can
+    /// be compared with the old one once the machine has run
+    pub fn replay(&mut self, transcript: &Transcript) {
+        self.inputs.extend(transcript.inputs());
+        self.record_transcript();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run_program, run_step};
+
+    // reads a number and prints it back until it reads 0
+    fn echo() -> Vec<i64> {
+        vec![3, 11, 1006, 11, 10, 4, 11, 1105, 1, 0, 99, 0]
+    }
+
+    #[test]
+    fn test_records_inputs_and_replays_them() {
+        let mut state = ProgramState::new(&echo(), vec![]);
+        state.record_transcript();
+        let mut next = 3;
+        let mut outputs = Vec::new();
+        // the inputs are chosen as the program runs, one for each thing it says
+        state.inputs.push(next);
+        run_program(&mut state, true, |state, output| {
+            outputs.push(output);
+            next -= 1;
+            state.inputs.push(next);
+            false
+        });
+        assert_eq!(outputs, vec![3, 2, 1]);
+        let transcript = state.transcript.take().unwrap();
+        assert_eq!(transcript.inputs(), vec![3, 2, 1, 0]);
+        assert_eq!(
+            transcript.entries()[2],
+            TranscriptEntry {
+                outputs: 2,
+                value: 1
+            }
+        );
+        assert_eq!(transcript.outputs(), 3);
+
+        let text = transcript.to_string();
+        assert_eq!(
+            text,
+            "# outputs before the input, input\n0 3\n1 2\n2 1\n3 0\n"
+        );
+        let loaded: Transcript = text.parse().unwrap();
+        assert_eq!(loaded.entries(), transcript.entries());
+
+        let mut replayed = ProgramState::new(&echo(), vec![]);
+        replayed.replay(&loaded);
+        let mut again = Vec::new();
+        run_program(&mut replayed, true, |_, output| {
+            again.push(output);
+            false
+        });
+        assert_eq!(again, outputs);
+        assert_eq!(
+            replayed.transcript.unwrap().first_difference(&transcript),
+            None
+        );
+    }
+
+    #[test]
+    fn test_step_back_unrecords() {
+        let mut state = ProgramState::new(&echo(), vec![5, 0]);
+        state.enable_history(10, 10);
+        state.record_transcript();
+        for _ in 0..4 {
+            run_step(&mut state, true);
+        }
+        assert_eq!(state.transcript.as_ref().unwrap().outputs(), 1);
+        state.run_back_to_input();
+        let transcript = state.transcript.as_ref().unwrap();
+        assert_eq!((transcript.len(), transcript.outputs()), (0, 0));
+    }
+
+    #[test]
+    fn test_step_back_past_the_start_of_recording() {
+        let mut state = ProgramState::new(&echo(), vec![5, 0]);
+        state.enable_history(10, 10);
+        for _ in 0..3 {
+            run_step(&mut state, true);
+        }
+        state.record_transcript();
+        state.run_back_to_input();
+        let transcript = state.transcript.as_ref().unwrap();
+        assert_eq!((transcript.len(), transcript.outputs()), (0, 0));
+    }
+
+    #[test]
+    fn test_mode_from_args() {
+        let mode = |args: &[&str]| TranscriptMode::from_args(args.iter().map(|a| a.to_string()));
+        assert_eq!(
+            mode(&["record", "moves.txt"]),
+            TranscriptMode::Record("moves.txt".into())
+        );
+        assert_eq!(
+            mode(&["replay", "moves.txt"]),
+            TranscriptMode::Replay("moves.txt".into())
+        );
+        assert!(mode(&["replay", "moves.txt"]).is_replay());
+        assert_eq!(mode(&["replay"]), TranscriptMode::Live);
+        assert_eq!(mode(&[]), TranscriptMode::Live);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |text: &str| text.parse::<Transcript>().unwrap_err().to_string();
+        assert_eq!(error("0 1\n2 x"), "line 2, column 3: bad number \"x\"");
+        assert_eq!(
+            error("0 1 # ok\n 3"),
+            "line 2, column 1: expected 2 numbers, found 1"
+        );
+        assert_eq!(
+            error("4 1\n2 1"),
+            "line 2, column 1: output count went back from 4"
+        );
+        assert_eq!(error("-1 1"), "line 1, column 1: bad output count \"-1\"");
+
+        let mut other = "0 1\n2 1".parse::<Transcript>().unwrap();
+        let transcript = "0 1\n3 1".parse::<Transcript>().unwrap();
+        assert_eq!(transcript.first_difference(&other), Some(1));
+        other.entries.pop();
+        assert_eq!(transcript.first_difference(&other), Some(1));
+    }
+}
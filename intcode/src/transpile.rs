@@ -0,0 +1,334 @@
+//! Turns an intcode program into Rust source for a native function.
+//!
+//! Every reachable instruction becomes one arm of a `match` on the instruction pointer; control
+//! flow is not recovered beyond that, so loops and calls still go through the `match`. Programs
+//! that write into their own code can't be compiled this way, so they are refused up front when
+//! the write target is known, and stopped with `Exit::CodeWrite` when it is only known at run time.
+//! Most puzzle inputs patch operands in place and are refused; days 5 and 9 transpile.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+
+use crate::decode::{self, Instruction, Mode, Opcode, Param};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TranspileError {
+    /// Nothing could be decoded from address 0
+    NoCode,
+    /// The instruction at `ip` writes into the code region at `address`
+    CodeWrite { ip: usize, address: usize },
+}
+
+impl fmt::Display for TranspileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TranspileError::NoCode => write!(f, "no instructions could be decoded from address 0"),
+            TranspileError::CodeWrite { ip, address } => write!(
+                f,
+                "instruction at {} writes into code at {}, the program modifies itself",
+                ip, address
+            ),
+        }
+    }
+}
+
+/// Why a transpiled function returned
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Exit {
+    Halted,
+    /// The device had no input. `ip` still points at the input instruction, so calling the
+    /// function again once input is available resumes the program.
+    NeedsInput,
+    /// Jumped to an address that was not decoded as an instruction
+    BadJump(usize),
+    /// A relative or computed write landed in the code region
+    CodeWrite {
+        ip: usize,
+        address: usize,
+    },
+    /// The instruction at `ip` tried to write to a negative address
+    NegativeAddress {
+        ip: usize,
+        address: i64,
+    },
+}
+
+/// Registers and memory of a transpiled program
+#[derive(Clone, Debug)]
+pub struct NativeState {
+    pub memory: Vec<i64>,
+    pub ip: usize,
+    pub relative_base: i64,
+}
+
+impl NativeState {
+    pub fn new(program: &[i64]) -> Self {
+        NativeState {
+            memory: program.to_vec(),
+            ip: 0,
+            relative_base: 0,
+        }
+    }
+
+    pub fn read(&self, address: i64) -> i64 {
+        if address < 0 {
+            return 0;
+        }
+        *self.memory.get(address as usize).unwrap_or(&0)
+    }
+
+    pub fn write(&mut self, address: usize, value: i64) {
+        if address >= self.memory.len() {
+            self.memory.resize(address + 1, 0);
+        }
+        self.memory[address] = value;
+    }
+
+    /// Writes to an address only known at run time, unless it is negative or falls in one of the
+    /// half open `code` ranges
+    pub fn write_outside(
+        &mut self,
+        address: i64,
+        value: i64,
+        code: &[(usize, usize)],
+    ) -> Result<(), Exit> {
+        if address < 0 {
+            return Err(Exit::NegativeAddress {
+                ip: self.ip,
+                address,
+            });
+        }
+        let target = address as usize;
+        if in_code(code, target) {
+            return Err(Exit::CodeWrite {
+                ip: self.ip,
+                address: target,
+            });
+        }
+        self.write(target, value);
+        Ok(())
+    }
+}
+
+/// Generates a Rust function named `name` equivalent to `program`
+pub fn transpile(program: &[i64], name: &str) -> Result<String, TranspileError> {
+    let instructions = decode::reachable(program);
+    if instructions.is_empty() {
+        return Err(TranspileError::NoCode);
+    }
+
+    let code = code_ranges(&instructions);
+    for instruction in instructions.values() {
+        if let Some(target) = instruction.static_write_target() {
+            if in_code(&code, target) {
+                return Err(TranspileError::CodeWrite {
+                    ip: instruction.address,
+                    address: target,
+                });
+            }
+        }
+    }
+
+    let mut source = String::new();
+    writeln!(
+        source,
+        "// Generated by intcode::transpile from a {} word program, do not edit.",
+        program.len()
+    )
+    .unwrap();
+    writeln!(source, "#[allow(clippy::all)]").unwrap();
+    writeln!(
+        source,
+        "pub fn {}<D: intcode::Device>(state: &mut intcode::transpile::NativeState, io: &mut D) -> intcode::transpile::Exit {{",
+        name
+    )
+    .unwrap();
+    writeln!(source, "    use intcode::transpile::Exit;").unwrap();
+    if instructions.values().any(|instruction| {
+        instruction.opcode.write_param().is_some() && instruction.static_write_target().is_none()
+    }) {
+        writeln!(source, "    const CODE: &[(usize, usize)] = &{:?};", code).unwrap();
+    }
+    writeln!(source, "    loop {{").unwrap();
+    writeln!(source, "        match state.ip {{").unwrap();
+    for instruction in instructions.values() {
+        writeln!(source, "            // {}", instruction).unwrap();
+        writeln!(source, "            {} => {{", instruction.address).unwrap();
+        for line in arm_body(instruction) {
+            writeln!(source, "                {}", line).unwrap();
+        }
+        writeln!(source, "            }}").unwrap();
+    }
+    writeln!(source, "            ip => return Exit::BadJump(ip),").unwrap();
+    writeln!(source, "        }}").unwrap();
+    writeln!(source, "    }}").unwrap();
+    writeln!(source, "}}").unwrap();
+
+    Ok(source)
+}
+
+fn code_ranges(instructions: &BTreeMap<usize, Instruction>) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for instruction in instructions.values() {
+        match ranges.last_mut() {
+            Some(last) if last.1 == instruction.address => last.1 = instruction.next(),
+            _ => ranges.push((instruction.address, instruction.next())),
+        }
+    }
+
+    ranges
+}
+
+fn in_code(code: &[(usize, usize)], address: usize) -> bool {
+    code.iter()
+        .any(|(start, end)| *start <= address && address < *end)
+}
+
+fn relative(offset: i64) -> String {
+    format!("state.relative_base.wrapping_add({})", offset)
+}
+
+fn read(param: &Param) -> String {
+    match param.mode {
+        Mode::Position => format!("state.read({})", param.value),
+        Mode::Immediate => format!("{}i64", param.value),
+        Mode::Relative => format!("state.read({})", relative(param.value)),
+    }
+}
+
+fn store(instruction: &Instruction, value: &str) -> String {
+    // static targets were checked against the code ranges before generating anything
+    if let Some(target) = instruction.static_write_target() {
+        return format!("state.write({}, {});", target, value);
+    }
+    let param = instruction.params[instruction.opcode.write_param().unwrap()];
+    format!(
+        "if let Err(exit) = state.write_outside({}, {}, CODE) {{ return exit; }}",
+        relative(param.value),
+        value
+    )
+}
+
+fn jump_target(param: &Param) -> String {
+    match param.mode {
+        Mode::Immediate => format!("{}", param.value),
+        _ => format!("{} as usize", read(param)),
+    }
+}
+
+fn arm_body(instruction: &Instruction) -> Vec<String> {
+    let params = &instruction.params;
+    let next = format!("state.ip = {};", instruction.next());
+    match instruction.opcode {
+        Opcode::Add | Opcode::Multiply => {
+            let operation = if instruction.opcode == Opcode::Add {
+                "wrapping_add"
+            } else {
+                "wrapping_mul"
+            };
+            vec![
+                format!(
+                    "let value = {}.{}({});",
+                    read(&params[0]),
+                    operation,
+                    read(&params[1])
+                ),
+                store(instruction, "value"),
+                next,
+            ]
+        }
+        Opcode::LessThan | Opcode::Equals => {
+            let comparison = if instruction.opcode == Opcode::LessThan {
+                "<"
+            } else {
+                "=="
+            };
+            vec![
+                format!(
+                    "let value = ({} {} {}) as i64;",
+                    read(&params[0]),
+                    comparison,
+                    read(&params[1])
+                ),
+                store(instruction, "value"),
+                next,
+            ]
+        }
+        Opcode::Input => vec![
+            "let value = match io.input() { Some(value) => value, None => return Exit::NeedsInput };"
+                .to_string(),
+            store(instruction, "value"),
+            next,
+        ],
+        Opcode::Output => vec![format!("io.output({});", read(&params[0])), next],
+        Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+            let jump_when = instruction.opcode == Opcode::JumpIfTrue;
+            if params[0].mode == Mode::Immediate {
+                if (params[0].value != 0) == jump_when {
+                    return vec![format!("state.ip = {};", jump_target(&params[1]))];
+                }
+                return vec![next];
+            }
+
+            let comparison = if jump_when { "!=" } else { "==" };
+            vec![
+                format!("if {} {} 0 {{", read(&params[0]), comparison),
+                format!("    state.ip = {};", jump_target(&params[1])),
+                "} else {".to_string(),
+                format!("    {}", next),
+                "}".to_string(),
+            ]
+        }
+        Opcode::AdjustBase => vec![
+            format!(
+                "state.relative_base = state.relative_base.wrapping_add({});",
+                read(&params[0])
+            ),
+            next,
+        ],
+        Opcode::Halt => vec!["return Exit::Halted;".to_string()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refuses_self_modifying_programs() {
+        // the first instruction rewrites the operand of the output instruction at 4
+        let program = vec![1101, 5, 0, 5, 104, 0, 99];
+        assert_eq!(
+            transpile(&program, "run"),
+            Err(TranspileError::CodeWrite { ip: 0, address: 5 })
+        );
+    }
+
+    #[test]
+    fn test_computed_writes_stop_in_code_or_below_zero() {
+        let code = [(0, 4)];
+        let mut state = NativeState::new(&[109, 1, 99, 0]);
+        state.ip = 2;
+        assert_eq!(
+            state.write_outside(1, 7, &code),
+            Err(Exit::CodeWrite { ip: 2, address: 1 })
+        );
+        assert_eq!(
+            state.write_outside(-1, 7, &code),
+            Err(Exit::NegativeAddress { ip: 2, address: -1 })
+        );
+        assert_eq!(state.write_outside(6, 7, &code), Ok(()));
+        assert_eq!(state.memory, vec![109, 1, 99, 0, 0, 0, 7]);
+    }
+
+    #[test]
+    fn test_generates_an_arm_per_instruction() {
+        let program = vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+        let source = transpile(&program, "equal_to_eight").unwrap();
+        assert!(source.contains("pub fn equal_to_eight<D: intcode::Device>"));
+        for address in &[0, 2, 6, 8] {
+            assert!(source.contains(&format!("            {} => {{", address)));
+        }
+        assert!(source.contains("let value = (state.read(9) == state.read(10)) as i64;"));
+    }
+}
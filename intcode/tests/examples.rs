@@ -0,0 +1,12 @@
+//! The example programs from the puzzle statements
+
+extern crate intcode;
+
+use intcode::testing::puzzle_examples;
+
+#[test]
+fn test_puzzle_examples() {
+    for (_, example) in puzzle_examples() {
+        example.check();
+    }
+}
@@ -0,0 +1,50 @@
+//! What the ASCII programs print, checked against the text in `golden/`. Run with
+//! `UPDATE_GOLDEN=1` to write the files again after a deliberate change.
+
+extern crate intcode;
+
+use intcode::testing::{ascii_output, assert_golden};
+
+fn golden(name: &str) -> String {
+    format!("{}/tests/golden/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+fn program(text: &str) -> Vec<i64> {
+    intcode::parse_program(text).unwrap()
+}
+
+#[test]
+fn test_scaffold_camera() {
+    let camera = program(include_str!("../../17/input.txt"));
+    assert_golden(golden("scaffold_camera.txt"), &ascii_output(&camera, &[]));
+}
+
+#[test]
+fn test_springdroid_walk() {
+    let springdroid = program(include_str!("../../21/input.txt"));
+    let script = [
+        "NOT B J", "NOT C T", "OR T J", "AND D J", "NOT A T", "OR T J", "WALK",
+    ];
+    assert_golden(
+        golden("springdroid_walk.txt"),
+        &ascii_output(&springdroid, &script),
+    );
+}
+
+#[test]
+fn test_springdroid_falls_in_a_hole() {
+    let springdroid = program(include_str!("../../21/input.txt"));
+    assert_golden(
+        golden("springdroid_no_jumps.txt"),
+        &ascii_output(&springdroid, &["WALK"]),
+    );
+}
+
+#[test]
+fn test_cryostasis_first_room() {
+    let adventure = program(include_str!("../../25/input.txt"));
+    assert_golden(
+        golden("cryostasis_first_room.txt"),
+        &ascii_output(&adventure, &[]),
+    );
+}
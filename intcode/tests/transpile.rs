@@ -0,0 +1,45 @@
+extern crate intcode;
+
+use intcode::transpile::{transpile, Exit, NativeState};
+use intcode::BufferDevice;
+
+include!("transpiled/compare_to_eight.rs");
+
+// day 5 example: outputs 999 below eight, 1000 for eight and 1001 above
+const PROGRAM: &str = "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99";
+
+#[test]
+fn test_generated_source_is_up_to_date() {
+    let program = intcode::get_base_program(PROGRAM);
+    let source = transpile(&program, "compare_to_eight").unwrap();
+    assert_eq!(source, include_str!("transpiled/compare_to_eight.rs"));
+}
+
+#[test]
+fn test_native_function_matches_interpreter() {
+    let program = intcode::get_base_program(PROGRAM);
+    for input in 5..12 {
+        let mut state = NativeState::new(&program);
+        let mut io = BufferDevice::new(vec![input]);
+        assert_eq!(compare_to_eight(&mut state, &mut io), Exit::Halted);
+
+        let mut interpreted = Vec::new();
+        let mut vm = intcode::ProgramState::new(&program, vec![input]);
+        intcode::run_program(&mut vm, false, |_, output| {
+            interpreted.push(output);
+            false
+        });
+        assert_eq!(io.outputs, interpreted);
+    }
+}
+
+#[test]
+fn test_native_function_resumes_after_input() {
+    let program = intcode::get_base_program(PROGRAM);
+    let mut state = NativeState::new(&program);
+    let mut io = BufferDevice::new(vec![]);
+    assert_eq!(compare_to_eight(&mut state, &mut io), Exit::NeedsInput);
+    io.inputs.push_back(8);
+    assert_eq!(compare_to_eight(&mut state, &mut io), Exit::Halted);
+    assert_eq!(io.outputs, vec![1000]);
+}
@@ -0,0 +1,91 @@
+// Generated by intcode::transpile from a 47 word program, do not edit.
+#[allow(clippy::all)]
+pub fn compare_to_eight<D: intcode::Device>(state: &mut intcode::transpile::NativeState, io: &mut D) -> intcode::transpile::Exit {
+    use intcode::transpile::Exit;
+    loop {
+        match state.ip {
+            // in [21]
+            0 => {
+                let value = match io.input() { Some(value) => value, None => return Exit::NeedsInput };
+                state.write(21, value);
+                state.ip = 2;
+            }
+            // eq [21], #8, [20]
+            2 => {
+                let value = (state.read(21) == 8i64) as i64;
+                state.write(20, value);
+                state.ip = 6;
+            }
+            // jnz [20], #22
+            6 => {
+                if state.read(20) != 0 {
+                    state.ip = 22;
+                } else {
+                    state.ip = 9;
+                }
+            }
+            // lt #8, [21], [20]
+            9 => {
+                let value = (8i64 < state.read(21)) as i64;
+                state.write(20, value);
+                state.ip = 13;
+            }
+            // jz [20], #31
+            13 => {
+                if state.read(20) == 0 {
+                    state.ip = 31;
+                } else {
+                    state.ip = 16;
+                }
+            }
+            // jz #0, #36
+            16 => {
+                state.ip = 36;
+            }
+            // mul [21], #125, [20]
+            22 => {
+                let value = state.read(21).wrapping_mul(125i64);
+                state.write(20, value);
+                state.ip = 26;
+            }
+            // out [20]
+            26 => {
+                io.output(state.read(20));
+                state.ip = 28;
+            }
+            // jnz #1, #46
+            28 => {
+                state.ip = 46;
+            }
+            // out #999
+            31 => {
+                io.output(999i64);
+                state.ip = 33;
+            }
+            // jnz #1, #46
+            33 => {
+                state.ip = 46;
+            }
+            // add #1000, #1, [20]
+            36 => {
+                let value = 1000i64.wrapping_add(1i64);
+                state.write(20, value);
+                state.ip = 40;
+            }
+            // out [20]
+            40 => {
+                io.output(state.read(20));
+                state.ip = 42;
+            }
+            // jnz #1, #46
+            42 => {
+                state.ip = 46;
+            }
+            // hlt
+            46 => {
+                return Exit::Halted;
+            }
+            ip => return Exit::BadJump(ip),
+        }
+    }
+}